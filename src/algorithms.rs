@@ -1,7 +1,9 @@
 //! Core MUMmer algorithms: MUM, MAM, MEM
 //! Maximal Unique Match (MUM), Maximal Almost-Unique Match (MAM), Maximal Exact Match (MEM)
 
-use crate::suffix_array::{SparseSuffixArray, Match};
+use std::collections::HashSet;
+
+use crate::suffix_array::{Index, SparseSuffixArray, Match};
 
 /// Match types for different MUMmer algorithms
 #[derive(Debug, Clone, PartialEq)]
@@ -9,11 +11,16 @@ pub enum MatchType {
     MUM,  // Maximal Unique Match
     MAM,  // Maximal Almost-Unique Match  
     MEM,  // Maximal Exact Match
+    MUMi, // Reference-unique matches found from suffix-array intervals
 }
 
 /// Find Maximal Unique Matches (MUMs)
 /// These are matches that are unique in both sequences
-pub fn find_mums(reference: &SparseSuffixArray, query: &[u8], min_len: usize) -> Vec<Match> {
+pub fn find_mums<I: Index + ?Sized>(reference: &I, query: &[u8], min_len: usize) -> Vec<Match> {
+    remove_redundant_matches(mum_candidates(reference, query, min_len))
+}
+
+fn mum_candidates<I: Index + ?Sized>(reference: &I, query: &[u8], min_len: usize) -> Vec<Match> {
     let mut matches = Vec::new();
     
     // For each possible starting position in the query
@@ -22,27 +29,28 @@ pub fn find_mums(reference: &SparseSuffixArray, query: &[u8], min_len: usize) ->
         for len in min_len..=(query.len() - i) {
             let pattern = &query[i..i + len];
             
-            // Search for this pattern in the reference
-            let pattern_matches = reference.find_matches(pattern);
+            // Search for this pattern in the reference, stopping after a second hit
+            let mut hits = reference.occurrences(pattern);
             
             // For MUM, we only want matches that are unique in both sequences
-            if pattern_matches.len() == 1 {
-                matches.push(Match::new(
-                    pattern_matches[0].ref_pos,
-                    i,
-                    pattern.len(),
-                ));
+            if let (Some(ref_pos), None) = (hits.next(), hits.next())
+                && is_maximal(reference.sequence(), query, ref_pos, i, len)
+            {
+                matches.push(Match::new(ref_pos, i, len));
             }
         }
     }
     
-    // Remove redundant matches (if one match is contained in another)
-    remove_redundant_matches(matches)
+    matches
 }
 
 /// Find Maximal Almost-Unique Matches (MAMs)
 /// These are matches that are unique in the reference but may repeat in the query
-pub fn find_mams(reference: &SparseSuffixArray, query: &[u8], min_len: usize) -> Vec<Match> {
+pub fn find_mams<I: Index + ?Sized>(reference: &I, query: &[u8], min_len: usize) -> Vec<Match> {
+    remove_redundant_matches(mam_candidates(reference, query, min_len))
+}
+
+fn mam_candidates<I: Index + ?Sized>(reference: &I, query: &[u8], min_len: usize) -> Vec<Match> {
     let mut matches = Vec::new();
     
     // For each possible starting position in the query
@@ -51,56 +59,168 @@ pub fn find_mams(reference: &SparseSuffixArray, query: &[u8], min_len: usize) ->
         for len in min_len..=(query.len() - i) {
             let pattern = &query[i..i + len];
             
-            // Search for this pattern in the reference
-            let pattern_matches = reference.find_matches(pattern);
+            // Search for this pattern in the reference, stopping after a second hit
+            let mut hits = reference.occurrences(pattern);
             
             // For MAM, we want matches that are unique in the reference
-            if pattern_matches.len() == 1 {
-                matches.push(Match::new(
-                    pattern_matches[0].ref_pos,
-                    i,
-                    pattern.len(),
-                ));
+            if let (Some(ref_pos), None) = (hits.next(), hits.next())
+                && is_maximal(reference.sequence(), query, ref_pos, i, len)
+            {
+                matches.push(Match::new(ref_pos, i, len));
             }
         }
     }
     
-    remove_redundant_matches(matches)
+    matches
 }
 
 /// Find Maximal Exact Matches (MEMs)
 /// These are all maximal matches regardless of uniqueness
-pub fn find_mems(reference: &SparseSuffixArray, query: &[u8], min_len: usize) -> Vec<Match> {
+pub fn find_mems<I: Index + ?Sized>(reference: &I, query: &[u8], min_len: usize) -> Vec<Match> {
+    find_all_mems(reference, query, min_len)
+}
+
+/// Find every maximal exact match of length >= `min_len` between the reference and the query
+///
+/// Each query position is seeded with its `min_len` prefix; every reference occurrence of the
+/// seed that cannot be extended to the left is extended to the right as far as the sequences
+/// agree. The result is sorted by reference position, then query position.
+pub fn find_all_mems<I: Index + ?Sized>(reference: &I, query: &[u8], min_len: usize) -> Vec<Match> {
+    find_all_mems_with_step(reference, query, min_len, 1)
+}
+
+/// Find maximal exact matches seeding only every `seed_step`-th query position
+///
+/// Seeds are extended both ways, so every match found is still maximal, but a MEM is only
+/// guaranteed to be found when it is at least `min_len + seed_step - 1` bases long. A step of 1
+/// gives the same result as `find_all_mems`.
+pub fn find_all_mems_with_step<I: Index + ?Sized>(reference: &I, query: &[u8], min_len: usize, seed_step: usize) -> Vec<Match> {
+    remove_redundant_matches(mem_candidates(reference, query, min_len, seed_step))
+}
+
+fn mem_candidates<I: Index + ?Sized>(reference: &I, query: &[u8], min_len: usize, seed_step: usize) -> Vec<Match> {
+    let ref_seq = reference.sequence();
+    let seed_len = min_len.max(1);
+    let seed_step = seed_step.max(1);
     let mut matches = Vec::new();
-    
-    // For each possible starting position in the query
+
+    for i in (0..query.len().saturating_sub(seed_len - 1)).step_by(seed_step) {
+        for ref_pos in reference.occurrences(&query[i..i + seed_len]) {
+            let left_extension = ref_seq[..ref_pos]
+                .iter()
+                .rev()
+                .zip(query[..i].iter().rev())
+                .take_while(|(r, q)| r == q)
+                .take(seed_step)
+                .count();
+            // The same match is reported from the previous seeded query position
+            if left_extension == seed_step {
+                continue;
+            }
+
+            let extension = ref_seq[ref_pos + seed_len..]
+                .iter()
+                .zip(&query[i + seed_len..])
+                .take_while(|(r, q)| r == q)
+                .count();
+            let (ref_pos, query_pos) = (ref_pos - left_extension, i - left_extension);
+            let len = left_extension + seed_len + extension;
+            debug_assert!(is_maximal(ref_seq, query, ref_pos, query_pos, len));
+            matches.push(Match::new(ref_pos, query_pos, len));
+        }
+    }
+
+    matches
+}
+
+/// Whether an exact match can be extended by neither the preceding nor the following base
+///
+/// A match touching the start or end of either sequence counts as maximal on that side.
+fn is_maximal(ref_seq: &[u8], query: &[u8], ref_pos: usize, query_pos: usize, len: usize) -> bool {
+    let left_maximal = ref_pos == 0 || query_pos == 0 || ref_seq[ref_pos - 1] != query[query_pos - 1];
+    let right_maximal = match (ref_seq.get(ref_pos + len), query.get(query_pos + len)) {
+        (Some(r), Some(q)) => r != q,
+        _ => true,
+    };
+    left_maximal && right_maximal
+}
+
+/// Find reference-unique maximal matches directly from the suffix and LCP arrays
+///
+/// For each query position the suffix-array interval is narrowed one character at a time until a
+/// single suffix remains; that match is then extended to the right. A match of length `len` at
+/// suffix-array rank `r` is unique when its LCP with both neighbours (`lcp[r]` and `lcp[r + 1]`)
+/// is below `len`. This avoids the repeated searches of the brute-force MUM scan.
+///
+/// A sparse index (sampling rate above 1) falls back to the equivalent MUM search.
+pub fn find_mumi(reference: &SparseSuffixArray, query: &[u8], min_len: usize) -> Vec<Match> {
+    match reference.as_suffix_array() {
+        Some(suffix_array) => remove_redundant_matches(mumi_candidates(suffix_array, query, min_len)),
+        None => remove_redundant_matches(mum_candidates(reference, query, min_len)),
+    }
+}
+
+fn mumi_candidates(reference: &SparseSuffixArray, query: &[u8], min_len: usize) -> Vec<Match> {
+    let ref_seq = reference.sequence();
+    let suffix_array = reference.suffix_array();
+    let lcp_array = reference.lcp_array();
+    let mut matches = Vec::new();
+
     for i in 0..query.len() {
-        // Try different lengths starting from min_len
-        for len in min_len..=(query.len() - i) {
-            let pattern = &query[i..i + len];
-            
-            // Search for this pattern in the reference
-            let pattern_matches = reference.find_matches(pattern);
-            
-            // For MEM, we include all matches regardless of uniqueness
-            for pattern_match in pattern_matches {
-                matches.push(Match::new(
-                    pattern_match.ref_pos,
-                    i,
-                    pattern.len(),
-                ));
+        let mut interval = (0, suffix_array.len());
+        let mut depth = 0;
+        while (depth == 0 || interval.1 - interval.0 > 1) && i + depth < query.len() {
+            match reference.refine_interval(interval, depth, query[i + depth]) {
+                Some(next) => interval = next,
+                None => break,
             }
+            depth += 1;
+        }
+        if depth == 0 || interval.1 - interval.0 != 1 {
+            continue;
+        }
+
+        let rank = interval.0;
+        let ref_pos = suffix_array[rank];
+        // Left-extendable matches are reported from an earlier query position
+        if i > 0 && ref_pos > 0 && query[i - 1] == ref_seq[ref_pos - 1] {
+            continue;
+        }
+
+        let extension = ref_seq[ref_pos + depth..]
+            .iter()
+            .zip(&query[i + depth..])
+            .take_while(|(r, q)| r == q)
+            .count();
+        let len = depth + extension;
+
+        let neighbour_lcp = lcp_array[rank].max(lcp_array.get(rank + 1).copied().unwrap_or(0));
+        if len >= min_len && neighbour_lcp < len {
+            matches.push(Match::new(ref_pos, i, len));
         }
     }
-    
-    remove_redundant_matches(matches)
+
+    matches
+}
+
+/// Collapse matches with the same position, length and strand, keeping the first of each
+pub fn remove_duplicate_matches(matches: Vec<Match>) -> Vec<Match> {
+    let mut seen = HashSet::new();
+    matches
+        .into_iter()
+        .filter(|m| seen.insert((m.ref_pos, m.query_pos, m.len, m.strand)))
+        .collect()
 }
 
 /// Remove redundant matches (matches that are contained within other matches)
-fn remove_redundant_matches(mut matches: Vec<Match>) -> Vec<Match> {
-    // Sort matches by reference position, then by query position
+fn remove_redundant_matches(matches: Vec<Match>) -> Vec<Match> {
+    // Exact duplicates would otherwise each be compared against every kept match
+    let mut matches = remove_duplicate_matches(matches);
+    // Sort matches by reference position, longest first, then by query position,
+    // so that a containing match is always seen before the matches it contains
     matches.sort_by(|a, b| {
         a.ref_pos.cmp(&b.ref_pos)
+            .then_with(|| b.len.cmp(&a.len))
             .then_with(|| a.query_pos.cmp(&b.query_pos))
     });
     
@@ -122,6 +242,38 @@ fn remove_redundant_matches(mut matches: Vec<Match>) -> Vec<Match> {
         }
     }
     
+    result.sort_by(|a, b| {
+        a.ref_pos.cmp(&b.ref_pos)
+            .then_with(|| a.query_pos.cmp(&b.query_pos))
+    });
+    result
+}
+
+/// Remove only matches that are part of a longer match on the same diagonal, keeping
+/// maximal matches nested inside other matches
+fn remove_non_maximal_matches(matches: Vec<Match>) -> Vec<Match> {
+    let mut matches = remove_duplicate_matches(matches);
+    let diagonal = |m: &Match| m.ref_pos as i64 - m.query_pos as i64;
+    matches.sort_by(|a, b| {
+        diagonal(a).cmp(&diagonal(b))
+            .then_with(|| a.ref_pos.cmp(&b.ref_pos))
+            .then_with(|| b.len.cmp(&a.len))
+    });
+    
+    let mut result: Vec<Match> = Vec::new();
+    for current in matches {
+        let extends_previous = result.last().is_some_and(|last| {
+            diagonal(last) == diagonal(&current) && last.ref_pos + last.len >= current.ref_pos + current.len
+        });
+        if !extends_previous {
+            result.push(current);
+        }
+    }
+    
+    result.sort_by(|a, b| {
+        a.ref_pos.cmp(&b.ref_pos)
+            .then_with(|| a.query_pos.cmp(&b.query_pos))
+    });
     result
 }
 
@@ -134,23 +286,121 @@ fn is_match_contained(a: &Match, b: &Match) -> bool {
         && a.query_pos + a.len >= b.query_pos + b.len
 }
 
+/// Flag matches whose reference and query spans agree only when case is ignored
+pub fn flag_case_differences(matches: &mut [Match], reference: &[u8], query: &[u8]) {
+    for m in matches {
+        let ref_span = &reference[m.ref_pos..m.ref_pos + m.len];
+        let query_span = &query[m.query_pos..m.query_pos + m.len];
+        m.case_difference = ref_span != query_span && ref_span.eq_ignore_ascii_case(query_span);
+    }
+}
+
+/// Matches whose reference span overlaps the half-open interval `[start, end)`
+pub fn filter_matches_by_ref_range(matches: &[Match], start: usize, end: usize) -> Vec<Match> {
+    matches
+        .iter()
+        .filter(|m| m.ref_pos < end && m.ref_pos + m.len > start)
+        .cloned()
+        .collect()
+}
+
+/// Matches whose query span overlaps the half-open interval `[start, end)`
+pub fn filter_matches_by_query_range(matches: &[Match], start: usize, end: usize) -> Vec<Match> {
+    matches
+        .iter()
+        .filter(|m| m.query_pos < end && m.query_pos + m.len > start)
+        .cloned()
+        .collect()
+}
+
+/// Shortest probe length used by `estimate_min_len` for its first pass
+pub const AUTO_MIN_LEN_PROBE: usize = 50;
+
+/// Pick a minimum match length for an unfamiliar reference/query pair from a quick first pass
+///
+/// The floor is the length above which a random match between sequences of these sizes is
+/// unlikely (log4 of the search space). A probe pass at a higher threshold then measures how much
+/// of the query is covered by reference-unique matches and what fraction of the long matches are
+/// repeats; low coverage (divergent pair) and high repeat content both raise the floor by up to half
+pub fn estimate_min_len(reference: &SparseSuffixArray, query: &[u8]) -> usize {
+    let search_space = (reference.sequence().len().max(1) as f64) * (query.len().max(1) as f64);
+    let floor = (search_space.log(4.0).ceil() as usize).max(1);
+    let probe_len = AUTO_MIN_LEN_PROBE.max(2 * floor);
+    if query.len() < probe_len {
+        return floor;
+    }
+
+    let unique = find_mumi(reference, query, probe_len);
+    let all = find_all_mems(reference, query, probe_len);
+    let covered: usize = unique.iter().map(|m| m.len).sum();
+    let coverage = (covered as f64 / query.len() as f64).min(1.0);
+    let repeat_fraction = if all.is_empty() {
+        0.0
+    } else {
+        1.0 - (unique.len() as f64 / all.len() as f64).min(1.0)
+    };
+
+    let penalty = (1.0 - coverage + repeat_fraction) / 2.0;
+    (floor + (floor as f64 * penalty).round() as usize).min(probe_len)
+}
+
 /// Main function to run MUMmer algorithms
-pub fn run_mummer_algorithm(
-    reference: &SparseSuffixArray,
+pub fn run_mummer_algorithm<I: Index + ?Sized>(
+    reference: &I,
+    query: &[u8],
+    algorithm: MatchType,
+    min_len: usize,
+) -> Vec<Match> {
+    run_mummer_algorithm_with(reference, query, algorithm, min_len, false)
+}
+
+/// Run a MUMmer algorithm, optionally keeping maximal matches nested inside other matches
+/// instead of removing every match contained in another
+///
+/// MUMi walks suffix-array intervals, so on an index that is not a dense `SparseSuffixArray` it
+/// falls back to the equivalent (slower) MUM search
+pub fn run_mummer_algorithm_with<I: Index + ?Sized>(
+    reference: &I,
+    query: &[u8],
+    algorithm: MatchType,
+    min_len: usize,
+    keep_redundant: bool,
+) -> Vec<Match> {
+    run_mummer_algorithm_sampled(reference, query, algorithm, min_len, keep_redundant, 1)
+}
+
+/// Run a MUMmer algorithm, seeding the MEM search only at every `seed_step`-th query position
+///
+/// See `find_all_mems_with_step`; the other algorithms always examine every query position.
+pub fn run_mummer_algorithm_sampled<I: Index + ?Sized>(
+    reference: &I,
     query: &[u8],
     algorithm: MatchType,
     min_len: usize,
+    keep_redundant: bool,
+    seed_step: usize,
 ) -> Vec<Match> {
-    match algorithm {
-        MatchType::MUM => find_mums(reference, query, min_len),
-        MatchType::MAM => find_mams(reference, query, min_len),
-        MatchType::MEM => find_mems(reference, query, min_len),
+    let candidates = match algorithm {
+        MatchType::MUM => mum_candidates(reference, query, min_len),
+        MatchType::MAM => mam_candidates(reference, query, min_len),
+        MatchType::MEM => mem_candidates(reference, query, min_len, seed_step),
+        MatchType::MUMi => match reference.as_suffix_array() {
+            Some(suffix_array) => mumi_candidates(suffix_array, query, min_len),
+            None => mum_candidates(reference, query, min_len),
+        },
+    };
+    
+    if keep_redundant {
+        remove_non_maximal_matches(candidates)
+    } else {
+        remove_redundant_matches(candidates)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bench::synthetic_sequence;
     use crate::suffix_array::SparseSuffixArray;
 
     #[test]
@@ -169,6 +419,185 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_find_all_mems_threshold() {
+        // Two 7bp MEMs and one 3bp MEM ("TGA" at the end)
+        let reference_seq = b"TTTTTACGTACGCCCCCGATCGATAAAAATGA";
+        let query = b"GGGGGACGTACGGGGGGGATCGATGGGGGTGA";
+        let reference = SparseSuffixArray::new(reference_seq, 1).unwrap();
+
+        let matches = find_all_mems(&reference, query, 5);
+        assert_eq!(matches, vec![Match::new(5, 5, 7), Match::new(17, 17, 7)]);
+
+        let matches = find_all_mems(&reference, query, 3);
+        assert!(matches.contains(&Match::new(29, 29, 3)));
+        assert!(matches.contains(&Match::new(5, 5, 7)));
+        assert!(matches.contains(&Match::new(17, 17, 7)));
+    }
+
+    #[test]
+    fn test_candidates_are_left_and_right_maximal() {
+        // "ACGTACG" is shared; its 6bp prefix and suffix are unique too but extendable
+        let reference_seq = b"TTTTTACGTACGCCCCC";
+        let query = b"GGGGGACGTACGGGGGG";
+        let reference = SparseSuffixArray::new(reference_seq, 1).unwrap();
+
+        for candidates in [
+            mum_candidates(&reference, query, 6),
+            mam_candidates(&reference, query, 6),
+            mem_candidates(&reference, query, 6, 1),
+        ] {
+            assert_eq!(candidates, vec![Match::new(5, 5, 7)]);
+        }
+    }
+
+    #[test]
+    fn test_find_all_mems_with_step() {
+        let reference_seq = b"ACGTTGCAACGTAGGCTTACGATCCGTAGCATGGACTTTAGCCATG";
+        let query = b"TTGCAACGTAGGCAAAGATCCGTAGCTTTTGGACTTTAGCCAGTACGATCC";
+        let reference = SparseSuffixArray::new(reference_seq, 1).unwrap();
+        let full = find_all_mems(&reference, query, 5);
+
+        assert_eq!(find_all_mems_with_step(&reference, query, 5, 1), full);
+        let sampled = find_all_mems_with_step(&reference, query, 5, 4);
+        assert!(!sampled.is_empty() && sampled.len() < full.len());
+        // Sampled seeds still extend to whole MEMs of the full search
+        assert!(sampled.iter().all(|m| full.contains(m)));
+
+        // A step longer than the short matches still catches the longest one in full
+        let longest = full.iter().max_by_key(|m| m.len).unwrap();
+        let coarse = find_all_mems_with_step(&reference, query, 5, 8);
+        assert!(coarse.contains(longest));
+        assert!(full.iter().any(|m| m.len < 8 && !coarse.contains(m)));
+    }
+
+    #[test]
+    fn test_find_mumi_matches_brute_force_mum() {
+        let reference_seq = b"ACGTTGCAACGTAGGCTTACGATCCGTAGCATGGACT";
+        let query = b"TTGCAACGTAGGCAAAGATCCGTAGCTTTTGGACTACGT";
+        let reference = SparseSuffixArray::new(reference_seq, 1).unwrap();
+
+        let brute_force = find_mums(&reference, query, 4);
+        let mumi = find_mumi(&reference, query, 4);
+        assert!(mumi.len() >= 3);
+        assert_eq!(mumi, brute_force);
+    }
+
+    #[test]
+    fn test_keep_redundant_retains_nested_match() {
+        let reference = SparseSuffixArray::new(b"AAAACGTACGTCCCC", 1).unwrap();
+        let query = b"TTACGTACGTGG";
+        // The query's second ACGT against the reference's first one lies inside ACGTACGT
+        let nested = Match::new(3, 6, 4);
+
+        let matches = run_mummer_algorithm_with(&reference, query, MatchType::MEM, 4, false);
+        assert!(matches.contains(&Match::new(3, 2, 8)));
+        assert!(!matches.contains(&nested));
+
+        let matches = run_mummer_algorithm_with(&reference, query, MatchType::MEM, 4, true);
+        assert!(matches.contains(&Match::new(3, 2, 8)));
+        assert!(matches.contains(&nested));
+
+        // Sub-matches of a longer match on the same diagonal are still dropped
+        let matches = run_mummer_algorithm_with(&reference, query, MatchType::MAM, 4, true);
+        assert!(matches.contains(&Match::new(3, 2, 8)));
+        assert!(!matches.contains(&Match::new(3, 2, 7)));
+        assert!(!matches.contains(&Match::new(4, 3, 7)));
+    }
+
+    #[test]
+    fn test_estimate_min_len_tracks_divergence() {
+        let reference_seq = synthetic_sequence(4000, 12345);
+        let mutate_every = |step: usize| -> Vec<u8> {
+            reference_seq
+                .iter()
+                .enumerate()
+                .map(|(i, &base)| if i % step == step / 2 { if base == b'A' { b'C' } else { b'A' } } else { base })
+                .collect()
+        };
+        let reference = SparseSuffixArray::new(&reference_seq, 1).unwrap();
+
+        let similar = estimate_min_len(&reference, &mutate_every(400));
+        let divergent = estimate_min_len(&reference, &mutate_every(12));
+        assert!(similar < divergent, "similar {} should be below divergent {}", similar, divergent);
+        assert!(divergent <= AUTO_MIN_LEN_PROBE);
+    }
+
+    #[test]
+    fn test_remove_duplicate_matches() {
+        let reference_seq = b"ACGTACGTACGTTTACGTACGT";
+        let reference = SparseSuffixArray::new(reference_seq, 1).unwrap();
+        let query = b"ACGTACGT";
+
+        // Two passes over the same query emit every candidate twice
+        let candidates = [mem_candidates(&reference, query, 4, 1), mem_candidates(&reference, query, 4, 1)].concat();
+        let unique = remove_duplicate_matches(candidates.clone());
+        assert_eq!(unique.len() * 2, candidates.len());
+        assert_eq!(unique, mem_candidates(&reference, query, 4, 1));
+
+        // Same coordinates on the other strand are a different match
+        let mut both_strands = unique.clone();
+        both_strands.push(unique[0].clone().with_strand(crate::Strand::Reverse));
+        assert_eq!(remove_duplicate_matches(both_strands).len(), unique.len() + 1);
+
+        assert_eq!(remove_redundant_matches(candidates), find_all_mems(&reference, query, 4));
+    }
+
+    #[test]
+    fn test_filter_matches_by_range() {
+        let inside = Match::new(110, 0, 20);
+        let overlapping_start = Match::new(90, 30, 15);
+        let overlapping_end = Match::new(195, 60, 10);
+        let before = Match::new(50, 100, 50);
+        let after = Match::new(200, 5, 10);
+        let matches = vec![inside.clone(), overlapping_start.clone(), overlapping_end.clone(), before, after];
+
+        let by_ref = filter_matches_by_ref_range(&matches, 100, 200);
+        assert_eq!(by_ref, vec![inside.clone(), overlapping_start.clone(), overlapping_end]);
+
+        // Query [25, 60): the 30..45 match lies inside, 0..20 and 60..70 touch neither end
+        let by_query = filter_matches_by_query_range(&matches, 25, 60);
+        assert_eq!(by_query, vec![overlapping_start]);
+        let by_query = filter_matches_by_query_range(&matches, 10, 31);
+        assert_eq!(by_query, vec![inside, Match::new(90, 30, 15), Match::new(200, 5, 10)]);
+    }
+
+    /// Naive scanning index standing in for an external backend
+    struct ScanIndex(Vec<u8>);
+
+    impl Index for ScanIndex {
+        fn find_matches(&self, pattern: &[u8]) -> Vec<Match> {
+            if pattern.is_empty() || pattern.len() > self.0.len() {
+                return Vec::new();
+            }
+            self.0
+                .windows(pattern.len())
+                .enumerate()
+                .filter(|(_, window)| *window == pattern)
+                .map(|(pos, _)| Match::new(pos, 0, pattern.len()))
+                .collect()
+        }
+
+        fn sequence(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    #[test]
+    fn test_run_mummer_algorithm_with_custom_index() {
+        let reference_seq = b"TTTTTACGTACGCCCCCGATCGATAAAAATGA";
+        let query = b"ACGTACGGGGGGATCGATGGTGA";
+        let mock = ScanIndex(reference_seq.to_vec());
+        let suffix_array = SparseSuffixArray::new(reference_seq, 1).unwrap();
+
+        for algorithm in [MatchType::MUM, MatchType::MAM, MatchType::MEM, MatchType::MUMi] {
+            let from_mock = run_mummer_algorithm(&mock, query, algorithm.clone(), 3);
+            let from_suffix_array = run_mummer_algorithm(&suffix_array, query, algorithm.clone(), 3);
+            assert!(!from_mock.is_empty());
+            assert_eq!(from_mock, from_suffix_array, "{:?}", algorithm);
+        }
+    }
+
     #[test]
     fn test_find_mams() {
         let reference_seq = b"ATCGGCTA$";
@@ -0,0 +1,433 @@
+//! Banded global alignment, whole-sequence identity and delta encoding of gapped alignments
+
+/// Scores used by the global aligner; mismatches and gaps should be negative
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scoring {
+    pub match_score: i64,
+    pub mismatch: i64,
+    pub gap: i64,
+}
+
+impl Default for Scoring {
+    fn default() -> Self {
+        Self {
+            match_score: 1,
+            mismatch: -1,
+            gap: -2,
+        }
+    }
+}
+
+/// Diagonals searched on each side of the main one, beyond the length difference
+pub const DEFAULT_IDENTITY_BAND: usize = 64;
+
+/// Summary of an optimal global alignment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlobalAlignment {
+    pub score: i64,
+    /// Aligned columns with the same base in both sequences
+    pub matches: usize,
+    /// Total aligned columns, including gaps
+    pub length: usize,
+}
+
+const DIAGONAL: u8 = 0;
+const UP: u8 = 1; // gap in `b`
+const LEFT: u8 = 2; // gap in `a`
+
+/// Needleman-Wunsch alignment of `a` against `b`, restricted to the `band` diagonals on either
+/// side of the main diagonal (widened to cover the length difference)
+///
+/// Only the band is stored, so memory is O(len * band). Bases are compared case-insensitively.
+pub fn global_alignment(a: &[u8], b: &[u8], scoring: &Scoring, band: usize) -> GlobalAlignment {
+    let (score, ops) = banded_alignment(a, b, scoring, band);
+    let (mut i, mut j, mut matches) = (0, 0, 0);
+    for op in &ops {
+        match op {
+            AlignOp::Aligned => {
+                if a[i].eq_ignore_ascii_case(&b[j]) {
+                    matches += 1;
+                }
+                i += 1;
+                j += 1;
+            }
+            AlignOp::Deletion => i += 1,
+            AlignOp::Insertion => j += 1,
+        }
+    }
+    GlobalAlignment { score, matches, length: ops.len() }
+}
+
+/// Columns of the alignment `global_alignment` finds, `a` being the reference and `b` the query
+pub fn global_alignment_ops(a: &[u8], b: &[u8], scoring: &Scoring, band: usize) -> Vec<AlignOp> {
+    banded_alignment(a, b, scoring, band).1
+}
+
+/// Score and columns of the banded global alignment of `a` against `b`
+fn banded_alignment(a: &[u8], b: &[u8], scoring: &Scoring, band: usize) -> (i64, Vec<AlignOp>) {
+    let (n, m) = (a.len(), b.len());
+    let w = band.max(n.abs_diff(m));
+    let width = 2 * w + 1;
+    let in_band = |i: usize, j: usize| j + w >= i && j <= i + w;
+    let index = |i: usize, j: usize| i * width + (j + w - i);
+
+    let mut scores = vec![i64::MIN; (n + 1) * width];
+    let mut trace = vec![DIAGONAL; (n + 1) * width];
+    let get = |scores: &[i64], i: usize, j: usize| {
+        if in_band(i, j) { scores[index(i, j)] } else { i64::MIN }
+    };
+
+    for i in 0..=n {
+        for j in i.saturating_sub(w)..=m.min(i + w) {
+            if i == 0 && j == 0 {
+                scores[index(0, 0)] = 0;
+                continue;
+            }
+            let mut best = (i64::MIN, DIAGONAL);
+            if i > 0 && j > 0 {
+                let prev = get(&scores, i - 1, j - 1);
+                if prev != i64::MIN {
+                    let step = if a[i - 1].eq_ignore_ascii_case(&b[j - 1]) { scoring.match_score } else { scoring.mismatch };
+                    best = (prev + step, DIAGONAL);
+                }
+            }
+            if i > 0 {
+                let prev = get(&scores, i - 1, j);
+                if prev != i64::MIN && prev + scoring.gap > best.0 {
+                    best = (prev + scoring.gap, UP);
+                }
+            }
+            if j > 0 {
+                let prev = get(&scores, i, j - 1);
+                if prev != i64::MIN && prev + scoring.gap > best.0 {
+                    best = (prev + scoring.gap, LEFT);
+                }
+            }
+            scores[index(i, j)] = best.0;
+            trace[index(i, j)] = best.1;
+        }
+    }
+
+    // Walk back from the end, then put the columns in order
+    let (mut i, mut j) = (n, m);
+    let mut ops = Vec::new();
+    while i > 0 || j > 0 {
+        let step = if i == 0 { LEFT } else if j == 0 { UP } else { trace[index(i, j)] };
+        match step {
+            DIAGONAL => {
+                ops.push(AlignOp::Aligned);
+                i -= 1;
+                j -= 1;
+            }
+            UP => {
+                ops.push(AlignOp::Deletion);
+                i -= 1;
+            }
+            _ => {
+                ops.push(AlignOp::Insertion);
+                j -= 1;
+            }
+        }
+    }
+    ops.reverse();
+
+    (scores[index(n, m)], ops)
+}
+
+/// Score of the banded global alignment of `a` against `b`, as `global_alignment` computes it
+///
+/// Only two rows of the band are kept and no traceback is recorded, so memory is O(band)
+/// however long the sequences are.
+pub fn global_alignment_score(a: &[u8], b: &[u8], scoring: &Scoring, band: usize) -> i64 {
+    let (n, m) = (a.len(), b.len());
+    let w = band.max(n.abs_diff(m));
+    let width = 2 * w + 1;
+    // Column j of row i is stored at offset j + w - i
+    let mut previous = vec![i64::MIN; width];
+    let mut current = vec![i64::MIN; width];
+
+    for i in 0..=n {
+        current.fill(i64::MIN);
+        for j in i.saturating_sub(w)..=m.min(i + w) {
+            let offset = j + w - i;
+            if i == 0 && j == 0 {
+                current[offset] = 0;
+                continue;
+            }
+            let mut best = i64::MIN;
+            // (i - 1, j - 1) and (i - 1, j) sit at the same and the next offset of the row above
+            if i > 0 && j > 0 && previous[offset] != i64::MIN {
+                let step = if a[i - 1].eq_ignore_ascii_case(&b[j - 1]) { scoring.match_score } else { scoring.mismatch };
+                best = previous[offset] + step;
+            }
+            if i > 0 && offset + 1 < width && previous[offset + 1] != i64::MIN {
+                best = best.max(previous[offset + 1] + scoring.gap);
+            }
+            if j > 0 && offset > 0 && current[offset - 1] != i64::MIN {
+                best = best.max(current[offset - 1] + scoring.gap);
+            }
+            current[offset] = best;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[m + w - n]
+}
+
+/// Fraction of aligned columns that are identical in a banded global alignment of `a` and `b`
+/// Two empty sequences have identity 0
+pub fn sequence_identity(a: &[u8], b: &[u8], scoring: &Scoring) -> f64 {
+    let alignment = global_alignment(a, b, scoring, DEFAULT_IDENTITY_BAND);
+    if alignment.length == 0 {
+        0.0
+    } else {
+        alignment.matches as f64 / alignment.length as f64
+    }
+}
+
+/// Gap character used in aligned rows
+pub const GAP: u8 = b'-';
+
+/// One column of a pairwise alignment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignOp {
+    Aligned,   // a reference base against a query base, identical or not
+    Deletion,  // a reference base against a gap in the query
+    Insertion, // a query base against a gap in the reference
+}
+
+/// Columns of a pair of aligned rows
+pub fn align_ops(aligned_ref: &[u8], aligned_query: &[u8]) -> Vec<AlignOp> {
+    aligned_ref
+        .iter()
+        .zip(aligned_query)
+        .map(|(&r, &q)| {
+            if q == GAP {
+                AlignOp::Deletion
+            } else if r == GAP {
+                AlignOp::Insertion
+            } else {
+                AlignOp::Aligned
+            }
+        })
+        .collect()
+}
+
+/// Aligned rows of `ref_slice` and `query_slice` laid out by alignment columns, with `GAP`
+/// facing each indel; the inverse of `align_ops`
+pub fn aligned_rows(ref_slice: &[u8], query_slice: &[u8], ops: &[AlignOp]) -> (Vec<u8>, Vec<u8>) {
+    let (mut aligned_ref, mut aligned_query) = (Vec::with_capacity(ops.len()), Vec::with_capacity(ops.len()));
+    let (mut r, mut q) = (0, 0);
+    for op in ops {
+        match op {
+            AlignOp::Aligned => {
+                aligned_ref.push(ref_slice[r]);
+                aligned_query.push(query_slice[q]);
+                r += 1;
+                q += 1;
+            }
+            AlignOp::Deletion => {
+                aligned_ref.push(ref_slice[r]);
+                aligned_query.push(GAP);
+                r += 1;
+            }
+            AlignOp::Insertion => {
+                aligned_ref.push(GAP);
+                aligned_query.push(query_slice[q]);
+                q += 1;
+            }
+        }
+    }
+    (aligned_ref, aligned_query)
+}
+
+/// Encode alignment columns as MUMmer delta integers
+///
+/// Each integer is the distance from the previous indel (or the start) to the next one, counting
+/// the indel itself: positive when a reference base faces a gap in the query, negative when a
+/// query base faces a gap in the reference. The list ends with a 0, as in a delta file, so an
+/// ungapped alignment is just the 0.
+pub fn delta_from_ops(ops: &[AlignOp]) -> Vec<i64> {
+    let mut deltas = Vec::new();
+    let mut distance = 0;
+    for op in ops {
+        distance += 1;
+        match op {
+            AlignOp::Aligned => continue,
+            AlignOp::Deletion => deltas.push(distance),
+            AlignOp::Insertion => deltas.push(-distance),
+        }
+        distance = 0;
+    }
+    deltas.push(0);
+    deltas
+}
+
+/// Encode a gapped alignment, given as its aligned rows, as MUMmer delta integers
+pub fn encode_delta(aligned_ref: &[u8], aligned_query: &[u8]) -> Vec<i64> {
+    delta_from_ops(&align_ops(aligned_ref, aligned_query))
+}
+
+/// Rebuild the gapped aligned rows of `ref_slice` and `query_slice` from delta integers
+///
+/// The inverse of `encode_delta`; the integers stop at the first 0 and the bases left after the
+/// last indel are aligned to each other. Rows are cut short if the deltas run past a slice.
+pub fn decode_delta(ref_slice: &[u8], query_slice: &[u8], deltas: &[i64]) -> (String, String) {
+    let (mut aligned_ref, mut aligned_query) = (Vec::new(), Vec::new());
+    let (mut r, mut q) = (0, 0);
+
+    for &delta in deltas.iter().take_while(|&&delta| delta != 0) {
+        // Aligned pairs before the indel
+        for _ in 1..delta.unsigned_abs() {
+            match (ref_slice.get(r), query_slice.get(q)) {
+                (Some(&rb), Some(&qb)) => {
+                    aligned_ref.push(rb);
+                    aligned_query.push(qb);
+                    r += 1;
+                    q += 1;
+                }
+                _ => break,
+            }
+        }
+        if delta > 0 {
+            let Some(&rb) = ref_slice.get(r) else { break };
+            aligned_ref.push(rb);
+            aligned_query.push(GAP);
+            r += 1;
+        } else {
+            let Some(&qb) = query_slice.get(q) else { break };
+            aligned_ref.push(GAP);
+            aligned_query.push(qb);
+            q += 1;
+        }
+    }
+    for (&rb, &qb) in ref_slice[r.min(ref_slice.len())..].iter().zip(&query_slice[q.min(query_slice.len())..]) {
+        aligned_ref.push(rb);
+        aligned_query.push(qb);
+    }
+
+    (
+        String::from_utf8_lossy(&aligned_ref).into_owned(),
+        String::from_utf8_lossy(&aligned_query).into_owned(),
+    )
+}
+
+/// Percent identity of every window of `window` consecutive columns in a pair of aligned rows
+///
+/// The window slides one column at a time, so the profile has one value per starting column;
+/// gap columns count as differences. It is empty if the rows are shorter than the window.
+pub fn identity_profile(aligned_ref: &[u8], aligned_query: &[u8], window: usize) -> Vec<f64> {
+    let identical: Vec<bool> = aligned_ref
+        .iter()
+        .zip(aligned_query)
+        .map(|(&r, &q)| r != GAP && r.eq_ignore_ascii_case(&q))
+        .collect();
+    if window == 0 || identical.len() < window {
+        return Vec::new();
+    }
+
+    let mut count = identical[..window].iter().filter(|&&same| same).count();
+    let mut profile = vec![100.0 * count as f64 / window as f64];
+    for start in 1..=identical.len() - window {
+        count = count + identical[start + window - 1] as usize - identical[start - 1] as usize;
+        profile.push(100.0 * count as f64 / window as f64);
+    }
+    profile
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|i| b"ACGTTGCAAGTC"[(i * 7 + i / 5) % 12]).collect()
+    }
+
+    #[test]
+    fn test_identity_single_substitution() {
+        let a = sample(200);
+        let mut b = a.clone();
+        b[100] = if b[100] == b'A' { b'C' } else { b'A' };
+
+        let identity = sequence_identity(&a, &b, &Scoring::default());
+        assert!((identity - 199.0 / 200.0).abs() < 1e-9, "identity {}", identity);
+        assert_eq!(sequence_identity(&a, &a, &Scoring::default()), 1.0);
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        let aligned_ref = "ACGTTA-CGGATC--A";
+        let aligned_query = "ACG-TACCGGA-CTTA";
+        let deltas = encode_delta(aligned_ref.as_bytes(), aligned_query.as_bytes());
+        assert_eq!(deltas, vec![4, -3, 5, -2, -1, 0]);
+
+        let ungapped = |row: &str| row.bytes().filter(|&b| b != GAP).collect::<Vec<u8>>();
+        let decoded = decode_delta(&ungapped(aligned_ref), &ungapped(aligned_query), &deltas);
+        assert_eq!(decoded, (aligned_ref.to_string(), aligned_query.to_string()));
+    }
+
+    #[test]
+    fn test_delta_from_ops_with_one_deletion_and_one_insertion() {
+        use AlignOp::*;
+        // ACGTA-CG against ACG-ATCG: a deletion in the 4th column, an insertion in the 6th
+        let ops = [Aligned, Aligned, Aligned, Deletion, Aligned, Insertion, Aligned, Aligned];
+        assert_eq!(delta_from_ops(&ops), vec![4, -2, 0]);
+        assert_eq!(align_ops(b"ACGTA-CG", b"ACG-ATCG"), ops);
+        assert_eq!(delta_from_ops(&[Aligned; 5]), vec![0]);
+    }
+
+    #[test]
+    fn test_score_only_alignment_agrees_with_global_alignment() {
+        let pairs: [(&[u8], &[u8]); 4] = [
+            (b"ACGTTGCAAGTCCATGGACC", b"ACGTTGCAGTCCATGGACC"),
+            (b"ACGTTGCAAGTCCATGGACC", b"ACGTAGCAAGTCCTTGGACCA"),
+            (b"GATTACA", b"GATTACA"),
+            (b"", b"ACG"),
+        ];
+        for (a, b) in pairs {
+            for band in [0, 2, 64] {
+                assert_eq!(
+                    global_alignment_score(a, b, &Scoring::default(), band),
+                    global_alignment(a, b, &Scoring::default(), band).score
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_global_alignment_with_deletion() {
+        let a = b"ACGTACGTTTGCA";
+        let b = b"ACGTACGTTGCA";
+        let alignment = global_alignment(a, b, &Scoring::default(), 2);
+        assert_eq!(alignment.length, 13);
+        assert_eq!(alignment.matches, 12);
+        assert_eq!(alignment.score, 12 - 2);
+
+        let ops = global_alignment_ops(a, b, &Scoring::default(), 2);
+        assert_eq!(ops.len(), alignment.length);
+        let (aligned_ref, aligned_query) = aligned_rows(a, b, &ops);
+        assert_eq!(aligned_ref, a);
+        assert_eq!(aligned_query.iter().filter(|&&base| base == GAP).count(), 1);
+        assert_eq!(aligned_query.iter().filter(|&&base| base != GAP).copied().collect::<Vec<u8>>(), b);
+        assert_eq!(align_ops(&aligned_ref, &aligned_query), ops);
+    }
+
+    #[test]
+    fn test_identity_profile_dips_at_divergent_patch() {
+        let aligned_ref = sample(100);
+        let mut aligned_query = aligned_ref.clone();
+        // Columns 40..50 are all substitutions or gaps
+        for column in 40..50 {
+            aligned_query[column] = if column % 3 == 0 { GAP } else if aligned_ref[column] == b'A' { b'C' } else { b'A' };
+        }
+
+        let profile = identity_profile(&aligned_ref, &aligned_query, 10);
+        assert_eq!(profile.len(), 91);
+        assert_eq!(profile[0], 100.0);
+        assert_eq!(profile[40], 0.0);
+        assert_eq!(profile[35], 50.0);
+        assert_eq!(profile[90], 100.0);
+        let lowest = profile.iter().cloned().fold(f64::INFINITY, f64::min);
+        assert_eq!(profile.iter().position(|&p| p == lowest), Some(40));
+        assert!(identity_profile(&aligned_ref, &aligned_query, 101).is_empty());
+    }
+}
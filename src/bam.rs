@@ -0,0 +1,298 @@
+//! BAM output: the SAM writer's header and records, binary-encoded and BGZF-compressed
+
+use std::io::{self, Write};
+
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+
+use crate::output_format::{match_scores, sam_records, write_sam_header, OutputOptions, SamRecord};
+use crate::suffix_array::Match;
+
+/// Largest amount of data put in one BGZF block, leaving room for incompressible input to grow
+const BGZF_BLOCK_SIZE: usize = 0xff00;
+
+/// The empty block that marks the end of a BGZF file
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Writes its input as a series of BGZF blocks: gzip members of at most 64KiB that record their
+/// own compressed size, so readers can seek between them
+pub struct BgzfWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, buffer: Vec::with_capacity(BGZF_BLOCK_SIZE) }
+    }
+
+    /// Write any buffered data and the end-of-file block, returning the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_block()?;
+        self.inner.write_all(&BGZF_EOF)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.buffer)?;
+        let compressed = encoder.finish()?;
+        let mut crc = Crc::new();
+        crc.update(&self.buffer);
+
+        // gzip header with the BC extra field holding the total block size minus one
+        let block_size = 18 + compressed.len() + 8;
+        self.inner.write_all(&[0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0])?;
+        self.inner.write_all(&((block_size - 1) as u16).to_le_bytes())?;
+        self.inner.write_all(&compressed)?;
+        self.inner.write_all(&crc.sum().to_le_bytes())?;
+        self.inner.write_all(&(self.buffer.len() as u32).to_le_bytes())?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let len = data.len().min(BGZF_BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..len]);
+        if self.buffer.len() == BGZF_BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(len)
+    }
+
+    /// Close the current block; data already written becomes readable from the inner writer
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.inner.flush()
+    }
+}
+
+/// Writes one BAM file for a whole run: the header up front, the records of each query as they
+/// come, and the end-of-file block once finished
+pub struct BamWriter<W: Write> {
+    bgzf: BgzfWriter<W>,
+}
+
+impl<W: Write> BamWriter<W> {
+    /// Start a BAM file against the single reference `reference_seq`, named as in the SAM header
+    pub fn new(inner: W, ref_name: &str, reference_seq: &[u8]) -> io::Result<Self> {
+        let mut bgzf = BgzfWriter::new(inner);
+        let mut header_text = Vec::new();
+        write_sam_header(&mut header_text, ref_name, reference_seq)?;
+        bgzf.write_all(b"BAM\x01")?;
+        bgzf.write_all(&to_i32(header_text.len())?.to_le_bytes())?;
+        bgzf.write_all(&header_text)?;
+        // The single reference sequence
+        bgzf.write_all(&1i32.to_le_bytes())?;
+        bgzf.write_all(&to_i32(ref_name.len() + 1)?.to_le_bytes())?;
+        bgzf.write_all(ref_name.as_bytes())?;
+        bgzf.write_all(&[0])?;
+        bgzf.write_all(&to_i32(reference_seq.len())?.to_le_bytes())?;
+        Ok(Self { bgzf })
+    }
+
+    /// Append the records of one query's matches, which must be canonical
+    pub fn write_matches(&mut self, matches: &[Match], query_file: &str, reference_seq: &[u8], query_seq: &[u8], query_qual: Option<&[u8]>, options: &OutputOptions) -> io::Result<()> {
+        let scores = match_scores(matches, reference_seq, query_seq, options);
+        self.write_scored_matches(matches, query_file, query_seq, query_qual, scores.as_deref())
+    }
+
+    /// Append the records of one query's matches, with `scores` already computed by `match_scores`
+    pub(crate) fn write_scored_matches(&mut self, matches: &[Match], query_file: &str, query_seq: &[u8], query_qual: Option<&[u8]>, scores: Option<&[i64]>) -> io::Result<()> {
+        for record in sam_records(matches, query_file, query_seq, query_qual, scores) {
+            let encoded = encode_record(&record)?;
+            self.bgzf.write_all(&to_i32(encoded.len())?.to_le_bytes())?;
+            self.bgzf.write_all(&encoded)?;
+        }
+        Ok(())
+    }
+
+    /// Write the remaining records and the end-of-file block, returning the inner writer
+    pub fn finish(self) -> io::Result<W> {
+        self.bgzf.finish()
+    }
+}
+
+/// Write matches as a complete BAM file with the same header and records as the SAM writer
+/// `scores` gives the AS tag of each record, as computed by `match_scores`
+#[allow(clippy::too_many_arguments)]
+pub fn write_matches_bam<W: Write>(out: &mut W, matches: &[Match], query_file: &str, reference_seq: &[u8], query_seq: &[u8], query_qual: Option<&[u8]>, scores: Option<&[i64]>, options: &OutputOptions) -> io::Result<()> {
+    let mut bam = BamWriter::new(out, &options.ref_name, reference_seq)?;
+    bam.write_scored_matches(matches, query_file, query_seq, query_qual, scores)?;
+    bam.finish()?;
+    Ok(())
+}
+
+/// A BAM alignment record, without its leading block size
+fn encode_record(record: &SamRecord) -> io::Result<Vec<u8>> {
+    if record.qname.len() > 254 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Query name '{}' is too long for BAM", record.qname),
+        ));
+    }
+    let cigar = parse_cigar(&record.cigar)?;
+    let pos = to_i32(record.pos)? - 1;
+    let ref_span: u32 = cigar
+        .iter()
+        .filter(|&&op| matches!(op & 0xf, 0 | 2 | 3 | 7 | 8)) // M, D, N, =, X
+        .map(|&op| op >> 4)
+        .sum();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&(if record.pos > 0 { 0i32 } else { -1 }).to_le_bytes());
+    out.extend_from_slice(&pos.to_le_bytes());
+    out.push(record.qname.len() as u8 + 1);
+    out.push(record.mapq);
+    out.extend_from_slice(&reg2bin(pos.max(0) as u32, pos.max(0) as u32 + ref_span.max(1)).to_le_bytes());
+    out.extend_from_slice(&(cigar.len() as u16).to_le_bytes());
+    out.extend_from_slice(&record.flag.to_le_bytes());
+    out.extend_from_slice(&to_i32(record.seq.len())?.to_le_bytes());
+    // No mate: next refID and pos -1, template length 0
+    out.extend_from_slice(&(-1i32).to_le_bytes());
+    out.extend_from_slice(&(-1i32).to_le_bytes());
+    out.extend_from_slice(&0i32.to_le_bytes());
+    out.extend_from_slice(record.qname.as_bytes());
+    out.push(0);
+    for op in &cigar {
+        out.extend_from_slice(&op.to_le_bytes());
+    }
+    for pair in record.seq.chunks(2) {
+        let high = base_code(pair[0]) << 4;
+        out.push(high | pair.get(1).map_or(0, |&base| base_code(base)));
+    }
+    match record.qual {
+        Some(qual) => out.extend(qual.iter().map(|q| q.saturating_sub(33))),
+        None => out.extend(std::iter::repeat_n(0xff, record.seq.len())),
+    }
+    if let Some(score) = record.score {
+        out.extend_from_slice(b"ASi");
+        out.extend_from_slice(&(score.clamp(i32::MIN.into(), i32::MAX.into()) as i32).to_le_bytes());
+    }
+    Ok(out)
+}
+
+/// CIGAR operations as BAM encodes them: length << 4 | operation code
+fn parse_cigar(cigar: &str) -> io::Result<Vec<u32>> {
+    if cigar == "*" {
+        return Ok(Vec::new());
+    }
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid CIGAR '{}'", cigar));
+    let mut ops = Vec::new();
+    let mut len: u32 = 0;
+    for c in cigar.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            len = len.checked_mul(10).and_then(|len| len.checked_add(digit)).ok_or_else(invalid)?;
+        } else {
+            let code = "MIDNSHP=X".find(c).ok_or_else(invalid)? as u32;
+            ops.push(len << 4 | code);
+            len = 0;
+        }
+    }
+    Ok(ops)
+}
+
+/// 4-bit BAM code of a base; anything that is not an IUPAC code is N
+fn base_code(base: u8) -> u8 {
+    b"=ACMGRSVTWYHKDBN"
+        .iter()
+        .position(|&code| code == base.to_ascii_uppercase())
+        .unwrap_or(15) as u8
+}
+
+/// Smallest bin of the BAM index that holds the 0-based half-open region `[beg, end)`
+fn reg2bin(beg: u32, end: u32) -> u16 {
+    let end = end - 1;
+    for (shift, offset) in [(14, 4681), (17, 585), (20, 73), (23, 9), (26, 1)] {
+        if beg >> shift == end >> shift {
+            return (offset + (beg >> shift)) as u16;
+        }
+    }
+    0
+}
+
+fn to_i32(value: usize) -> io::Result<i32> {
+    i32::try_from(value).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("{} is too large for BAM", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    use flate2::read::MultiGzDecoder;
+
+    fn read_i32(data: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_bam_round_trip() {
+        let mut bam = Vec::new();
+        let options = OutputOptions::default();
+        write_matches_bam(&mut bam, &[Match::new(2, 1, 4)], "q", b"AAACGTAA", b"TACGTT", Some(b"IIII#I"), None, &options).unwrap();
+        assert!(bam.ends_with(&BGZF_EOF));
+        assert_eq!(&bam[12..14], b"BC");
+
+        let mut data = Vec::new();
+        MultiGzDecoder::new(&bam[..]).read_to_end(&mut data).unwrap();
+        assert_eq!(&data[..4], b"BAM\x01");
+        let l_text = read_i32(&data, 4) as usize;
+        assert_eq!(&data[8..8 + l_text], b"@HD\tVN:1.6\n@SQ\tSN:reference\tLN:8\n");
+        let mut at = 8 + l_text;
+        assert_eq!(read_i32(&data, at), 1);
+        assert_eq!(&data[at + 8..at + 18], b"reference\0");
+        assert_eq!(read_i32(&data, at + 18), 8);
+        at += 22;
+
+        // Exactly one record, at POS 3 with CIGAR 1S4M1S
+        let block_size = read_i32(&data, at) as usize;
+        assert_eq!(at + 4 + block_size, data.len());
+        let record = &data[at + 4..];
+        assert_eq!(read_i32(record, 0), 0);
+        assert_eq!(read_i32(record, 4) + 1, 3);
+        assert_eq!(u16::from_le_bytes([record[12], record[13]]), 3);
+        assert_eq!(read_i32(record, 16), 6);
+        assert_eq!(&record[32..34], b"q\0");
+        let cigar: Vec<u32> = (0..3).map(|i| read_i32(record, 34 + 4 * i) as u32).collect();
+        assert_eq!(cigar, vec![1 << 4 | 4, 4 << 4, 1 << 4 | 4]);
+        assert_eq!(&record[46..49], &[0x81, 0x24, 0x88]);
+        assert_eq!(&record[49..], &[40, 40, 40, 40, 2, 40]);
+    }
+
+    #[test]
+    fn test_one_header_and_eof_per_run() {
+        let options = OutputOptions::default();
+        let reference = b"AAACGTAA";
+        let mut bam = BamWriter::new(Vec::new(), &options.ref_name, reference).unwrap();
+        bam.write_matches(&[Match::new(2, 1, 4)], "q1", reference, b"TACGTT", None, &options).unwrap();
+        bam.write_matches(&[Match::new(0, 0, 3)], "q2", reference, b"AAA", None, &options).unwrap();
+        let bam = bam.finish().unwrap();
+        assert_eq!(bam.windows(BGZF_EOF.len()).filter(|block| *block == BGZF_EOF).count(), 1);
+        assert!(bam.ends_with(&BGZF_EOF));
+
+        let mut data = Vec::new();
+        MultiGzDecoder::new(&bam[..]).read_to_end(&mut data).unwrap();
+        assert_eq!(data.windows(4).filter(|magic| *magic == b"BAM\x01").count(), 1);
+        let mut at = 8 + read_i32(&data, 4) as usize + 22;
+        let mut names = Vec::new();
+        while at < data.len() {
+            let block_size = read_i32(&data, at) as usize;
+            let record = &data[at + 4..at + 4 + block_size];
+            let name_len = record[8] as usize;
+            names.push(&record[32..32 + name_len - 1]);
+            at += 4 + block_size;
+        }
+        assert_eq!(at, data.len());
+        assert_eq!(names, vec![&b"q1"[..], &b"q2"[..]]);
+    }
+}
@@ -0,0 +1,116 @@
+//! Synthetic benchmark of the full nucmer pipeline, for catching performance regressions
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::error::HelixError;
+use crate::nucmer::{NucmerAligner, NucmerOptions};
+
+/// Seed of the sequences generated by `run_bench`, so every run aligns the same data
+pub const BENCH_SEED: u64 = 0x5eed;
+
+/// Pseudo-random bases from a linear congruential generator; the same seed gives the same sequence
+pub fn synthetic_sequence(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            b"ACGT"[(state >> 62) as usize]
+        })
+        .collect()
+}
+
+/// A query related to `reference`: a copy with one substitution every 50 bases and its second
+/// half reverse complemented, so both strands and the extension code are exercised
+pub fn synthetic_query(reference: &[u8]) -> Vec<u8> {
+    let mut query = reference.to_vec();
+    for base in query.iter_mut().step_by(50).skip(1) {
+        *base = if *base == b'A' { b'C' } else { b'A' };
+    }
+    let half = query.len() / 2;
+    let tail: Vec<u8> = query[half..]
+        .iter()
+        .rev()
+        .map(|&base| match base {
+            b'A' => b'T',
+            b'T' => b'A',
+            b'C' => b'G',
+            _ => b'C',
+        })
+        .collect();
+    query.truncate(half);
+    query.extend(tail);
+    query
+}
+
+/// Timings of one benchmark run
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub size: usize, // bases in both the reference and the query
+    pub index_time: Duration,
+    pub align_time: Duration,
+    pub matches: usize,
+}
+
+impl BenchReport {
+    /// Reference bases indexed per second
+    pub fn index_throughput(&self) -> f64 {
+        self.size as f64 / self.index_time.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Query bases aligned per second
+    pub fn align_throughput(&self) -> f64 {
+        self.size as f64 / self.align_time.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Build an index of a `size`-base synthetic reference and align a related query against it
+pub fn run_bench(size: usize) -> Result<BenchReport, HelixError> {
+    let reference = synthetic_sequence(size, BENCH_SEED);
+    let query = synthetic_query(&reference);
+    let options = NucmerOptions {
+        quiet: true,
+        ..NucmerOptions::default()
+    };
+
+    let index_start = Instant::now();
+    let aligner = NucmerAligner::new(&reference, options)?;
+    let index_time = index_start.elapsed();
+
+    let align_start = Instant::now();
+    let matches = aligner.align(&query).len();
+    let align_time = align_start.elapsed();
+
+    Ok(BenchReport { size, index_time, align_time, matches })
+}
+
+/// Write a benchmark report as `key: value` lines
+pub fn write_bench_report<W: Write>(out: &mut W, report: &BenchReport) -> io::Result<()> {
+    writeln!(out, "size: {} bp", report.size)?;
+    writeln!(out, "index build: {:.3}s ({:.0} bp/s)", report.index_time.as_secs_f64(), report.index_throughput())?;
+    writeln!(out, "alignment: {:.3}s ({:.0} bp/s)", report.align_time.as_secs_f64(), report.align_throughput())?;
+    writeln!(out, "matches: {}", report.matches)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_runs_on_tiny_input() {
+        assert_eq!(synthetic_sequence(500, BENCH_SEED), synthetic_sequence(500, BENCH_SEED));
+        assert_ne!(synthetic_sequence(500, BENCH_SEED), synthetic_sequence(500, BENCH_SEED + 1));
+
+        let report = run_bench(300).unwrap();
+        assert_eq!(report.size, 300);
+        assert!(report.matches > 0);
+
+        let mut out = Vec::new();
+        write_bench_report(&mut out, &report).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let fields: Vec<&str> = text.lines().map(|line| line.split(':').next().unwrap()).collect();
+        assert_eq!(fields, vec!["size", "index build", "alignment", "matches"]);
+        assert!(text.contains("bp/s"));
+    }
+}
@@ -0,0 +1,71 @@
+//! Binary caching of alignment results, so a long run can be re-formatted without realigning
+//!
+//! Results are one list of matches per query, stored with bincode after a short header.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use crate::suffix_array::Match;
+
+/// Magic bytes and format version at the start of every results file
+const RESULTS_HEADER: &[u8; 5] = b"HXAR\x03";
+
+/// Write the matches of every query
+pub fn write_results<W: Write>(out: &mut W, results: &[Vec<Match>]) -> io::Result<()> {
+    out.write_all(RESULTS_HEADER)?;
+    bincode::serialize_into(out, results).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Read results written by `write_results`
+pub fn read_results<R: Read>(input: &mut R) -> io::Result<Vec<Vec<Match>>> {
+    let mut header = [0; RESULTS_HEADER.len()];
+    input.read_exact(&mut header)?;
+    if &header != RESULTS_HEADER {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a HelixAlign results file"));
+    }
+    bincode::deserialize_from(input).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Save the matches of every query to `path`
+pub fn serialize_results(path: &str, results: &[Vec<Match>]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write_results(&mut out, results)?;
+    out.flush()
+}
+
+/// Load results saved by `serialize_results`
+pub fn deserialize_results(path: &str) -> io::Result<Vec<Vec<Match>>> {
+    read_results(&mut BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{QueryCoords, Strand};
+
+    #[test]
+    fn test_results_round_trip() {
+        let results = vec![
+            vec![
+                Match::new(0, 5, 20),
+                Match { case_difference: true, ..Match::new(1 << 40, 7, 31) },
+                Match::new(12, 3, 9)
+                    .with_score(-3)
+                    .with_strand(Strand::Reverse)
+                    .with_query_coords(QueryCoords::ReverseComplement),
+            ],
+            Vec::new(),
+            vec![Match::new(99, 0, 1)],
+        ];
+
+        let path = std::env::temp_dir().join(format!("helixalign-results-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        serialize_results(path, &results).unwrap();
+        let loaded = deserialize_results(path);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.unwrap(), results);
+
+        let err = read_results(&mut &b"NOTHX\x00"[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
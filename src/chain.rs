@@ -0,0 +1,409 @@
+//! Chaining of exact matches into colinear alignments
+
+use crate::error::HelixError;
+use crate::suffix_array::Match;
+
+/// A colinear chain of matches, ordered by reference (and query) position
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chain {
+    pub matches: Vec<Match>,
+    pub score: i64,
+}
+
+/// Cost of the indel between two chained matches: `gap_open + gap_extend * len` for an indel of
+/// `len` bases, nothing when the matches lie on the same diagonal
+///
+/// The default charges one per base with no opening cost, so any split of an indel costs the
+/// same; a positive `gap_open` makes one long indel cheaper than several short ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GapCosts {
+    pub gap_open: i64,
+    pub gap_extend: i64,
+}
+
+impl Default for GapCosts {
+    fn default() -> Self {
+        Self {
+            gap_open: 0,
+            gap_extend: 1,
+        }
+    }
+}
+
+/// Parameters of `best_chain_with`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChainOptions {
+    pub max_gap: usize,               // bases allowed between consecutive matches, in either sequence
+    pub max_query_gap: Option<usize>, // tighter limit on query bases only, e.g. for read mapping
+    pub gap_costs: GapCosts,
+}
+
+impl Default for ChainOptions {
+    fn default() -> Self {
+        Self {
+            max_gap: 90,
+            max_query_gap: None,
+            gap_costs: GapCosts::default(),
+        }
+    }
+}
+
+impl ChainOptions {
+    /// Reject negative gap costs, which would reward chains for their indels
+    pub fn validate(&self) -> Result<(), HelixError> {
+        let GapCosts { gap_open, gap_extend } = self.gap_costs;
+        if gap_open < 0 || gap_extend < 0 {
+            return Err(HelixError::InvalidOptions(format!(
+                "gap costs must be non-negative, got gap_open {} and gap_extend {}",
+                gap_open, gap_extend
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Kind of indel between two consecutive matches of a chain, relative to the reference
+#[derive(Debug, Clone, PartialEq)]
+pub enum GapKind {
+    Insertion, // extra bases in the query
+    Deletion,  // bases of the reference missing from the query
+}
+
+/// An indel implied by the spacing of two consecutive matches in a chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gap {
+    pub ref_pos: usize,   // 0-based reference position where the gap starts
+    pub query_pos: usize, // 0-based query position where the gap starts
+    pub len: usize,
+    pub kind: GapKind,
+}
+
+impl Chain {
+    /// Indels between consecutive matches, where the reference and query gaps differ
+    ///
+    /// Matches that overlap, which `best_chain_with` never chains but a chain built by hand may
+    /// hold, share the overlapping bases: the indel is still the shift in diagonal between them,
+    /// placed at the end of the first match.
+    pub fn gaps(&self) -> Vec<Gap> {
+        self.matches
+            .windows(2)
+            .filter_map(|pair| {
+                let (prev, next) = (&pair[0], &pair[1]);
+                let shift = diagonal_shift(prev, next);
+                let kind = match shift.cmp(&0) {
+                    std::cmp::Ordering::Equal => return None,
+                    std::cmp::Ordering::Greater => GapKind::Deletion,
+                    std::cmp::Ordering::Less => GapKind::Insertion,
+                };
+                Some(Gap {
+                    ref_pos: prev.ref_pos + prev.len,
+                    query_pos: prev.query_pos + prev.len,
+                    len: shift.unsigned_abs() as usize,
+                    kind,
+                })
+            })
+            .collect()
+    }
+
+    /// 0-based start of the chain in the reference
+    pub fn ref_start(&self) -> usize {
+        self.matches.first().map_or(0, |m| m.ref_pos)
+    }
+
+    /// 0-based exclusive end of the chain in the reference
+    pub fn ref_end(&self) -> usize {
+        self.matches.last().map_or(0, |m| m.ref_pos + m.len)
+    }
+
+    /// 0-based start of the chain in the query
+    pub fn query_start(&self) -> usize {
+        self.matches.first().map_or(0, |m| m.query_pos)
+    }
+
+    /// 0-based exclusive end of the chain in the query
+    pub fn query_end(&self) -> usize {
+        self.matches.last().map_or(0, |m| m.query_pos + m.len)
+    }
+}
+
+/// Check whether `next` can follow `prev` in a chain with at most `max_gap` bases between them,
+/// and at most `max_query_gap` in the query
+fn can_follow(prev: &Match, next: &Match, options: &ChainOptions) -> bool {
+    let prev_ref_end = prev.ref_pos + prev.len;
+    let prev_query_end = prev.query_pos + prev.len;
+    let max_query_gap = options.max_query_gap.map_or(options.max_gap, |limit| limit.min(options.max_gap));
+    next.ref_pos >= prev_ref_end
+        && next.query_pos >= prev_query_end
+        && next.ref_pos - prev_ref_end <= options.max_gap
+        && next.query_pos - prev_query_end <= max_query_gap
+}
+
+/// Reference bases minus query bases between the starts of two matches: positive when `next` lies
+/// on a later diagonal, i.e. the query skips reference bases
+/// For matches that do not overlap, this is the reference gap minus the query gap.
+fn diagonal_shift(prev: &Match, next: &Match) -> i64 {
+    (next.ref_pos as i64 - prev.ref_pos as i64) - (next.query_pos as i64 - prev.query_pos as i64)
+}
+
+/// Penalty for joining two matches, for an indel as long as the difference between the
+/// reference and query gaps
+fn gap_penalty(prev: &Match, next: &Match, costs: &GapCosts) -> i64 {
+    match diagonal_shift(prev, next).abs() {
+        0 => 0,
+        indel => costs.gap_open + costs.gap_extend * indel,
+    }
+}
+
+/// Find the highest-scoring colinear chain of matches
+///
+/// A chain scores the total length of its matches minus the diagonal shift between each
+/// consecutive pair. Consecutive matches may not overlap and may be at most `max_gap` apart.
+pub fn best_chain(matches: &[Match], max_gap: usize) -> Option<Chain> {
+    best_chain_with(matches, &ChainOptions { max_gap, ..ChainOptions::default() })
+}
+
+/// Like `best_chain`, with each diagonal shift charged as an indel priced by `options.gap_costs`
+/// and query gaps limited by `options.max_query_gap`
+pub fn best_chain_with(matches: &[Match], options: &ChainOptions) -> Option<Chain> {
+    if matches.is_empty() {
+        return None;
+    }
+
+    let mut sorted = matches.to_vec();
+    sorted.sort_by(|a, b| {
+        a.ref_pos.cmp(&b.ref_pos)
+            .then_with(|| a.query_pos.cmp(&b.query_pos))
+    });
+
+    let mut scores: Vec<i64> = sorted.iter().map(|m| m.len as i64).collect();
+    let mut previous: Vec<Option<usize>> = vec![None; sorted.len()];
+
+    for j in 0..sorted.len() {
+        for i in 0..j {
+            if !can_follow(&sorted[i], &sorted[j], options) {
+                continue;
+            }
+            let score = scores[i] + sorted[j].len as i64 - gap_penalty(&sorted[i], &sorted[j], &options.gap_costs);
+            if score > scores[j] {
+                scores[j] = score;
+                previous[j] = Some(i);
+            }
+        }
+    }
+
+    // Ties go to the earliest chain end
+    let mut best = 0;
+    for j in 1..sorted.len() {
+        if scores[j] > scores[best] {
+            best = j;
+        }
+    }
+
+    let mut chain_matches = Vec::new();
+    let mut current = Some(best);
+    while let Some(j) = current {
+        chain_matches.push(sorted[j].clone());
+        current = previous[j];
+    }
+    chain_matches.reverse();
+
+    Some(Chain {
+        matches: chain_matches,
+        score: scores[best],
+    })
+}
+
+/// Group matches into clusters of nearby matches on similar diagonals
+///
+/// Two matches are linked when the later one starts at most `max_gap` bases after the earlier
+/// one ends in both sequences and their diagonals differ by at most
+/// `max(diag_diff, diag_factor * gap)`. Clusters are the connected groups of linked matches,
+/// each sorted by reference position; clusters are ordered by their first match.
+pub fn cluster_matches(matches: &[Match], max_gap: usize, diag_diff: usize, diag_factor: f64) -> Vec<Vec<Match>> {
+    let mut sorted = matches.to_vec();
+    sorted.sort_by(|a, b| {
+        a.ref_pos.cmp(&b.ref_pos)
+            .then_with(|| a.query_pos.cmp(&b.query_pos))
+    });
+
+    // Union-find over match indices
+    let mut parent: Vec<usize> = (0..sorted.len()).collect();
+    fn find(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    for j in 0..sorted.len() {
+        for i in (0..j).rev() {
+            let (a, b) = (&sorted[i], &sorted[j]);
+            if b.query_pos < a.query_pos {
+                continue;
+            }
+            let ref_gap = b.ref_pos.saturating_sub(a.ref_pos + a.len);
+            let query_gap = b.query_pos.saturating_sub(a.query_pos + a.len);
+            let gap = ref_gap.max(query_gap);
+            if gap > max_gap {
+                continue;
+            }
+            let diagonal_shift = (b.ref_pos as i64 - b.query_pos as i64)
+                .abs_diff(a.ref_pos as i64 - a.query_pos as i64) as usize;
+            let allowed_shift = diag_diff.max((diag_factor * gap as f64) as usize);
+            if diagonal_shift <= allowed_shift {
+                let (root_a, root_b) = (find(&mut parent, i), find(&mut parent, j));
+                parent[root_b] = root_a;
+            }
+        }
+    }
+
+    let mut clusters: Vec<Vec<Match>> = Vec::new();
+    let mut cluster_of_root = std::collections::HashMap::new();
+    for (i, m) in sorted.into_iter().enumerate() {
+        let root = find(&mut parent, i);
+        let index = *cluster_of_root.entry(root).or_insert_with(|| {
+            clusters.push(Vec::new());
+            clusters.len() - 1
+        });
+        clusters[index].push(m);
+    }
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_best_chain_prefers_longer_chain() {
+        let matches = vec![
+            // Competing chain near the start of the reference: 2 x 20bp
+            Match::new(0, 0, 20),
+            Match::new(30, 30, 20),
+            // Best chain further along: 3 x 25bp with a small diagonal shift
+            Match::new(500, 10, 25),
+            Match::new(545, 50, 25),
+            Match::new(590, 100, 25),
+        ];
+
+        let chain = best_chain(&matches, 90).unwrap();
+        assert_eq!(chain.matches.len(), 3);
+        assert_eq!(chain.score, 25 * 3 - 5 - 5);
+        assert_eq!((chain.ref_start(), chain.ref_end()), (500, 615));
+        assert_eq!((chain.query_start(), chain.query_end()), (10, 125));
+    }
+
+    #[test]
+    fn test_best_chain_respects_max_gap() {
+        let matches = vec![Match::new(0, 0, 20), Match::new(200, 200, 20)];
+        let chain = best_chain(&matches, 90).unwrap();
+        assert_eq!(chain.matches.len(), 1);
+        assert!(best_chain(&[], 90).is_none());
+    }
+
+    #[test]
+    fn test_affine_gap_costs_bridge_one_long_gap() {
+        // A 12bp deletion between two 20bp anchors
+        let matches = vec![Match::new(0, 0, 20), Match::new(32, 20, 20)];
+
+        let linear = ChainOptions { gap_costs: GapCosts { gap_open: 0, gap_extend: 2 }, ..ChainOptions::default() };
+        let chain = best_chain_with(&matches, &linear).unwrap();
+        assert_eq!(chain.matches.len(), 1);
+
+        let affine = ChainOptions { gap_costs: GapCosts { gap_open: 5, gap_extend: 1 }, ..ChainOptions::default() };
+        let chain = best_chain_with(&matches, &affine).unwrap();
+        assert_eq!(chain.matches.len(), 2);
+        assert_eq!(chain.score, 40 - (5 + 12));
+
+        // The default matches the plain chain score
+        assert_eq!(best_chain_with(&matches, &ChainOptions::default()), best_chain(&matches, 90));
+    }
+
+    #[test]
+    fn test_gap_open_prefers_one_long_gap() {
+        // Four 20bp anchors with 6 extra reference bases, either in one deletion or in three
+        // 2bp deletions; both routes share the first and last anchor
+        let one_gap = vec![Match::new(0, 0, 20), Match::new(20, 20, 20), Match::new(40, 40, 20), Match::new(66, 60, 20)];
+        let three_gaps = vec![Match::new(0, 0, 20), Match::new(22, 20, 20), Match::new(44, 40, 20), Match::new(66, 60, 20)];
+        let mut matches = one_gap.clone();
+        matches.extend_from_slice(&three_gaps[1..3]);
+
+        let affine = ChainOptions { gap_costs: GapCosts { gap_open: 5, gap_extend: 1 }, ..ChainOptions::default() };
+        let chain = best_chain_with(&matches, &affine).unwrap();
+        assert_eq!(chain.matches, one_gap);
+        assert_eq!(chain.score, 80 - (5 + 6));
+        assert_eq!(best_chain_with(&three_gaps, &affine).unwrap().score, 80 - 3 * (5 + 2));
+
+        // Without an opening cost, both routes cost the same
+        let linear = ChainOptions::default();
+        assert_eq!(best_chain_with(&one_gap, &linear).unwrap().score, best_chain_with(&three_gaps, &linear).unwrap().score);
+    }
+
+    #[test]
+    fn test_negative_gap_costs_rejected() {
+        assert!(ChainOptions::default().validate().is_ok());
+        let negative = ChainOptions { gap_costs: GapCosts { gap_open: -1, gap_extend: 1 }, ..ChainOptions::default() };
+        assert!(matches!(negative.validate(), Err(HelixError::InvalidOptions(_))));
+        let negative = ChainOptions { gap_costs: GapCosts { gap_open: 0, gap_extend: -1 }, ..ChainOptions::default() };
+        assert!(negative.validate().is_err());
+    }
+
+    #[test]
+    fn test_max_query_gap() {
+        // A 60bp query gap, matched by a 60bp reference gap
+        let matches = vec![Match::new(0, 0, 20), Match::new(80, 80, 20)];
+        assert_eq!(best_chain(&matches, 90).unwrap().matches.len(), 2);
+
+        let read_mapping = ChainOptions { max_query_gap: Some(50), ..ChainOptions::default() };
+        assert_eq!(best_chain_with(&matches, &read_mapping).unwrap().matches.len(), 1);
+
+        // Reference gaps are still bounded by max_gap alone
+        let matches = vec![Match::new(0, 0, 20), Match::new(80, 30, 20)];
+        let chain = best_chain_with(&matches, &ChainOptions { gap_costs: GapCosts { gap_open: 0, gap_extend: 0 }, ..read_mapping });
+        assert_eq!(chain.unwrap().matches.len(), 2);
+    }
+
+    #[test]
+    fn test_chain_gaps() {
+        let chain = Chain {
+            matches: vec![
+                Match::new(0, 0, 10),
+                Match::new(13, 10, 10), // 3bp deletion
+                Match::new(25, 22, 10), // same spacing on both sides, no indel
+            ],
+            score: 27,
+        };
+        assert_eq!(chain.gaps(), vec![Gap { ref_pos: 10, query_pos: 10, len: 3, kind: GapKind::Deletion }]);
+    }
+
+    #[test]
+    fn test_overlapping_matches_gaps() {
+        let chain = Chain {
+            matches: vec![
+                Match::new(0, 0, 10),
+                Match::new(8, 12, 10),  // overlaps in the reference: 4bp insertion
+                Match::new(20, 20, 10), // overlaps in the query: 4bp deletion
+                Match::new(25, 25, 10), // overlaps on the same diagonal, no indel
+            ],
+            score: 0,
+        };
+        assert_eq!(chain.gaps(), vec![
+            Gap { ref_pos: 10, query_pos: 10, len: 4, kind: GapKind::Insertion },
+            Gap { ref_pos: 18, query_pos: 22, len: 4, kind: GapKind::Deletion },
+        ]);
+    }
+
+    #[test]
+    fn test_cluster_matches() {
+        let matches = vec![
+            Match::new(0, 0, 20),
+            Match::new(30, 32, 20),   // small diagonal shift, joins the first cluster
+            Match::new(60, 200, 20),  // far off diagonal
+            Match::new(400, 400, 20), // too far along both sequences
+        ];
+        let clusters = cluster_matches(&matches, 90, 5, 0.12);
+        assert_eq!(clusters.len(), 3);
+        assert_eq!(clusters[0], vec![Match::new(0, 0, 20), Match::new(30, 32, 20)]);
+    }
+}
@@ -0,0 +1,136 @@
+//! Matching against circular references, whose matches may run across the origin
+
+use crate::suffix_array::{Index, Match, SparseSuffixArray, Strand};
+
+/// The reference with its first `overlap` bases appended, so that a match of up to
+/// `overlap + 1` bases can run across the origin
+pub fn circular_sequence(reference: &[u8], overlap: usize) -> Vec<u8> {
+    let overlap = overlap.min(reference.len());
+    [reference, &reference[..overlap]].concat()
+}
+
+/// A circular reference, searched through an index of its `circular_sequence`
+///
+/// Occurrences starting in the appended copy are hidden: they repeat an occurrence at the start
+/// of the reference, and would otherwise make every pattern found there look non-unique.
+pub struct CircularIndex<'a> {
+    index: &'a SparseSuffixArray,
+    ref_len: usize,
+}
+
+impl<'a> CircularIndex<'a> {
+    /// Wrap an index of `circular_sequence(reference, _)` for a reference of `ref_len` bases
+    pub fn new(index: &'a SparseSuffixArray, ref_len: usize) -> Self {
+        Self { index, ref_len }
+    }
+
+    /// Length of the circular reference, without the appended copy
+    pub fn ref_len(&self) -> usize {
+        self.ref_len
+    }
+}
+
+impl Index for CircularIndex<'_> {
+    fn find_matches(&self, pattern: &[u8]) -> Vec<Match> {
+        self.occurrences(pattern).map(|ref_pos| Match::new(ref_pos, 0, pattern.len())).collect()
+    }
+
+    /// The reference followed by its appended copy, so extensions can cross the origin
+    fn sequence(&self) -> &[u8] {
+        self.index.sequence()
+    }
+
+    fn occurrences<'a>(&'a self, pattern: &'a [u8]) -> Box<dyn Iterator<Item = usize> + 'a> {
+        let ref_len = self.ref_len;
+        Box::new(self.index.occurrences(pattern).filter(move |&ref_pos| ref_pos < ref_len))
+    }
+}
+
+/// Fold matches found on a `CircularIndex` back onto the circular reference
+///
+/// Every match then starts in `[0, ref_len)`; one running across the origin keeps its full
+/// length, so its end lies past `ref_len`. The part of such a match after the origin is also
+/// found on its own at the start of the reference, and is dropped.
+pub fn fold_circular_matches(matches: Vec<Match>, ref_len: usize) -> Vec<Match> {
+    let wrapping: Vec<Match> = matches.iter().filter(|m| m.ref_pos + m.len > ref_len).cloned().collect();
+    matches
+        .into_iter()
+        .filter(|m| {
+            !wrapping.iter().any(|w| {
+                // m lies on w's diagonal, within the part of w after the origin
+                let unwrapped = m.ref_pos + ref_len;
+                w.strand == m.strand
+                    && unwrapped >= w.ref_pos
+                    && unwrapped + m.len <= w.ref_pos + w.len
+                    && unwrapped - w.ref_pos == m.query_pos.wrapping_sub(w.query_pos)
+            })
+        })
+        .collect()
+}
+
+/// Split each canonical match that runs across the origin of a circular reference of `ref_len`
+/// bases into the part before the origin and the part after it
+///
+/// Formats with a fixed reference length cannot hold a span past its end, so they write the two
+/// parts as separate records. A split part has no DP score of its own and ranks by length.
+pub fn split_at_origin(matches: &[Match], ref_len: usize) -> Vec<Match> {
+    let mut split = Vec::with_capacity(matches.len());
+    for m in matches {
+        if m.ref_pos + m.len <= ref_len {
+            split.push(m.clone());
+            continue;
+        }
+        let before = ref_len - m.ref_pos;
+        let after = m.len - before;
+        // On the reverse strand the reference start pairs with the end of the query span
+        let (head_query, tail_query) = match m.strand {
+            Strand::Forward => (m.query_pos, m.query_pos + before),
+            Strand::Reverse => (m.query_pos + after, m.query_pos),
+        };
+        split.push(Match { ref_pos: m.ref_pos, query_pos: head_query, len: before, score: None, ..m.clone() });
+        split.push(Match { ref_pos: 0, query_pos: tail_query, len: after, score: None, ..m.clone() });
+    }
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_mummer_algorithm, MatchType};
+
+    #[test]
+    fn test_match_across_origin() {
+        let reference = b"GATTCAGCTAAGTCGATGCACCGTAGGCTTACG";
+        let ref_len = reference.len();
+        // The last 8 and first 8 bases of the reference, between unrelated flanks
+        let query = [&b"CCCC"[..], &reference[ref_len - 8..], &reference[..8], b"CCCC"].concat();
+        let extended = SparseSuffixArray::new(&circular_sequence(reference, query.len()), 1).unwrap();
+        let index = CircularIndex::new(&extended, ref_len);
+
+        for algorithm in [MatchType::MEM, MatchType::MAM] {
+            let matches = fold_circular_matches(run_mummer_algorithm(&index, &query, algorithm, 6), ref_len);
+            assert_eq!(matches, vec![Match::new(ref_len - 8, 4, 16)]);
+        }
+
+        // A linear index only sees the two halves
+        let linear = SparseSuffixArray::new(reference, 1).unwrap();
+        let matches = run_mummer_algorithm(&linear, &query, MatchType::MEM, 6);
+        assert_eq!(matches, vec![Match::new(0, 12, 8), Match::new(ref_len - 8, 4, 8)]);
+    }
+
+    #[test]
+    fn test_split_at_origin() {
+        let forward = Match::new(30, 4, 16);
+        assert_eq!(split_at_origin(std::slice::from_ref(&forward), 33), vec![Match::new(30, 4, 3), Match::new(0, 7, 13)]);
+
+        // The reverse complement of query[4..20] matches: its first 3 bases are the last 3 of the span
+        let reverse = Match::new(30, 4, 16).with_strand(Strand::Reverse);
+        assert_eq!(
+            split_at_origin(&[reverse], 33),
+            vec![Match::new(30, 17, 3).with_strand(Strand::Reverse), Match::new(0, 4, 13).with_strand(Strand::Reverse)],
+        );
+
+        let inside = Match::new(10, 0, 23);
+        assert_eq!(split_at_origin(std::slice::from_ref(&inside), 33), vec![inside]);
+    }
+}
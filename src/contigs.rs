@@ -0,0 +1,460 @@
+//! Mapping between concatenated sequence coordinates and the records they came from
+
+use std::io::{self, Write};
+use std::num::NonZeroUsize;
+
+use crate::nucmer::Alignment;
+use crate::suffix_array::Match;
+
+/// Start offsets and names of the records concatenated into a single sequence
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContigMap {
+    names: Vec<String>,
+    starts: Vec<usize>,
+    total_len: usize,
+}
+
+impl ContigMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a map from (name, sequence) records in the order they are concatenated
+    pub fn from_records(records: &[(String, Vec<u8>)]) -> Self {
+        let mut map = Self::new();
+        for (name, sequence) in records {
+            map.add_contig(name.clone(), sequence.len());
+        }
+        map
+    }
+
+    /// Append a contig of `len` bases after the existing ones
+    pub fn add_contig(&mut self, name: String, len: usize) {
+        self.names.push(name);
+        self.starts.push(self.total_len);
+        self.total_len += len;
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Name of the contig at `index`
+    pub fn name(&self, index: usize) -> &str {
+        &self.names[index]
+    }
+
+    /// Length of the contig at `index`
+    pub fn contig_len(&self, index: usize) -> usize {
+        let end = self.starts.get(index + 1).copied().unwrap_or(self.total_len);
+        end - self.starts[index]
+    }
+
+    /// Resolve a concatenated position to (contig index, 0-based offset within the contig)
+    pub fn locate(&self, pos: usize) -> Option<(usize, usize)> {
+        if pos >= self.total_len {
+            return None;
+        }
+        // Last contig starting at or before pos; empty contigs share a start with the next one
+        let index = self.starts.partition_point(|&start| start <= pos) - 1;
+        Some((index, pos - self.starts[index]))
+    }
+}
+
+/// 0-based half-open ranges of the contigs of a scaffold: the stretches between runs of at
+/// least `min_run` N bases
+/// Shorter runs stay inside their contig; a `min_run` of 0 keeps the whole sequence as one contig.
+pub fn split_on_n(sequence: &[u8], min_run: usize) -> Vec<(usize, usize)> {
+    if min_run == 0 {
+        return if sequence.is_empty() { Vec::new() } else { vec![(0, sequence.len())] };
+    }
+    let mut contigs = Vec::new();
+    let mut contig_start = 0;
+    let mut pos = 0;
+    while pos < sequence.len() {
+        let run = sequence[pos..].iter().take_while(|&&base| base.eq_ignore_ascii_case(&b'N')).count();
+        if run >= min_run || (run > 0 && (pos == 0 || pos + run == sequence.len())) {
+            if pos > contig_start {
+                contigs.push((contig_start, pos));
+            }
+            contig_start = pos + run;
+        }
+        pos += run.max(1);
+    }
+    if sequence.len() > contig_start {
+        contigs.push((contig_start, sequence.len()));
+    }
+    contigs
+}
+
+/// Align each contig of a scaffold on its own with `align`, mapping the matches back to
+/// scaffold coordinates
+/// Contigs are the ranges given by `split_on_n`; matches are returned in canonical form.
+pub fn align_contigs(sequence: &[u8], min_run: usize, align: impl Fn(&[u8]) -> Vec<Match>) -> Vec<Match> {
+    let mut matches = Vec::new();
+    for (start, end) in split_on_n(sequence, min_run) {
+        let contig = &sequence[start..end];
+        matches.extend(align(contig).into_iter().map(|m| {
+            let mut m = m.canonicalize(contig.len());
+            m.query_pos += start;
+            m
+        }));
+    }
+    matches
+}
+
+/// Parse a `name:start-end` region with 1-based inclusive coordinates
+pub fn parse_region(region: &str) -> Result<(String, usize, usize), String> {
+    let (name, range) = region
+        .rsplit_once(':')
+        .ok_or_else(|| format!("Invalid region '{}', expected name:start-end", region))?;
+    let (start, end) = range
+        .split_once('-')
+        .ok_or_else(|| format!("Invalid region '{}', expected name:start-end", region))?;
+    let start: usize = start.replace(',', "").parse()
+        .map_err(|_| format!("Invalid start coordinate '{}'", start))?;
+    let end: usize = end.replace(',', "").parse()
+        .map_err(|_| format!("Invalid end coordinate '{}'", end))?;
+    Ok((name.to_string(), start, end))
+}
+
+/// Extract the 1-based inclusive subsequence `name:start-end` from (name, sequence) records
+pub fn extract_region<'a>(records: &'a [(String, Vec<u8>)], region: &str) -> Result<&'a [u8], String> {
+    let (name, start, end) = parse_region(region)?;
+    let (_, sequence) = records
+        .iter()
+        .find(|(record_name, _)| *record_name == name)
+        .ok_or_else(|| format!("Contig '{}' not found in reference", name))?;
+    if start == 0 || start > end || end > sequence.len() {
+        return Err(format!(
+            "Region {}:{}-{} is out of range for contig of length {}",
+            name, start, end, sequence.len()
+        ));
+    }
+    Ok(&sequence[start - 1..end])
+}
+
+/// Order matches contig by contig, by increasing reference start within each contig
+/// The sort is stable, so matches at the same reference start keep their relative order
+pub fn group_matches_by_reference(matches: &mut [Match], contigs: &ContigMap) {
+    matches.sort_by_key(|m| {
+        let contig = contigs.locate(m.ref_pos).map_or(usize::MAX, |(index, _)| index);
+        (contig, m.ref_pos)
+    });
+}
+
+/// The leftmost-reference match of each query record, in reference order
+///
+/// `query_records` maps the concatenated query back to its records; a match belongs to the record
+/// its query start falls in. Ties on reference position go to the lower query position.
+pub fn first_match_per_record(matches: &[Match], query_records: &ContigMap) -> Vec<Match> {
+    let mut first: Vec<Option<&Match>> = vec![None; query_records.len()];
+    for m in matches {
+        let Some((record, _)) = query_records.locate(m.query_pos) else { continue };
+        let current = &mut first[record];
+        if current.is_none_or(|f| (m.ref_pos, m.query_pos) < (f.ref_pos, f.query_pos)) {
+            *current = Some(m);
+        }
+    }
+    let mut result: Vec<Match> = first.into_iter().flatten().cloned().collect();
+    result.sort_by_key(|m| (m.ref_pos, m.query_pos));
+    result
+}
+
+/// The `max_per_record` best matches of each query record, by decreasing `rank_score`
+///
+/// `query_records` maps the concatenated query back to its records, as for
+/// `first_match_per_record`. Equal scores keep the earlier match; kept matches stay in their
+/// input order.
+pub fn best_matches_per_record(matches: &[Match], query_records: &ContigMap, max_per_record: usize) -> Vec<Match> {
+    let ranked: Vec<(usize, i64)> = matches.iter().map(|m| (m.query_pos, m.rank_score())).collect();
+    let keep = best_per_record(&ranked, query_records, max_per_record);
+    matches.iter().zip(keep).filter(|&(_, keep)| keep).map(|(m, _)| m.clone()).collect()
+}
+
+/// The `max_per_record` best clustered alignments of each query record, by decreasing DP score
+///
+/// Alignments are ranked and placed in their records as their `Alignment::to_match` would be by
+/// `best_matches_per_record`.
+pub fn best_alignments_per_record(alignments: &[Alignment], query_records: &ContigMap, max_per_record: usize) -> Vec<Alignment> {
+    let ranked: Vec<(usize, i64)> = alignments.iter().map(|a| (a.query_start, a.score)).collect();
+    let keep = best_per_record(&ranked, query_records, max_per_record);
+    alignments.iter().zip(keep).filter(|&(_, keep)| keep).map(|(a, _)| a.clone()).collect()
+}
+
+// Whether each (query position, score) item is among the `max_per_record` best of its record
+fn best_per_record(ranked: &[(usize, i64)], query_records: &ContigMap, max_per_record: usize) -> Vec<bool> {
+    let mut order: Vec<usize> = (0..ranked.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(ranked[i].1));
+    let mut kept_per_record = vec![0; query_records.len()];
+    let mut keep = vec![false; ranked.len()];
+    for i in order {
+        let Some((record, _)) = query_records.locate(ranked[i].0) else { continue };
+        if kept_per_record[record] < max_per_record {
+            kept_per_record[record] += 1;
+            keep[i] = true;
+        }
+    }
+    keep
+}
+
+/// Matches overlapping one fixed-size window of a reference contig
+#[derive(Debug, Clone, PartialEq)]
+pub struct DensityWindow {
+    pub contig: usize,
+    /// 0-based start of the window within its contig
+    pub start: usize,
+    pub match_count: usize,
+    /// Bases in the window covered by at least one match
+    pub covered_bases: usize,
+}
+
+/// Bin matches into consecutive windows of `window` bases along every reference contig
+/// A match spanning a window boundary counts towards each window it overlaps
+pub fn match_density(matches: &[Match], contigs: &ContigMap, window: NonZeroUsize) -> Vec<DensityWindow> {
+    let window = window.get();
+    let mut first_window = Vec::with_capacity(contigs.len());
+    let mut windows = Vec::new();
+    for contig in 0..contigs.len() {
+        first_window.push(windows.len());
+        for start in (0..contigs.contig_len(contig)).step_by(window) {
+            windows.push(DensityWindow { contig, start, match_count: 0, covered_bases: 0 });
+        }
+    }
+
+    // Split every match into per-window pieces, in concatenated coordinates
+    let mut pieces: Vec<Vec<(usize, usize)>> = vec![Vec::new(); windows.len()];
+    for m in matches {
+        let end = m.ref_pos + m.len;
+        let mut pos = m.ref_pos;
+        while pos < end {
+            let Some((contig, offset)) = contigs.locate(pos) else { break };
+            let window_index = offset / window;
+            let window_end = (window_index + 1) * window;
+            let piece_end = end.min(pos - offset + window_end.min(contigs.contig_len(contig)));
+            pieces[first_window[contig] + window_index].push((pos, piece_end));
+            pos = piece_end;
+        }
+    }
+
+    for (density, mut pieces) in windows.iter_mut().zip(pieces) {
+        density.match_count = pieces.len();
+        pieces.sort_unstable();
+        let mut covered_end = 0;
+        for (start, end) in pieces {
+            let start = start.max(covered_end);
+            if end > start {
+                density.covered_bases += end - start;
+                covered_end = end;
+            }
+        }
+    }
+    windows
+}
+
+/// Write density windows as `contig window_start match_count covered_bases` TSV
+/// Window starts are 1-based
+pub fn write_density<W: Write>(out: &mut W, windows: &[DensityWindow], contigs: &ContigMap) -> io::Result<()> {
+    writeln!(out, "#contig\twindow_start\tmatch_count\tcovered_bases")?;
+    for window in windows {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}",
+            contigs.name(window.contig), window.start + 1, window.match_count, window.covered_bases
+        )?;
+    }
+    Ok(())
+}
+
+/// Index of the contig a query aligns to best, by summed `rank_score` (match length unless the
+/// matches carry alignment scores)
+/// Ties go to the earlier contig; `None` when no match lies in any contig
+pub fn best_contig(matches: &[Match], contigs: &ContigMap) -> Option<usize> {
+    let mut aligned = vec![0i64; contigs.len()];
+    for m in matches {
+        if let Some((contig, _)) = contigs.locate(m.ref_pos) {
+            aligned[contig] += m.rank_score();
+        }
+    }
+    aligned
+        .iter()
+        .enumerate()
+        .filter(|&(_, &score)| score > 0)
+        .min_by_key(|&(contig, &score)| (std::cmp::Reverse(score), contig))
+        .map(|(contig, _)| contig)
+}
+
+/// Write `query_id ref_contig` assignments as TSV, with `*` for unaligned queries
+pub fn write_best_contigs<W: Write>(
+    out: &mut W,
+    assignments: &[(&str, Option<usize>)],
+    contigs: &ContigMap,
+) -> io::Result<()> {
+    writeln!(out, "#query\tref_contig")?;
+    for (query, contig) in assignments {
+        writeln!(out, "{}\t{}", query, contig.map_or("*", |contig| contigs.name(contig)))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_contigs() -> ContigMap {
+        ContigMap::from_records(&[
+            ("chr1".to_string(), b"ACGTACGTAC".to_vec()),
+            ("chr2".to_string(), b"GGGGCCCC".to_vec()),
+        ])
+    }
+
+    #[test]
+    fn test_locate() {
+        let contigs = two_contigs();
+        assert_eq!(contigs.locate(0), Some((0, 0)));
+        assert_eq!(contigs.locate(9), Some((0, 9)));
+        assert_eq!(contigs.locate(10), Some((1, 0)));
+        assert_eq!(contigs.locate(17), Some((1, 7)));
+        assert_eq!(contigs.locate(18), None);
+        assert_eq!(contigs.name(1), "chr2");
+    }
+
+    #[test]
+    fn test_group_matches_by_reference() {
+        let contigs = two_contigs();
+        let mut matches = vec![
+            Match::new(14, 0, 3),
+            Match::new(5, 8, 4),
+            Match::new(11, 20, 2),
+            Match::new(1, 30, 3),
+        ];
+        group_matches_by_reference(&mut matches, &contigs);
+
+        let located: Vec<(usize, usize)> = matches
+            .iter()
+            .map(|m| contigs.locate(m.ref_pos).unwrap())
+            .collect();
+        assert_eq!(located, vec![(0, 1), (0, 5), (1, 1), (1, 4)]);
+    }
+
+    #[test]
+    fn test_match_density() {
+        let contigs = two_contigs();
+        // Three overlapping matches in chr1:1-4 and one in chr2:6-8
+        let matches = vec![
+            Match::new(0, 0, 3),
+            Match::new(1, 10, 2),
+            Match::new(2, 20, 2),
+            Match::new(15, 30, 3),
+        ];
+        let windows = match_density(&matches, &contigs, NonZeroUsize::new(5).unwrap());
+        let summary: Vec<(usize, usize, usize, usize)> = windows
+            .iter()
+            .map(|w| (w.contig, w.start, w.match_count, w.covered_bases))
+            .collect();
+        assert_eq!(summary, vec![(0, 0, 3, 4), (0, 5, 0, 0), (1, 0, 0, 0), (1, 5, 1, 3)]);
+
+        let mut out = Vec::new();
+        write_density(&mut out, &windows[..1], &contigs).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "#contig\twindow_start\tmatch_count\tcovered_bases\nchr1\t1\t3\t4\n");
+    }
+
+    #[test]
+    fn test_best_contig_prefers_longest_total() {
+        let contigs = two_contigs();
+        // One 6bp match in chr1 against two matches totalling 7bp in chr2
+        let matches = vec![Match::new(2, 0, 6), Match::new(10, 8, 4), Match::new(15, 14, 3)];
+        assert_eq!(best_contig(&matches, &contigs), Some(1));
+        assert_eq!(best_contig(&matches[..2], &contigs), Some(0));
+        assert_eq!(best_contig(&[], &contigs), None);
+
+        let mut out = Vec::new();
+        write_best_contigs(&mut out, &[("read1", Some(1)), ("read2", None)], &contigs).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "#query\tref_contig\nread1\tchr2\nread2\t*\n");
+    }
+
+    #[test]
+    fn test_split_on_n() {
+        let sequence = b"NNACGTNNACGTNNNNTTGNN";
+        assert_eq!(split_on_n(sequence, 3), vec![(2, 12), (16, 19)]);
+        assert_eq!(split_on_n(sequence, 2), vec![(2, 6), (8, 12), (16, 19)]);
+        assert_eq!(split_on_n(sequence, 0), vec![(0, 21)]);
+        assert!(split_on_n(b"NNNN", 2).is_empty());
+    }
+
+    #[test]
+    fn test_first_match_per_record() {
+        // Queries chr1 (10bp) and chr2 (8bp) concatenated
+        let query_records = two_contigs();
+        let matches = vec![
+            Match::new(40, 0, 5),
+            Match::new(12, 4, 5),
+            Match::new(30, 11, 4),
+            Match::new(12, 2, 3),
+            Match::new(25, 13, 4),
+        ];
+        assert_eq!(
+            first_match_per_record(&matches, &query_records),
+            vec![Match::new(12, 2, 3), Match::new(25, 13, 4)]
+        );
+    }
+
+    #[test]
+    fn test_best_matches_per_record() {
+        // Five matches in each of the two query records
+        let query_records = ContigMap::from_records(&[
+            ("read1".to_string(), vec![b'A'; 100]),
+            ("read2".to_string(), vec![b'A'; 100]),
+        ]);
+        let lens = [12, 30, 8, 25, 20];
+        let matches: Vec<Match> = (0..2)
+            .flat_map(|record| lens.iter().enumerate().map(move |(i, &len)| Match::new(i * 50, record * 100 + i * 15, len)))
+            .collect();
+        let kept = best_matches_per_record(&matches, &query_records, 2);
+        assert_eq!(
+            kept,
+            vec![Match::new(50, 15, 30), Match::new(150, 45, 25), Match::new(50, 115, 30), Match::new(150, 145, 25)]
+        );
+        assert_eq!(best_matches_per_record(&matches, &query_records, 10), matches);
+    }
+
+    #[test]
+    fn test_best_alignments_per_record_rank_by_score() {
+        let query_records = ContigMap::from_records(&[
+            ("read1".to_string(), vec![b'A'; 100]),
+            ("read2".to_string(), vec![b'A'; 100]),
+        ]);
+        let alignment = |query_start: usize, len: usize, score: i64| Alignment {
+            ref_start: 0,
+            ref_end: len,
+            query_start,
+            query_end: query_start + len,
+            strand: crate::Strand::Forward,
+            anchors: Vec::new(),
+            score,
+        };
+        // The longest alignment of read1 has the most indels and the lowest score
+        let alignments = vec![alignment(0, 60, 20), alignment(10, 40, 35), alignment(50, 30, 28), alignment(120, 50, 40)];
+        let kept = best_alignments_per_record(&alignments, &query_records, 1);
+        assert_eq!(kept, vec![alignments[1].clone(), alignments[3].clone()]);
+    }
+
+    #[test]
+    fn test_extract_region() {
+        let records = vec![
+            ("chr1".to_string(), b"ACGTACGTAC".to_vec()),
+            ("chr2".to_string(), b"GGGGCCCCAATTGGCCAATT".to_vec()),
+        ];
+        assert_eq!(extract_region(&records, "chr2:5-14").unwrap(), b"CCCCAATTGG");
+        assert_eq!(extract_region(&records, "chr1:1-1").unwrap(), b"A");
+
+        assert!(extract_region(&records, "chr1:5-11").unwrap_err().contains("out of range"));
+        assert!(extract_region(&records, "chr1:0-3").is_err());
+        assert!(extract_region(&records, "chr3:1-3").unwrap_err().contains("not found"));
+        assert!(extract_region(&records, "chr1").is_err());
+    }
+}
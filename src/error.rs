@@ -0,0 +1,58 @@
+//! Errors returned by the index and aligner constructors and the parallel aligners
+
+use std::error::Error;
+use std::fmt;
+
+/// Why an index or aligner could not be built
+#[derive(Debug)]
+pub enum HelixError {
+    /// An index or aligner was given a reference with no bases
+    EmptySequence,
+    /// A suffix array sampling rate of 0
+    InvalidSamplingRate(usize),
+    /// No sampling rate fits the index of `sequence_len` bases in the memory budget
+    IndexTooLarge { sequence_len: usize, min_bytes: usize },
+    /// The memory budget needs a sampling rate above the shortest pattern to be searched for
+    IndexTooSparse { sampling_rate: usize, min_pattern_len: usize },
+    /// Aligner options that cannot produce meaningful alignments
+    InvalidOptions(String),
+    /// The thread pool for a parallel run could not be started
+    ThreadPool(rayon::ThreadPoolBuildError),
+}
+
+impl fmt::Display for HelixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HelixError::EmptySequence => write!(f, "The reference sequence is empty"),
+            HelixError::InvalidSamplingRate(k) => write!(f, "Sampling rate k must be greater than 0, got {}", k),
+            HelixError::IndexTooLarge { sequence_len, min_bytes } => write!(
+                f,
+                "A sequence of {} bases needs at least {} bytes of index memory",
+                sequence_len, min_bytes
+            ),
+            HelixError::IndexTooSparse { sampling_rate, min_pattern_len } => write!(
+                f,
+                "The index memory budget samples every {} suffixes, so it cannot find matches of {} bases; raise the budget or the minimum match length",
+                sampling_rate, min_pattern_len
+            ),
+            HelixError::InvalidOptions(message) => write!(f, "{}", message),
+            HelixError::ThreadPool(e) => write!(f, "Could not start the thread pool: {}", e),
+        }
+    }
+}
+
+impl Error for HelixError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HelixError::ThreadPool(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The command line reports errors as strings
+impl From<HelixError> for String {
+    fn from(e: HelixError) -> Self {
+        e.to_string()
+    }
+}
@@ -1,4 +1,10 @@
-use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::num::NonZeroUsize;
+
+use memmap2::Mmap;
+
+use crate::{DnaSequence, Match, Strand};
 
 #[derive(Debug, Clone)]
 pub struct GenomicStats {
@@ -10,6 +16,7 @@ pub struct GenomicStats {
     pub min_length: usize,
     pub max_length: usize,
     pub gc_content: f64,
+    pub sorted_lengths: Vec<usize>, // sequence lengths, longest first
 }
 
 impl GenomicStats {
@@ -24,6 +31,7 @@ impl GenomicStats {
                 min_length: 0,
                 max_length: 0,
                 gc_content: 0.0,
+                sorted_lengths: Vec::new(),
             };
         }
 
@@ -62,7 +70,30 @@ impl GenomicStats {
             min_length,
             max_length,
             gc_content,
+            sorted_lengths,
+        }
+    }
+
+    /// Cumulative length curve over sequences sorted longest first
+    /// Returns (1-based rank, total length of the sequences up to and including that rank)
+    pub fn cumulative_lengths(&self) -> Vec<(usize, usize)> {
+        self.sorted_lengths
+            .iter()
+            .scan(0, |total, &len| {
+                *total += len;
+                Some(*total)
+            })
+            .enumerate()
+            .map(|(i, total)| (i + 1, total))
+            .collect()
+    }
+
+    /// Write the cumulative length curve as TSV rows of label, rank and cumulative length
+    pub fn write_cumulative<W: Write>(&self, out: &mut W, label: &str) -> io::Result<()> {
+        for (rank, total) in self.cumulative_lengths() {
+            writeln!(out, "{}\t{}\t{}", label, rank, total)?;
         }
+        Ok(())
     }
 
     fn calculate_nx(lengths: &[usize], percentage: f64) -> usize {
@@ -80,56 +111,708 @@ impl GenomicStats {
         lengths.first().copied().unwrap_or(0)
     }
 
-    pub fn print_stats(&self, label: &str) {
-        println!("{} Statistics:", label);
-        println!("  Number of sequences: {}", self.num_sequences);
-        println!("  Total length: {}", self.total_length);
-        println!("  Mean length: {:.2}", self.mean_length);
-        println!("  Min length: {}", self.min_length);
-        println!("  Max length: {}", self.max_length);
-        println!("  N50: {}", self.n50);
-        println!("  N90: {}", self.n90);
-        println!("  GC content: {:.2}%", self.gc_content);
-        println!();
+    pub fn print_stats(&self, label: &str, precision: usize) -> std::io::Result<()> {
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        self.write_stats(&mut out, label, precision)
+    }
+
+    /// Write the statistics block with `precision` decimal places for floating point values
+    pub fn write_stats<W: Write>(&self, out: &mut W, label: &str, precision: usize) -> io::Result<()> {
+        writeln!(out, "{} Statistics:", label)?;
+        writeln!(out, "  Number of sequences: {}", self.num_sequences)?;
+        writeln!(out, "  Total length: {}", self.total_length)?;
+        writeln!(out, "  Mean length: {:.*}", precision, self.mean_length)?;
+        writeln!(out, "  Min length: {}", self.min_length)?;
+        writeln!(out, "  Max length: {}", self.max_length)?;
+        writeln!(out, "  N50: {}", self.n50)?;
+        writeln!(out, "  N90: {}", self.n90)?;
+        writeln!(out, "  GC content: {:.*}%", precision, self.gc_content)?;
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+/// A bin of a match length histogram covering lengths in `[min_len, max_len)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LengthBin {
+    pub min_len: usize,
+    pub max_len: usize,
+    pub count: usize,
+}
+
+/// Histogram of match lengths in log-spaced (power of two) bins
+/// Bins run from the one holding the shortest match to the one holding the longest
+pub fn match_length_histogram(matches: &[Match]) -> Vec<LengthBin> {
+    let bin_of = |len: usize| len.max(1).ilog2() as usize;
+    let (Some(first), Some(last)) = (
+        matches.iter().map(|m| bin_of(m.len)).min(),
+        matches.iter().map(|m| bin_of(m.len)).max(),
+    ) else {
+        return Vec::new();
+    };
+    
+    let mut bins: Vec<LengthBin> = (first..=last)
+        .map(|bin| LengthBin { min_len: 1 << bin, max_len: 1 << (bin + 1), count: 0 })
+        .collect();
+    for m in matches {
+        bins[bin_of(m.len) - first].count += 1;
+    }
+    bins
+}
+
+/// Frequencies of A, C, G and T among the unambiguous bases of a sequence
+/// A sequence with no unambiguous bases is treated as uniform
+pub fn base_frequencies(sequence: &[u8]) -> [f64; 4] {
+    let mut counts = [0usize; 4];
+    for base in sequence {
+        match base.to_ascii_uppercase() {
+            b'A' => counts[0] += 1,
+            b'C' => counts[1] += 1,
+            b'G' => counts[2] += 1,
+            b'T' => counts[3] += 1,
+            _ => {}
+        }
+    }
+    let total: usize = counts.iter().sum();
+    if total == 0 {
+        return [0.25; 4];
+    }
+    counts.map(|count| count as f64 / total as f64)
+}
+
+/// Expected number of exact matches of at least `len` bases between random sequences of the
+/// given lengths, both drawn from `base_freqs` (A, C, G, T)
+///
+/// Two random bases agree with probability `p = sum(f^2)`, so a match of `len` bases is expected
+/// `ref_len * query_len * p^len` times by chance; smaller values are more significant.
+pub fn match_evalue(len: usize, ref_len: usize, query_len: usize, base_freqs: &[f64; 4]) -> f64 {
+    let p: f64 = base_freqs.iter().map(|f| f * f).sum();
+    ref_len as f64 * query_len as f64 * p.powi(len as i32)
+}
+
+/// Write a match length histogram, one bin per line with a bar of `#` scaled to the largest bin
+pub fn write_length_histogram<W: Write>(out: &mut W, label: &str, bins: &[LengthBin]) -> io::Result<()> {
+    writeln!(out, "Match length histogram for {}:", label)?;
+    let largest = bins.iter().map(|bin| bin.count).max().unwrap_or(0).max(1);
+    for bin in bins {
+        let bar = "#".repeat((bin.count * 50).div_ceil(largest));
+        writeln!(out, "  [{:>6}, {:>6})  {:>8}  {}", bin.min_len, bin.max_len, bin.count, bar)?;
+    }
+    Ok(())
+}
+
+/// Matched bases per strand and diagonal (`ref_pos - query_pos`) bin of `bin_size` diagonals
+///
+/// A reverse match runs along an anti-diagonal of the query as given, so it is binned by its
+/// diagonal against the reverse complement of the query, where it runs parallel to the main
+/// diagonal; `query_len` is the length of the whole query. Bins are keyed by their strand and
+/// lowest diagonal and only non-empty ones are returned, forward strand first, each strand in
+/// diagonal order. A query that aligns cleanly puts nearly all its weight in one bin; a
+/// scrambled one spreads it out.
+pub fn diagonal_histogram(matches: &[Match], query_len: usize, bin_size: NonZeroUsize) -> Vec<(Strand, isize, usize)> {
+    let bin_size = bin_size.get() as isize;
+    [Strand::Forward, Strand::Reverse]
+        .into_iter()
+        .flat_map(|strand| {
+            let mut bins = std::collections::BTreeMap::new();
+            for m in matches.iter().filter(|m| m.strand == strand) {
+                let query_pos = m.canonicalize(query_len).query_pos;
+                let query_pos = match strand {
+                    Strand::Forward => query_pos,
+                    Strand::Reverse => query_len - query_pos - m.len,
+                };
+                let diagonal = m.ref_pos as isize - query_pos as isize;
+                *bins.entry(diagonal.div_euclid(bin_size) * bin_size).or_insert(0) += m.len;
+            }
+            bins.into_iter().map(move |(bin, weight)| (strand, bin, weight))
+        })
+        .collect()
+}
+
+/// Write a diagonal histogram as one bar per bin, scaled to the heaviest bin
+pub fn write_diagonal_histogram<W: Write>(out: &mut W, label: &str, bins: &[(Strand, isize, usize)]) -> io::Result<()> {
+    writeln!(out, "Diagonal histogram for {}:", label)?;
+    let largest = bins.iter().map(|&(_, _, weight)| weight).max().unwrap_or(0).max(1);
+    for &(strand, diagonal, weight) in bins {
+        let bar = "#".repeat((weight * 50).div_ceil(largest));
+        let strand = if strand == Strand::Reverse { '-' } else { '+' };
+        writeln!(out, "  {} {:>10}  {:>8}  {}", strand, diagonal, weight, bar)?;
+    }
+    Ok(())
+}
+
+/// Number and total length of the matches on each strand
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OrientationStats {
+    pub forward_count: usize,
+    pub forward_len: usize,
+    pub reverse_count: usize,
+    pub reverse_len: usize,
+}
+
+impl OrientationStats {
+    /// Whether most matched bases are on the reverse strand, as for a misoriented contig
+    pub fn mostly_reverse(&self) -> bool {
+        self.reverse_len > self.forward_len
+    }
+}
+
+pub fn orientation_stats(matches: &[Match]) -> OrientationStats {
+    let mut stats = OrientationStats::default();
+    for m in matches {
+        if m.strand == Strand::Reverse {
+            stats.reverse_count += 1;
+            stats.reverse_len += m.len;
+        } else {
+            stats.forward_count += 1;
+            stats.forward_len += m.len;
+        }
     }
+    stats
+}
+
+/// Write a one-line strand summary, naming the majority strand by matched bases
+pub fn write_orientation_stats<W: Write>(out: &mut W, label: &str, stats: &OrientationStats) -> io::Result<()> {
+    let majority = if stats.mostly_reverse() { "reverse" } else { "forward" };
+    writeln!(
+        out,
+        "Orientation for {}: forward {} matches ({} bp), reverse {} matches ({} bp), mostly {}",
+        label, stats.forward_count, stats.forward_len, stats.reverse_count, stats.reverse_len, majority
+    )
 }
 
 pub fn parse_fasta(filename: &str) -> Vec<Vec<u8>> {
-    let content = std::fs::read_to_string(filename)
-        .expect("Could not read file");
+    parse_fasta_records(filename)
+        .into_iter()
+        .map(|(_, sequence)| sequence)
+        .filter(|sequence| !sequence.is_empty())
+        .collect()
+}
+
+/// Parse a FASTA file into (name, sequence) records
+/// The name is the header up to the first whitespace; a filename of "-" reads from stdin
+pub fn parse_fasta_records(filename: &str) -> Vec<(String, Vec<u8>)> {
+    let records = if filename == "-" {
+        parse_fasta_reader(io::stdin().lock())
+    } else {
+        File::open(filename).and_then(parse_fasta_reader)
+    };
+    records.expect("Could not read file")
+}
+
+/// Load (name, sequence) records from a FASTA, FASTQ or `.2bit` file, detected by its signature
+/// Files are memory-mapped rather than copied; a filename of "-" reads from stdin
+pub fn read_sequence_records(filename: &str) -> io::Result<Vec<(String, Vec<u8>)>> {
+    Ok(read_sequence_records_with_quality(filename, BaseOptions::default())?
+        .into_iter()
+        .map(|(name, sequence, _)| (name, sequence))
+        .collect())
+}
+
+/// A (name, sequence, quality) record; the quality string is only present for FASTQ input
+pub type QualityRecord = (String, Vec<u8>, Option<Vec<u8>>);
+
+/// Load records as `read_sequence_records` does, along with each record's quality string when
+/// the file is FASTQ, normalizing sequence bytes as `bases` says
+pub fn read_sequence_records_with_quality(filename: &str, bases: BaseOptions) -> io::Result<Vec<QualityRecord>> {
+    let mut buffer = Vec::new();
+    let mapped;
+    let data: &[u8] = if filename == "-" {
+        io::stdin().lock().read_to_end(&mut buffer)?;
+        &buffer
+    } else {
+        mapped = map_file(filename)?;
+        mapped.as_deref().unwrap_or(&[])
+    };
+    
+    let invalid_data = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+    let records = if crate::twobit::is_twobit(data) {
+        crate::twobit::parse_twobit(data).map_err(invalid_data)?
+    } else if is_fastq(data) {
+        return Ok(parse_fastq_bytes_with(data, bases)
+            .map_err(invalid_data)?
+            .into_iter()
+            .map(|(name, sequence, quality)| (name, sequence, Some(quality)))
+            .collect());
+    } else {
+        parse_fasta_bytes_with(data, bases).map_err(invalid_data)?
+    };
+    Ok(records.into_iter().map(|(name, sequence)| (name, sequence, None)).collect())
+}
+
+/// Whether raw bytes look like FASTQ: the first non-blank byte opens an `@` header
+pub fn is_fastq(data: &[u8]) -> bool {
+    data.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'@')
+}
+
+/// A (name, sequence, quality) FASTQ record
+pub type FastqRecord = (String, Vec<u8>, Vec<u8>);
+
+/// Parse FASTQ records from raw bytes into (name, sequence, quality)
+/// Names and bases are read as in `parse_fasta_bytes`. The sequence runs up to the `+` line and
+/// the quality string is then read until it is as long as the sequence, so quality lines
+/// starting with `@` are not mistaken for headers.
+pub fn parse_fastq_bytes(data: &[u8]) -> Vec<FastqRecord> {
+    parse_fastq_bytes_with(data, BaseOptions::default()).expect("invalid bases are read as N")
+}
+
+/// Parse FASTQ records as `parse_fastq_bytes` does, normalizing sequence bytes as `bases` says;
+/// a skipped base takes its quality with it
+pub fn parse_fastq_bytes_with(data: &[u8], bases: BaseOptions) -> Result<Vec<FastqRecord>, String> {
+    let mut records = Vec::new();
+    let mut lines = data.split(|&b| b == b'\n' || b == b'\r').filter(|line| !line.is_empty());
     
-    let mut sequences = Vec::new();
+    while let Some(line) = lines.next() {
+        let Some(header) = line.strip_prefix(b"@") else { continue };
+        let name = String::from_utf8_lossy(header).split_whitespace().next().unwrap_or("").to_string();
+        let mut raw = Vec::new();
+        for line in lines.by_ref() {
+            if line.starts_with(b"+") {
+                break;
+            }
+            raw.extend(line.iter().filter(|base| !base.is_ascii_whitespace()));
+        }
+        let mut raw_quality = Vec::with_capacity(raw.len());
+        while raw_quality.len() < raw.len() {
+            let Some(line) = lines.next() else { break };
+            raw_quality.extend_from_slice(line);
+        }
+        
+        let (mut sequence, mut quality) = (Vec::with_capacity(raw.len()), Vec::with_capacity(raw.len()));
+        for (i, &byte) in raw.iter().enumerate() {
+            if push_base(&mut sequence, byte, bases, &name)? {
+                quality.extend(raw_quality.get(i));
+            }
+        }
+        records.push((name, sequence, quality));
+    }
+    Ok(records)
+}
+
+/// Parse a FASTA file by scanning a memory map of it, avoiding an in-memory copy of the file
+pub fn parse_fasta_mmap(filename: &str) -> io::Result<Vec<DnaSequence>> {
+    let mapped = map_file(filename)?;
+    let records = parse_fasta_bytes(mapped.as_deref().unwrap_or(&[]));
+    Ok(records
+        .into_iter()
+        .map(|(name, sequence)| DnaSequence { sequence, description: name })
+        .collect())
+}
+
+/// Memory-map a file read-only; empty files cannot be mapped and yield None
+fn map_file(filename: &str) -> io::Result<Option<Mmap>> {
+    let file = File::open(filename)?;
+    if file.metadata()?.len() == 0 {
+        return Ok(None);
+    }
+    // SAFETY: the map is read-only and only lives for the duration of parsing; as with any
+    // mmap, the input file must not be truncated by another process while it is parsed
+    let mapped = unsafe { Mmap::map(&file)? };
+    Ok(Some(mapped))
+}
+
+/// Drop records shorter than `min_len`, returning the kept records and how many were dropped
+pub fn filter_records_by_length(records: Vec<(String, Vec<u8>)>, min_len: usize) -> (Vec<(String, Vec<u8>)>, usize) {
+    let total = records.len();
+    let kept: Vec<(String, Vec<u8>)> = records
+        .into_iter()
+        .filter(|(_, sequence)| sequence.len() >= min_len)
+        .collect();
+    let skipped = total - kept.len();
+    (kept, skipped)
+}
+
+/// Drop records whose GC percentage (as reported by `GenomicStats`) lies outside
+/// `min_gc..=max_gc`, returning the kept records and how many were dropped
+pub fn filter_records_by_gc(records: Vec<(String, Vec<u8>)>, min_gc: f64, max_gc: f64) -> (Vec<(String, Vec<u8>)>, usize) {
+    let total = records.len();
+    let kept: Vec<(String, Vec<u8>)> = records
+        .into_iter()
+        .filter(|(_, sequence)| {
+            let gc = GenomicStats::new(std::slice::from_ref(sequence)).gc_content;
+            (min_gc..=max_gc).contains(&gc)
+        })
+        .collect();
+    let skipped = total - kept.len();
+    (kept, skipped)
+}
+
+/// How lopsided the inputs must be before `swap_warning` suspects a swapped reference and query
+pub const SWAP_RATIO: usize = 10;
+
+/// Advisory message when the reference and query look swapped, given their record lengths
+///
+/// Suspicious inputs are a query at least `SWAP_RATIO` times longer than the reference, or a
+/// reference of at least `SWAP_RATIO` times as many records as the query that are shorter on
+/// average, like reads given as the reference of an assembly.
+pub fn swap_warning(reference_lengths: &[usize], query_lengths: &[usize]) -> Option<String> {
+    let reference_len: usize = reference_lengths.iter().sum();
+    let query_len: usize = query_lengths.iter().sum();
+    if reference_len == 0 || query_len == 0 {
+        return None;
+    }
+    if query_len / reference_len >= SWAP_RATIO {
+        return Some(format!(
+            "the query ({} bp) is {}x longer than the reference ({} bp); were the reference and query swapped?",
+            query_len, query_len / reference_len, reference_len
+        ));
+    }
+    let (reference_records, query_records) = (reference_lengths.len(), query_lengths.len());
+    if reference_records >= SWAP_RATIO * query_records
+        && reference_len * query_records < query_len * reference_records
+    {
+        return Some(format!(
+            "the reference has {} records but the query only {}, and they are longer on average; were the reference and query swapped?",
+            reference_records, query_records
+        ));
+    }
+    None
+}
+
+/// Parse FASTA records from any reader
+pub fn parse_fasta_reader<R: Read>(mut reader: R) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content)?;
+    Ok(parse_fasta_bytes(&content))
+}
+
+/// What to do with a sequence byte that is neither a base, an IUPAC ambiguity code nor whitespace
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnInvalidBase {
+    #[default]
+    ToN,   // read it as an N
+    Error, // reject the whole input
+    Skip,  // leave it out of the sequence
+}
+
+/// How sequence bytes are normalized while reading
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BaseOptions {
+    pub on_invalid: OnInvalidBase,
+    pub keep_ambiguity_codes: bool, // keep IUPAC codes such as R and Y for `iupac_matches`, rather than reading them as N
+}
+
+/// Parse FASTA records from raw bytes
+/// The name is the header up to the first whitespace; whitespace inside sequence lines is
+/// dropped, bases are upper-cased and anything other than ACGTN becomes N
+pub fn parse_fasta_bytes(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    parse_fasta_bytes_with(data, BaseOptions::default()).expect("invalid bases are read as N")
+}
+
+/// Parse FASTA records as `parse_fasta_bytes` does, normalizing sequence bytes as `bases` says
+pub fn parse_fasta_bytes_with(data: &[u8], bases: BaseOptions) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut records = Vec::new();
+    let mut current_name: Option<String> = None;
     let mut current_seq = Vec::new();
     
-    for line in content.lines() {
-        if line.starts_with('>') {
-            if !current_seq.is_empty() {
-                sequences.push(current_seq);
+    // Splitting on \r as well handles Windows (\r\n) and old Mac (\r) line endings; a stray \r
+    // left on a sequence line would otherwise be read as an N
+    for line in data.split(|&b| b == b'\n' || b == b'\r') {
+        if let Some(header) = line.strip_prefix(b">") {
+            if current_name.is_some() || !current_seq.is_empty() {
+                records.push((current_name.take().unwrap_or_default(), current_seq));
                 current_seq = Vec::new();
             }
+            let header = String::from_utf8_lossy(header);
+            current_name = Some(header.split_whitespace().next().unwrap_or("").to_string());
         } else {
-            current_seq.extend_from_slice(line.as_bytes());
+            // Hand-edited files can carry stray tabs or spaces inside sequence lines
+            let name = current_name.as_deref().unwrap_or_default();
+            for &byte in line.iter().filter(|base| !base.is_ascii_whitespace()) {
+                push_base(&mut current_seq, byte, bases, name)?;
+            }
         }
     }
     
     // Don't forget the last sequence
-    if !current_seq.is_empty() {
-        sequences.push(current_seq);
+    if current_name.is_some() || !current_seq.is_empty() {
+        records.push((current_name.unwrap_or_default(), current_seq));
     }
     
-    // Convert to uppercase and validate DNA sequence
-    for seq in &mut sequences {
-        for base in seq {
-            *base = match *base {
-                b'a' | b'A' => b'A',
-                b'c' | b'C' => b'C',
-                b'g' | b'G' => b'G',
-                b't' | b'T' => b'T',
-                b'n' | b'N' => b'N',
-                _ => b'N', // Default to N for non-standard bases
-            };
+    Ok(records)
+}
+
+/// Append a sequence byte to `sequence`, normalized, following `bases.on_invalid` if it is not a
+/// base or an ambiguity code
+/// Returns whether anything was appended
+fn push_base(sequence: &mut Vec<u8>, byte: u8, bases: BaseOptions, record: &str) -> Result<bool, String> {
+    match (normalize_base(byte, bases.keep_ambiguity_codes), bases.on_invalid) {
+        (Some(base), _) => sequence.push(base),
+        (None, OnInvalidBase::ToN) => sequence.push(b'N'),
+        (None, OnInvalidBase::Skip) => return Ok(false),
+        (None, OnInvalidBase::Error) => {
+            return Err(format!(
+                "invalid base '{}' in record '{}' at position {}",
+                byte.escape_ascii(), record, sequence.len() + 1
+            ));
         }
     }
-    
-    sequences
+    Ok(true)
+}
+
+/// Convert to uppercase and validate a DNA base; None for anything that is not a base
+/// Ambiguity codes are read as N unless `keep_ambiguity_codes` is set
+fn normalize_base(base: u8, keep_ambiguity_codes: bool) -> Option<u8> {
+    match base.to_ascii_uppercase() {
+        base @ (b'A' | b'C' | b'G' | b'T' | b'N') => Some(base),
+        base @ (b'R' | b'Y' | b'S' | b'W' | b'K' | b'M' | b'B' | b'D' | b'H' | b'V') => {
+            Some(if keep_ambiguity_codes { base } else { b'N' })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_evalue_decays_exponentially() {
+        let freqs = base_frequencies(b"AATTGCATTA");
+        assert_eq!(freqs, [0.4, 0.1, 0.1, 0.4]);
+        let p: f64 = 0.34;
+
+        let evalues: Vec<f64> = (10..=30).step_by(10).map(|len| match_evalue(len, 1_000_000, 1_000, &freqs)).collect();
+        assert!(evalues[0] > evalues[1] && evalues[1] > evalues[2]);
+        // Each extra base scales the E-value by p
+        for pair in evalues.windows(2) {
+            assert!((pair[1] / pair[0] - p.powi(10)).abs() < 1e-12);
+        }
+        // A biased composition makes chance matches likelier than a uniform one
+        assert!(evalues[0] > match_evalue(10, 1_000_000, 1_000, &base_frequencies(b"ACGT")));
+    }
+
+    #[test]
+    fn test_stats_precision() {
+        let stats = GenomicStats::new(&[b"GGCATTTATC".to_vec(), b"ACG".to_vec()]);
+        let mut out = Vec::new();
+        stats.write_stats(&mut out, "Reference", 5).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("  GC content: 46.15385%"));
+        assert!(text.contains("  Mean length: 6.50000"));
+    }
+
+    #[test]
+    fn test_cumulative_lengths() {
+        let stats = GenomicStats::new(&[vec![b'A'; 5], vec![b'C'; 20], vec![b'G'; 10]]);
+        let curve = stats.cumulative_lengths();
+        assert_eq!(curve, vec![(1, 20), (2, 30), (3, 35)]);
+        assert_eq!(curve.last().unwrap().1, stats.total_length);
+
+        let mut out = Vec::new();
+        stats.write_cumulative(&mut out, "Reference").unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().next(), Some("Reference\t1\t20"));
+
+        assert!(GenomicStats::new(&[]).cumulative_lengths().is_empty());
+    }
+
+    #[test]
+    fn test_match_length_histogram() {
+        let matches: Vec<Match> = [20, 25, 31, 32, 100, 40]
+            .iter()
+            .map(|&len| Match::new(0, 0, len))
+            .collect();
+        let bins = match_length_histogram(&matches);
+        assert_eq!(bins, vec![
+            LengthBin { min_len: 16, max_len: 32, count: 3 },
+            LengthBin { min_len: 32, max_len: 64, count: 2 },
+            LengthBin { min_len: 64, max_len: 128, count: 1 },
+        ]);
+        assert!(match_length_histogram(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_diagonal_histogram_single_dominant_bin() {
+        // Collinear matches on diagonal 100 and one short stray match
+        let matches = vec![Match::new(100, 0, 30), Match::new(140, 40, 25), Match::new(210, 110, 40), Match::new(5, 90, 3)];
+        let bin_size = NonZeroUsize::new(10).unwrap();
+        let bins = diagonal_histogram(&matches, 150, bin_size);
+        assert_eq!(bins, vec![(Strand::Forward, -90, 3), (Strand::Forward, 100, 95)]);
+        assert_eq!(diagonal_histogram(&matches[..3], 150, bin_size), vec![(Strand::Forward, 100, 95)]);
+
+        let mut out = Vec::new();
+        write_diagonal_histogram(&mut out, "q", &bins[1..]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("Diagonal histogram for q:\n  +        100        95  {}\n", "#".repeat(50)));
+    }
+
+    #[test]
+    fn test_diagonal_histogram_bins_reverse_matches_per_strand() {
+        // Collinear reverse matches of a 150-base query: along the reverse complement of the
+        // query both lie on diagonal 100
+        let matches = vec![
+            Match::new(100, 120, 30).with_strand(Strand::Reverse),
+            Match::new(140, 85, 25).with_strand(Strand::Reverse),
+            Match::new(140, 0, 25),
+        ];
+        let bins = diagonal_histogram(&matches, 150, NonZeroUsize::new(10).unwrap());
+        assert_eq!(bins, vec![(Strand::Forward, 140, 25), (Strand::Reverse, 100, 55)]);
+
+        // The same matches counted along the reverse complement of the query
+        let rc: Vec<Match> = matches[..2]
+            .iter()
+            .map(|m| Match { query_pos: 150 - m.query_pos - m.len, ..m.clone() }.with_query_coords(crate::QueryCoords::ReverseComplement))
+            .collect();
+        assert_eq!(diagonal_histogram(&rc, 150, NonZeroUsize::new(10).unwrap()), vec![(Strand::Reverse, 100, 55)]);
+    }
+
+    #[test]
+    fn test_mostly_reverse_query() {
+        use crate::{NucmerAligner, NucmerOptions};
+
+        let reference = b"GATTACAGGCTTAGCATCGAACTGGTCCA";
+        // A reverse complemented piece of the reference followed by a short forward piece
+        let query = b"GGGCTAAGCCTGCCCCCCCCTGGTCCA";
+        let options = NucmerOptions { min_len: 8, ..NucmerOptions::default() };
+        let matches = NucmerAligner::new(reference, options).unwrap().align(query);
+        let stats = orientation_stats(&matches);
+        assert_eq!(stats, OrientationStats { forward_count: 1, forward_len: 8, reverse_count: 1, reverse_len: 10 });
+        assert!(stats.mostly_reverse());
+
+        let mut out = Vec::new();
+        write_orientation_stats(&mut out, "q", &stats).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Orientation for q: forward 1 matches (8 bp), reverse 1 matches (10 bp), mostly reverse\n"
+        );
+    }
+
+    #[test]
+    fn test_swap_warning() {
+        let warning = swap_warning(&[100], &[1_000_000]).unwrap();
+        assert!(warning.contains("10000x longer"), "{}", warning);
+        assert!(swap_warning(&[1_000_000], &[100]).is_none());
+        assert!(swap_warning(&[5_000_000, 200_000], &[150; 10_000]).is_none());
+
+        // Many short reads as the reference of one longer contig
+        let warning = swap_warning(&[150; 20], &[2_000]).unwrap();
+        assert!(warning.contains("20 records"), "{}", warning);
+        assert!(swap_warning(&[], &[100]).is_none());
+    }
+
+    #[test]
+    fn test_filter_records_by_length() {
+        let short = ("short".to_string(), b"ACGTTGCA".repeat(7)[..50].to_vec());
+        let long = ("long".to_string(), b"GATTACAC".repeat(63)[..500].to_vec());
+        let (kept, skipped) = filter_records_by_length(vec![short, long.clone()], 200);
+        assert_eq!(skipped, 1);
+        assert_eq!(kept, vec![long.clone()]);
+
+        let aligner = crate::NucmerAligner::new(&long.1, crate::NucmerOptions::default()).unwrap();
+        let aligned: Vec<&str> = kept
+            .iter()
+            .filter(|(_, sequence)| !aligner.align(sequence).is_empty())
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(aligned, vec!["long"]);
+    }
+
+    #[test]
+    fn test_filter_records_by_gc() {
+        let at_rich = ("contaminant".to_string(), b"ATTAAATTAT".repeat(30));
+        let balanced = ("contig".to_string(), b"GATTACAGCC".repeat(8).iter().chain(b"TTGACGATCA".repeat(22).iter()).copied().collect());
+        let (kept, skipped) = filter_records_by_gc(vec![at_rich, balanced.clone()], 30.0, 70.0);
+        assert_eq!(skipped, 1);
+        assert_eq!(kept, vec![balanced.clone()]);
+
+        let aligner = crate::NucmerAligner::new(&balanced.1, crate::NucmerOptions::default()).unwrap();
+        assert!(!aligner.align(&kept[0].1).is_empty());
+    }
+
+    #[test]
+    fn test_parse_fasta_reader() {
+        let input = std::io::Cursor::new(">chr1 first\nACGT\nacgn\n>chr2\nTTxA\n");
+        let records = parse_fasta_reader(input).unwrap();
+        assert_eq!(records, vec![
+            ("chr1".to_string(), b"ACGTACGN".to_vec()),
+            ("chr2".to_string(), b"TTNA".to_vec()),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_fastq_bytes() {
+        let input = b"@read1 lane 1\nACGT\nacgn\n+\nIIII\n@III\n@read2\nTTA\n+read2\n#5@\n";
+        assert!(is_fastq(input));
+        assert!(!is_fastq(b">chr1\nACGT\n"));
+        assert_eq!(parse_fastq_bytes(input), vec![
+            ("read1".to_string(), b"ACGTACGN".to_vec(), b"IIII@III".to_vec()),
+            ("read2".to_string(), b"TTA".to_vec(), b"#5@".to_vec()),
+        ]);
+    }
+
+    #[test]
+    fn test_on_invalid_base_policies() {
+        let input = b">chr1\nACG1T\n";
+        assert_eq!(parse_fasta_bytes(input), vec![("chr1".to_string(), b"ACGNT".to_vec())]);
+        let (skip, error) = (OnInvalidBase::Skip, OnInvalidBase::Error);
+        assert_eq!(
+            parse_fasta_bytes_with(input, BaseOptions { on_invalid: skip, ..BaseOptions::default() }).unwrap(),
+            vec![("chr1".to_string(), b"ACGT".to_vec())]
+        );
+        let message = parse_fasta_bytes_with(input, BaseOptions { on_invalid: error, ..BaseOptions::default() }).unwrap_err();
+        assert_eq!(message, "invalid base '1' in record 'chr1' at position 4");
+
+        let fastq = parse_fastq_bytes_with(b"@r\nAC1T\n+\nABCD\n", BaseOptions { on_invalid: skip, ..BaseOptions::default() }).unwrap();
+        assert_eq!(fastq, vec![("r".to_string(), b"ACT".to_vec(), b"ABD".to_vec())]);
+        assert!(parse_fastq_bytes_with(b"@r\nAC1T\n+\nABCD\n", BaseOptions { on_invalid: error, ..BaseOptions::default() }).is_err());
+    }
+
+    #[test]
+    fn test_ambiguity_codes_kept_only_on_request() {
+        let input = b">c\nACRyT\n";
+        assert_eq!(parse_fasta_bytes(input), vec![("c".to_string(), b"ACNNT".to_vec())]);
+        // Ambiguity codes are bases, not invalid bytes, whether or not they are kept
+        let strict = BaseOptions { on_invalid: OnInvalidBase::Error, ..BaseOptions::default() };
+        assert_eq!(parse_fasta_bytes_with(input, strict).unwrap(), vec![("c".to_string(), b"ACNNT".to_vec())]);
+        let iupac = BaseOptions { keep_ambiguity_codes: true, ..strict };
+        assert_eq!(parse_fasta_bytes_with(input, iupac).unwrap(), vec![("c".to_string(), b"ACRYT".to_vec())]);
+    }
+
+    #[test]
+    fn test_parse_fasta_mmap_matches_parse_fasta() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test_ref.fa");
+        let mapped: Vec<Vec<u8>> = parse_fasta_mmap(path)
+            .unwrap()
+            .into_iter()
+            .map(|record| record.sequence)
+            .filter(|sequence| !sequence.is_empty())
+            .collect();
+        assert_eq!(mapped, parse_fasta(path));
+        assert!(!mapped.is_empty());
+    }
+
+    #[test]
+    fn test_parse_fasta_strips_internal_whitespace() {
+        let records = parse_fasta_bytes(b">seq\nAC GT\tac \n  ggtt\n");
+        assert_eq!(records, vec![("seq".to_string(), b"ACGTACGGTT".to_vec())]);
+    }
+
+    #[test]
+    fn test_parse_fasta_crlf() {
+        let records = parse_fasta_bytes(b">chr1 desc\r\nACGT\r\nTTGA\r\n>chr2\r\nGG\r\r\n");
+        assert_eq!(records, vec![
+            ("chr1".to_string(), b"ACGTTTGA".to_vec()),
+            ("chr2".to_string(), b"GG".to_vec()),
+        ]);
+        assert!(records.iter().all(|(_, sequence)| !sequence.contains(&b'N')));
+
+        // Carriage returns alone also end lines
+        let records = parse_fasta_bytes(b">chr1\rACGT\rTTGA\r>chr2\rGG\r");
+        assert_eq!(records, vec![
+            ("chr1".to_string(), b"ACGTTTGA".to_vec()),
+            ("chr2".to_string(), b"GG".to_vec()),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_fasta_trailing_header_and_no_newline() {
+        let records = parse_fasta_bytes(b">chr1\nACGT\n>empty");
+        assert_eq!(records, vec![
+            ("chr1".to_string(), b"ACGT".to_vec()),
+            ("empty".to_string(), Vec::new()),
+        ]);
+
+        let records = parse_fasta_bytes(b">chr1\nACGT");
+        assert_eq!(records, vec![("chr1".to_string(), b"ACGT".to_vec())]);
+    }
 }
@@ -4,6 +4,21 @@ pub mod algorithms;
 pub mod nucmer;
 pub mod genomic_stats;
 pub mod output_format;
+pub mod repeats;
+pub mod chain;
+pub mod contigs;
+pub mod twobit;
+pub mod alignment;
+pub mod structural;
+pub mod circular;
+pub mod error;
+pub mod bench;
+pub mod multi_reference;
+pub mod paf;
+#[cfg(feature = "serialize")]
+pub mod cache;
+#[cfg(feature = "bam")]
+pub mod bam;
 
 pub use sequence::*;
 pub use suffix_array::*;
@@ -11,3 +26,18 @@ pub use algorithms::*;
 pub use nucmer::*;
 pub use genomic_stats::*;
 pub use output_format::*;
+pub use repeats::*;
+pub use chain::*;
+pub use contigs::*;
+pub use twobit::*;
+pub use alignment::*;
+pub use structural::*;
+pub use circular::*;
+pub use error::*;
+pub use bench::*;
+pub use multi_reference::*;
+pub use paf::*;
+#[cfg(feature = "serialize")]
+pub use cache::*;
+#[cfg(feature = "bam")]
+pub use bam::*;
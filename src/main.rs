@@ -1,25 +1,56 @@
 //! Rust implementation of HelixAlign - a versatile alignment tool for DNA and protein sequences.
 //! This is a command-line tool for finding maximal matches between sequences.
 
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::env;
-use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-use helixalign::{SparseSuffixArray, run_mummer_algorithm, MatchType, NucmerAligner, NucmerOptions, Match, parse_fasta, GenomicStats, align_multiple_sequences_parallel, OutputFormat, print_matches_in_format};
+use helixalign::{SparseSuffixArray, run_mummer_algorithm_sampled, Match, MatchType, NucmerOptions, GenomicStats, NucmerAligner, Alignment, OutputFormat, OutputOptions, ReverseCoords, DnaSequence, MatchPrinter, best_chain_with, ChainOptions, print_best_path, print_gap_report, ContigMap, read_sequence_records_with_quality, QualityRecord, OnInvalidBase, BaseOptions, group_matches_by_reference, extract_region, write_fasta, is_placeholder_query, match_length_histogram, write_length_histogram, orientation_stats, write_orientation_stats, diagonal_histogram, write_diagonal_histogram, filter_records_by_length, filter_records_by_gc, match_density, write_density, estimate_min_len, low_complexity_mask, remove_low_complexity_matches, remove_near_diagonal_matches, DUST_WINDOW, DUST_THRESHOLD, write_sam_header, DEFAULT_REF_NAME, write_paired_sam, Mate, structural_variants, write_structural_variants, write_structural_variants_header, DEFAULT_MIN_SV_SIZE, best_contig, write_best_contigs, Index, CircularIndex, circular_sequence, fold_circular_matches, first_match_per_record, best_matches_per_record, best_alignments_per_record, find_orfs, DEFAULT_MIN_ORF_LEN, Strand, run_bench, write_bench_report, ReferenceSet, write_reference_hits, print_buffered, DEFAULT_OUTPUT_BUFFER_SIZE, open_paf, PafRecord, write_paf_coords, write_paf_bed, write_matches_in_format, write_alignments_maf, write_buffered, swap_warning, align_contigs, group_repeat_families, write_repeat_families, write_repeat_families_header, with_thread_pool, OUTPUT_FORMAT_NAMES};
+use rayon::prelude::*;
 
-fn main() {
+fn main() -> ExitCode {
     let args: Vec<String> = env::args().collect();
     let program_name = &args[0];
     
     // Check if running as nucmer by program name
-    if program_name.contains("nucmer") {
-        run_nucmer(args);
-        return;
-    }
+    let result = if program_name.contains("nucmer") {
+        run_nucmer(args)
+    } else if args.get(1).map(String::as_str) == Some("extract") {
+        run_extract(&args)
+    } else if args.get(1).map(String::as_str) == Some("--complement-only") {
+        run_complement(&args)
+    } else if args.get(1).map(String::as_str) == Some("--translate") {
+        run_translate(&args)
+    } else if args.get(1).map(String::as_str) == Some("--bench") {
+        run_benchmark(&args)
+    } else if args.get(1).map(String::as_str) == Some("--best-reference") {
+        run_best_reference(&args)
+    } else if args.get(1).map(String::as_str) == Some("--from-paf") {
+        run_from_paf(&args)
+    } else {
+        // Otherwise run standard mummer functionality
+        run_mummer(args)
+    };
     
-    // Otherwise run standard mummer functionality
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_mummer(args: Vec<String>) -> Result<(), String> {
     if args.len() < 3 {
         print_usage(&args[0]);
-        return;
+        return Err("Missing reference or query file".to_string());
     }
     
     // Parse command line arguments
@@ -28,8 +59,38 @@ fn main() {
     let mut reference_file = "";
     let mut query_files = Vec::new();
     let mut show_stats = false;
+    let mut show_cumulative = false;
     let mut num_threads: Option<usize> = None;
     let mut output_format = OutputFormat::Default;
+    let mut output_options = OutputOptions::default();
+    let mut ref_name: Option<String> = None;
+    let mut best_path = false;
+    let mut chain_options = ChainOptions::default();
+    let mut report_gaps = false;
+    let mut min_called_fraction = 0.0;
+    let mut keep_redundant = false;
+    let mut seed_step = 1;
+    let mut circular = false;
+    let mut length_histogram = false;
+    let mut report_orientation = false;
+    let mut diagonal_bin: Option<NonZeroUsize> = None;
+    let mut min_query_len = 0;
+    let mut base_options = BaseOptions::default();
+    let mut min_gc = 0.0;
+    let mut max_gc = 100.0;
+    let mut density_window: Option<NonZeroUsize> = None;
+    let mut report_sv = false;
+    let mut group_repeats = false;
+    let mut report_best_contig = false;
+    let mut auto_min_len = false;
+    let mut mask_low_complexity = false;
+    let mut report_time = false;
+    let mut max_index_mem: Option<usize> = None;
+    let mut split_on_n: Option<usize> = None;
+    let mut group_by_ref = false;
+    let mut first_only = false;
+    let mut max_per_query: Option<usize> = None;
+    let mut diagonal_mask: Option<usize> = None;
     
     let mut i = 1;
     while i < args.len() {
@@ -37,37 +98,221 @@ fn main() {
             "-mum" => algorithm = MatchType::MUM,
             "-mumreference" | "-mumcand" => algorithm = MatchType::MAM,  // Same as MAM
             "-maxmatch" => algorithm = MatchType::MEM,
+            "-mumi" => algorithm = MatchType::MUMi,
             "-l" => {
                 if i + 1 < args.len() {
-                    min_len = args[i + 1].parse().expect("Invalid minimum length");
+                    min_len = parse_arg(&args[i + 1], "Invalid minimum length")?;
                     i += 1;
                 } else {
-                    eprintln!("Error: -l requires a value");
-                    return;
+                    return Err("-l requires a value".to_string());
                 }
             }
             "-t" | "--threads" => {
                 if i + 1 < args.len() {
-                    num_threads = Some(args[i + 1].parse().expect("Invalid thread count"));
+                    num_threads = Some(parse_arg(&args[i + 1], "Invalid thread count")?);
                     i += 1;
                 } else {
-                    eprintln!("Error: -t requires a value");
-                    return;
+                    return Err("-t requires a value".to_string());
                 }
             }
             "-f" | "--format" => {
                 if i + 1 < args.len() {
-                    output_format = OutputFormat::from_str(&args[i + 1]).unwrap_or(OutputFormat::Default);
+                    output_format = args[i + 1].parse()?;
+                    i += 1;
+                } else {
+                    return Err(format!("-f requires a format ({})", OUTPUT_FORMAT_NAMES));
+                }
+            }
+            "--delimiter" => {
+                if i + 1 < args.len() {
+                    output_options.delimiter = OutputOptions::parse_delimiter(&args[i + 1]).ok_or("Invalid delimiter")?;
+                    i += 1;
+                } else {
+                    return Err("--delimiter requires a value".to_string());
+                }
+            }
+            "--paf-tags" => output_options.paf_tags = true,
+            "--evalue" => output_options.evalue = true,
+            "--score" => output_options.score = true,
+            "--ref-name" => {
+                if i + 1 < args.len() {
+                    ref_name = Some(args[i + 1].clone());
+                    i += 1;
+                } else {
+                    return Err("--ref-name requires a value".to_string());
+                }
+            }
+            "--raw-reverse-coords" => output_options.raw_reverse_coords = true,
+            "--reverse-coords" => {
+                if i + 1 < args.len() {
+                    output_options.reverse_coords = ReverseCoords::parse(&args[i + 1])
+                        .ok_or("Invalid reverse coordinate order, expected ascending or descending")?;
+                    i += 1;
+                } else {
+                    return Err("--reverse-coords requires a value".to_string());
+                }
+            }
+            "--buffer-size" => {
+                if i + 1 < args.len() {
+                    output_options.buffer_size = parse_arg(&args[i + 1], "Invalid buffer size")?;
+                    i += 1;
+                } else {
+                    return Err("--buffer-size requires a value".to_string());
+                }
+            }
+            "--precision" => {
+                if i + 1 < args.len() {
+                    output_options.precision = Some(parse_arg(&args[i + 1], "Invalid precision")?);
+                    i += 1;
+                } else {
+                    return Err("--precision requires a value".to_string());
+                }
+            }
+            "--best-path" => best_path = true,
+            "--report-gaps" => report_gaps = true,
+            "--max-query-gap" => {
+                if i + 1 < args.len() {
+                    chain_options.max_query_gap = Some(parse_arg(&args[i + 1], "Invalid max query gap")?);
+                    i += 1;
+                } else {
+                    return Err("--max-query-gap requires a value".to_string());
+                }
+            }
+            "--gap-open" => {
+                if i + 1 < args.len() {
+                    chain_options.gap_costs.gap_open = parse_arg(&args[i + 1], "Invalid gap open cost")?;
+                    i += 1;
+                } else {
+                    return Err("--gap-open requires a value".to_string());
+                }
+            }
+            "--gap-extend" => {
+                if i + 1 < args.len() {
+                    chain_options.gap_costs.gap_extend = parse_arg(&args[i + 1], "Invalid gap extend cost")?;
+                    i += 1;
+                } else {
+                    return Err("--gap-extend requires a value".to_string());
+                }
+            }
+            "--keep-nested" => keep_redundant = true,
+            "--circular" => circular = true,
+            "--seed-step" => {
+                if i + 1 < args.len() {
+                    seed_step = parse_arg(&args[i + 1], "Invalid seed step")?;
+                    if seed_step == 0 {
+                        return Err("--seed-step must be positive".to_string());
+                    }
+                    i += 1;
+                } else {
+                    return Err("--seed-step requires a value".to_string());
+                }
+            }
+            "--match-length-histogram" => length_histogram = true,
+            "--orientation-stats" => report_orientation = true,
+            "--diag-hist" => {
+                if i + 1 < args.len() {
+                    let bin_size: usize = parse_arg(&args[i + 1], "Invalid diagonal bin size")?;
+                    diagonal_bin = Some(NonZeroUsize::new(bin_size).ok_or("--diag-hist bin size must be positive")?);
+                    i += 1;
+                } else {
+                    return Err("--diag-hist requires a bin size".to_string());
+                }
+            }
+            "--auto-minlen" => auto_min_len = true,
+            "--mask-low-complexity" => mask_low_complexity = true,
+            "--report-time" => report_time = true,
+            "--max-index-mem" => {
+                if i + 1 < args.len() {
+                    max_index_mem = Some(parse_arg(&args[i + 1], "Invalid index memory budget")?);
+                    i += 1;
+                } else {
+                    return Err("--max-index-mem requires a value".to_string());
+                }
+            }
+            "--split-on-n" => {
+                if i + 1 < args.len() {
+                    split_on_n = Some(parse_arg(&args[i + 1], "Invalid N run length")?);
+                    i += 1;
+                } else {
+                    return Err("--split-on-n requires a value".to_string());
+                }
+            }
+            "--paired" => {
+                // Pairing needs clustered, scored alignments, which only nucmer mode produces
+                return Err("--paired is only supported in nucmer mode".to_string());
+            }
+            "--sv" => report_sv = true,
+            "--group-repeats" => group_repeats = true,
+            "--best-contig" => report_best_contig = true,
+            "--density" => {
+                if i + 1 < args.len() {
+                    let window: usize = parse_arg(&args[i + 1], "Invalid density window")?;
+                    density_window = Some(NonZeroUsize::new(window).ok_or("--density window must be positive")?);
+                    i += 1;
+                } else {
+                    return Err("--density requires a value".to_string());
+                }
+            }
+            "--strict-bases" => base_options.on_invalid = OnInvalidBase::Error,
+            "--min-gc" => {
+                if i + 1 < args.len() {
+                    min_gc = parse_arg(&args[i + 1], "Invalid minimum GC percentage")?;
+                    i += 1;
+                } else {
+                    return Err("--min-gc requires a value".to_string());
+                }
+            }
+            "--max-gc" => {
+                if i + 1 < args.len() {
+                    max_gc = parse_arg(&args[i + 1], "Invalid maximum GC percentage")?;
+                    i += 1;
+                } else {
+                    return Err("--max-gc requires a value".to_string());
+                }
+            }
+            "--min-query-len" => {
+                if i + 1 < args.len() {
+                    min_query_len = parse_arg(&args[i + 1], "Invalid minimum query length")?;
+                    i += 1;
+                } else {
+                    return Err("--min-query-len requires a value".to_string());
+                }
+            }
+            "--min-called-fraction" => {
+                if i + 1 < args.len() {
+                    min_called_fraction = parse_arg(&args[i + 1], "Invalid called base fraction")?;
                     i += 1;
                 } else {
-                    eprintln!("Error: -f requires a format (delta, paf, sam)");
-                    return;
+                    return Err("--min-called-fraction requires a value".to_string());
                 }
             }
+            "--group-by-ref" => group_by_ref = true,
+            "--first-only" => first_only = true,
+            "--max-per-query" => {
+                if i + 1 < args.len() {
+                    let max: usize = parse_arg(&args[i + 1], "Invalid maximum alignments per query")?;
+                    if max == 0 {
+                        return Err("--max-per-query must be positive".to_string());
+                    }
+                    max_per_query = Some(max);
+                    i += 1;
+                } else {
+                    return Err("--max-per-query requires a value".to_string());
+                }
+            }
+            "--diagonal-mask" => {
+                if i + 1 < args.len() {
+                    diagonal_mask = Some(parse_arg(&args[i + 1], "Invalid diagonal mask width")?);
+                    i += 1;
+                } else {
+                    return Err("--diagonal-mask requires a value".to_string());
+                }
+            }
+            "--cumulative" => show_cumulative = true,
             "-stats" | "--stats" => {
                 show_stats = true;
             }
-            arg if !arg.starts_with('-') => {
+            arg if arg == "-" || !arg.starts_with('-') => {
                 if reference_file.is_empty() {
                     reference_file = arg;
                 } else {
@@ -75,9 +320,8 @@ fn main() {
                 }
             }
             _ => {
-                eprintln!("Unknown option: {}", args[i]);
                 print_usage(&args[0]);
-                return;
+                return Err(format!("Unknown option: {}", args[i]));
             }
         }
         i += 1;
@@ -88,55 +332,202 @@ fn main() {
         if args.len() >= 3 && !args[2].starts_with('-') {
             query_files.push(args[2].clone());
         } else {
-            eprintln!("Error: No query file provided");
             print_usage(&args[0]);
-            return;
+            return Err("No query file provided".to_string());
         }
     }
     
+    output_options.ref_name = ref_name.unwrap_or_else(|| default_ref_name(reference_file));
+    if min_gc > max_gc {
+        return Err(format!("--min-gc ({}) is above --max-gc ({})", min_gc, max_gc));
+    }
+    chain_options.validate()?;
+    if split_on_n == Some(0) {
+        return Err("--split-on-n must be positive".to_string());
+    }
+    
+    // Load every input once, so that any one of them can be read from stdin ("-")
+    let reference_records = read_records(reference_file, base_options)?;
+    let reference_seq = concatenate_records(&reference_records);
+    let (query_records, query_qualities): (Vec<_>, Vec<_>) = query_files
+        .iter()
+        .map(|f| read_query_records(f, min_query_len, (min_gc, max_gc), base_options))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .unzip();
+    warn_if_swapped(&reference_records, &query_files, &query_records);
+    
+    if show_cumulative {
+        print_cumulative_lengths(&reference_records, &query_records)?;
+    }
+    
     // Calculate and print statistics if requested
+    let stats_precision = output_options.precision.unwrap_or(2);
     if show_stats {
-        let ref_sequences = parse_fasta(reference_file);
-        let ref_stats = GenomicStats::new(&ref_sequences);
-        ref_stats.print_stats("Reference");
+        let ref_stats = GenomicStats::new(&non_empty_sequences(&reference_records));
+        ref_stats.print_stats("Reference", stats_precision).map_err(write_error)?;
         
-        for query_file in &query_files {
-            let query_sequences = parse_fasta(query_file);
-            let query_stats = GenomicStats::new(&query_sequences);
-            query_stats.print_stats("Query");
+        for records in &query_records {
+            let query_stats = GenomicStats::new(&non_empty_sequences(records));
+            query_stats.print_stats("Query", stats_precision).map_err(write_error)?;
         }
     }
     
-    // Set number of threads if specified
-    if let Some(threads) = num_threads {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(threads)
-            .build_global()
-            .ok(); // Ignore errors if global pool is already initialized
+    // A circular reference is indexed with enough of its start appended for any query record to wrap
+    let indexed_seq = if circular {
+        let overlap = query_records
+            .iter()
+            .flat_map(|records| records.iter().map(|(_, sequence)| sequence.len()))
+            .max()
+            .unwrap_or(0);
+        Cow::Owned(circular_sequence(&reference_seq, overlap))
+    } else {
+        Cow::Borrowed(&reference_seq[..])
+    };
+    
+    // Create suffix array for reference
+    let index_start = Instant::now();
+    let reference_sa = match max_index_mem {
+        Some(max_bytes) => SparseSuffixArray::with_memory_budget(&indexed_seq, max_bytes, min_len)?,
+        None => SparseSuffixArray::new(&indexed_seq, 1)?,
+    };
+    if reference_sa.sampling_rate() > 1 {
+        eprintln!("Index memory budget: sampling every {} suffixes", reference_sa.sampling_rate());
+    }
+    let circular_index = circular.then(|| CircularIndex::new(&reference_sa, reference_seq.len()));
+    let index: &(dyn Index + Sync) = match &circular_index {
+        Some(circular_index) => circular_index,
+        None => &reference_sa,
+    };
+    let index_time = index_start.elapsed();
+    if show_stats {
+        print_index_stats(&reference_sa, stats_precision)?;
     }
+    let contigs = ContigMap::from_records(&reference_records);
+    let low_complexity = mask_low_complexity.then(|| low_complexity_mask(&reference_seq, DUST_WINDOW, DUST_THRESHOLD));
+    
+    // Find every query file's matches in parallel, then print them in input order
+    let align_start = Instant::now();
+    let hits: Vec<(Option<usize>, QueryHits)> = with_thread_pool(num_threads, || {
+        query_records.par_iter().map(|records| {
+            let query_seq = concatenate_records(records);
+            
+            if is_placeholder_query(&query_seq, min_called_fraction) {
+                return (None, QueryHits::Placeholder);
+            }
+            
+            // A sparse index cannot find anything shorter than its sampling rate
+            let chosen_min_len = auto_min_len.then(|| estimate_min_len(&reference_sa, &query_seq).max(reference_sa.sampling_rate()));
+            let min_len = chosen_min_len.unwrap_or(min_len);
+            let find_contig_matches = |sequence: &[u8]| {
+                let mut matches = run_mummer_algorithm_sampled(index, sequence, algorithm.clone(), min_len, keep_redundant, seed_step);
+                if circular {
+                    matches = fold_circular_matches(matches, reference_seq.len());
+                }
+                if let Some(mask) = &low_complexity {
+                    remove_low_complexity_matches(&mut matches, mask);
+                }
+                matches
+            };
+            let find_matches = |sequence: &[u8]| {
+                let mut matches = match split_on_n {
+                    Some(min_run) => align_contigs(sequence, min_run, find_contig_matches),
+                    None => find_contig_matches(sequence),
+                };
+                if let Some(width) = diagonal_mask {
+                    remove_near_diagonal_matches(&mut matches, width);
+                }
+                matches
+            };
+            
+            if report_best_contig {
+                let assignments = records
+                    .iter()
+                    .map(|(name, sequence)| (name.as_str(), best_contig(&find_matches(sequence), &contigs)))
+                    .collect();
+                return (chosen_min_len, QueryHits::BestContigs(assignments));
+            }
+            
+            let mut matches = find_matches(&query_seq);
+            if let Some(max) = max_per_query {
+                matches = best_matches_per_record(&matches, &ContigMap::from_records(records), max);
+            }
+            if first_only {
+                matches = first_match_per_record(&matches, &ContigMap::from_records(records));
+            }
+            (chosen_min_len, QueryHits::Matches { query_seq, matches })
+        }).collect()
+    })?;
+    let align_time = align_start.elapsed();
+    let mut output_time = Duration::ZERO;
     
     // Process each query file
-    for query_file in query_files {
-        // Read reference and query sequences
-        let reference_seq = read_fasta_file(reference_file);
-        let query_seq = read_fasta_file(&query_file);
+    let mut printer = MatchPrinter::new(&output_format, &output_options);
+    // Tables from the report options print their column header above the first query's rows
+    let mut table_header = true;
+    for ((query_file, (chosen_min_len, hits)), query_qual) in query_files.iter().zip(hits).zip(&query_qualities) {
+        if let Some(chosen) = chosen_min_len {
+            eprintln!("Auto min_len for {}: {}", query_file, chosen);
+        }
+        let (query_seq, mut matches) = match hits {
+            QueryHits::Placeholder => {
+                eprintln!("Skipping query {}: too few called (non-N) bases", query_file);
+                continue;
+            }
+            QueryHits::BestContigs(assignments) => {
+                let output_start = Instant::now();
+                print_best_contigs(&assignments, &contigs)?;
+                output_time += output_start.elapsed();
+                continue;
+            }
+            QueryHits::Matches { query_seq, matches } => (query_seq, matches),
+        };
         
-        // Create suffix array for reference
-        let reference_sa = SparseSuffixArray::new(&reference_seq, 1)
-            .expect("Could not create suffix array");
+        if group_by_ref {
+            group_matches_by_reference(&mut matches, &contigs);
+        }
         
-        // Find matches - clone algorithm to avoid move error
-        let matches = run_mummer_algorithm(&reference_sa, &query_seq, algorithm.clone(), min_len);
+        if length_histogram {
+            print_length_histogram(query_file, &matches)?;
+        }
+        if report_orientation {
+            print_orientation_stats(query_file, &matches)?;
+        }
+        if let Some(bin_size) = diagonal_bin {
+            print_diagonal_histogram(query_file, &matches, query_seq.len(), bin_size)?;
+        }
         
-        // Print matches in the specified format
-        print_matches_in_format(&matches, &query_file, &output_format, &reference_seq, &query_seq);
+        // Print matches in the specified format, or only the best chain
+        let output_start = Instant::now();
+        if let Some(window) = density_window {
+            print_density(&matches, &contigs, window)?;
+        } else if report_sv {
+            print_structural_variants(query_file, &matches, NucmerOptions::default().max_gap, std::mem::take(&mut table_header))?;
+        } else if group_repeats {
+            print_repeat_families(query_file, &matches, std::mem::take(&mut table_header))?;
+        } else if best_path {
+            print_best_path(best_chain_with(&matches, &chain_options).as_ref(), query_file).map_err(write_error)?;
+        } else if report_gaps {
+            print_gap_report(best_chain_with(&matches, &chain_options).as_ref(), query_file, std::mem::take(&mut table_header)).map_err(write_error)?;
+        } else {
+            printer.print(&matches, query_file, &reference_seq, &query_seq, query_qual.as_deref())
+                .map_err(write_error)?;
+        }
+        output_time += output_start.elapsed();
     }
+    printer.finish().map_err(write_error)?;
+    
+    if report_time {
+        print_phase_times(index_time, align_time, output_time);
+    }
+    
+    Ok(())
 }
 
-fn run_nucmer(args: Vec<String>) {
+fn run_nucmer(args: Vec<String>) -> Result<(), String> {
     if args.len() < 3 {
         print_nucmer_usage(&args[0]);
-        return;
+        return Err("Missing reference or query file".to_string());
     }
     
     // Parse command line arguments for nucmer
@@ -147,9 +538,42 @@ fn run_nucmer(args: Vec<String>) {
     let mut reference_file = "";
     let mut query_files = Vec::new();
     let mut show_stats = false;
+    let mut show_cumulative = false;
     let mut num_threads: Option<usize> = None;
     let mut output_format = OutputFormat::Default;
+    let mut output_options = OutputOptions::default();
+    let mut ref_name: Option<String> = None;
+    let mut best_path = false;
+    let mut chain_options = ChainOptions::default();
+    let mut report_gaps = false;
+    let mut min_called_fraction = 0.0;
+    let mut keep_redundant = false;
+    let mut seed_step = 1;
+    let mut min_seed_anchors = 0;
+    let mut length_histogram = false;
+    let mut report_orientation = false;
+    let mut diagonal_bin: Option<NonZeroUsize> = None;
+    let mut min_query_len = 0;
+    let mut base_options = BaseOptions::default();
+    let mut min_gc = 0.0;
+    let mut max_gc = 100.0;
+    let mut density_window: Option<NonZeroUsize> = None;
+    let mut report_sv = false;
+    let mut group_repeats = false;
+    let mut report_best_contig = false;
+    let mut auto_min_len = false;
+    let mut mask_low_complexity = false;
+    let mut report_time = false;
+    let mut max_index_mem: Option<usize> = None;
+    let mut split_on_n: Option<usize> = None;
+    let mut paired: Option<(String, String)> = None;
+    let mut group_by_ref = false;
+    let mut first_only = false;
+    let mut max_per_query: Option<usize> = None;
+    let mut diagonal_mask: Option<usize> = None;
     let mut break_len = 200;
+    let mut x_drop = NucmerOptions::default().x_drop;
+    let mut iupac = false;
     let mut min_cluster = 65;
     let mut diag_diff = 5;
     let mut diag_factor = 0.12;
@@ -158,6 +582,8 @@ fn run_nucmer(args: Vec<String>) {
     let mut optimize = true;
     let mut simplify = true;
     let mut banding = false;
+    let mut quiet = false;
+    let mut progress_template: Option<String> = None;
     
     let mut i = 1;
     while i < args.len() {
@@ -165,98 +591,309 @@ fn run_nucmer(args: Vec<String>) {
             "-mum" => algorithm = MatchType::MUM,
             "-mumreference" | "-mumcand" => algorithm = MatchType::MAM,
             "-maxmatch" => algorithm = MatchType::MEM,
+            "-mumi" => algorithm = MatchType::MUMi,
             "-b" | "--breaklen" => {
                 if i + 1 < args.len() {
-                    break_len = args[i + 1].parse().expect("Invalid break length");
+                    break_len = parse_arg(&args[i + 1], "Invalid break length")?;
                     i += 1;
                 } else {
-                    eprintln!("Error: -b requires a value");
-                    return;
+                    return Err("-b requires a value".to_string());
+                }
+            }
+            "--iupac" => {
+                // Ambiguity codes are read as N unless they are to match their bases
+                iupac = true;
+                base_options.keep_ambiguity_codes = true;
+            }
+            "--xdrop" => {
+                if i + 1 < args.len() {
+                    x_drop = parse_arg(&args[i + 1], "Invalid X-drop")?;
+                    i += 1;
+                } else {
+                    return Err("--xdrop requires a value".to_string());
                 }
             }
             "-c" | "--mincluster" => {
                 if i + 1 < args.len() {
-                    min_cluster = args[i + 1].parse().expect("Invalid minimum cluster length");
+                    min_cluster = parse_arg(&args[i + 1], "Invalid minimum cluster length")?;
                     i += 1;
                 } else {
-                    eprintln!("Error: -c requires a value");
-                    return;
+                    return Err("-c requires a value".to_string());
                 }
             }
             "-D" | "--diagdiff" => {
                 if i + 1 < args.len() {
-                    diag_diff = args[i + 1].parse().expect("Invalid diagonal difference");
+                    diag_diff = parse_arg(&args[i + 1], "Invalid diagonal difference")?;
                     i += 1;
                 } else {
-                    eprintln!("Error: -D requires a value");
-                    return;
+                    return Err("-D requires a value".to_string());
                 }
             }
             "-d" | "--diagfactor" => {
                 if i + 1 < args.len() {
-                    diag_factor = args[i + 1].parse().expect("Invalid diagonal factor");
+                    diag_factor = parse_arg(&args[i + 1], "Invalid diagonal factor")?;
                     i += 1;
                 } else {
-                    eprintln!("Error: -d requires a value");
-                    return;
+                    return Err("-d requires a value".to_string());
                 }
             }
             "-noextend" => extend = false,
             "-f" | "--forward" => forward_only = true,
             "-g" | "--maxgap" => {
                 if i + 1 < args.len() {
-                    max_gap = args[i + 1].parse().expect("Invalid max gap");
+                    max_gap = parse_arg(&args[i + 1], "Invalid max gap")?;
                     i += 1;
                 } else {
-                    eprintln!("Error: -g requires a value");
-                    return;
+                    return Err("-g requires a value".to_string());
                 }
             }
             "-l" | "--minmatch" => {
                 if i + 1 < args.len() {
-                    min_len = args[i + 1].parse().expect("Invalid minimum match length");
+                    min_len = parse_arg(&args[i + 1], "Invalid minimum match length")?;
                     i += 1;
                 } else {
-                    eprintln!("Error: -l requires a value");
-                    return;
+                    return Err("-l requires a value".to_string());
                 }
             }
             "-L" | "--minalign" => {
                 // For now, we'll just parse this but not implement the functionality
                 if i + 1 < args.len() {
-                    let _min_align = args[i + 1].parse::<usize>().expect("Invalid minimum alignment length");
+                    let _min_align = parse_arg::<usize>(&args[i + 1], "Invalid minimum alignment length")?;
                     i += 1;
                 } else {
-                    eprintln!("Error: -L requires a value");
-                    return;
+                    return Err("-L requires a value".to_string());
                 }
             }
             "-nooptimize" => optimize = false,
             "-r" | "--reverse" => reverse_only = true,
             "-nosimplify" => simplify = false,
             "-banded" => banding = true,
+            "-q" | "--quiet" => quiet = true,
+            "--progress-template" => {
+                if i + 1 < args.len() {
+                    progress_template = Some(args[i + 1].clone());
+                    i += 1;
+                } else {
+                    return Err("--progress-template requires a value".to_string());
+                }
+            }
             "-t" | "--threads" => {
                 if i + 1 < args.len() {
-                    num_threads = Some(args[i + 1].parse().expect("Invalid thread count"));
+                    num_threads = Some(parse_arg(&args[i + 1], "Invalid thread count")?);
                     i += 1;
                 } else {
-                    eprintln!("Error: -t requires a value");
-                    return;
+                    return Err("-t requires a value".to_string());
                 }
             }
-            "-f" | "--format" => {
+            "--format" => {
+                if i + 1 < args.len() {
+                    output_format = args[i + 1].parse()?;
+                    i += 1;
+                } else {
+                    return Err(format!("--format requires a format ({})", OUTPUT_FORMAT_NAMES));
+                }
+            }
+            "--delimiter" => {
+                if i + 1 < args.len() {
+                    output_options.delimiter = OutputOptions::parse_delimiter(&args[i + 1]).ok_or("Invalid delimiter")?;
+                    i += 1;
+                } else {
+                    return Err("--delimiter requires a value".to_string());
+                }
+            }
+            "--paf-tags" => output_options.paf_tags = true,
+            "--evalue" => output_options.evalue = true,
+            "--score" => output_options.score = true,
+            "--ref-name" => {
+                if i + 1 < args.len() {
+                    ref_name = Some(args[i + 1].clone());
+                    i += 1;
+                } else {
+                    return Err("--ref-name requires a value".to_string());
+                }
+            }
+            "--raw-reverse-coords" | "--no-reverse-coordinate-adjustment" => output_options.raw_reverse_coords = true,
+            "--reverse-coords" => {
+                if i + 1 < args.len() {
+                    output_options.reverse_coords = ReverseCoords::parse(&args[i + 1])
+                        .ok_or("Invalid reverse coordinate order, expected ascending or descending")?;
+                    i += 1;
+                } else {
+                    return Err("--reverse-coords requires a value".to_string());
+                }
+            }
+            "--buffer-size" => {
+                if i + 1 < args.len() {
+                    output_options.buffer_size = parse_arg(&args[i + 1], "Invalid buffer size")?;
+                    i += 1;
+                } else {
+                    return Err("--buffer-size requires a value".to_string());
+                }
+            }
+            "--precision" => {
+                if i + 1 < args.len() {
+                    output_options.precision = Some(parse_arg(&args[i + 1], "Invalid precision")?);
+                    i += 1;
+                } else {
+                    return Err("--precision requires a value".to_string());
+                }
+            }
+            "--best-path" => best_path = true,
+            "--report-gaps" => report_gaps = true,
+            "--max-query-gap" => {
+                if i + 1 < args.len() {
+                    chain_options.max_query_gap = Some(parse_arg(&args[i + 1], "Invalid max query gap")?);
+                    i += 1;
+                } else {
+                    return Err("--max-query-gap requires a value".to_string());
+                }
+            }
+            "--gap-open" => {
+                if i + 1 < args.len() {
+                    chain_options.gap_costs.gap_open = parse_arg(&args[i + 1], "Invalid gap open cost")?;
+                    i += 1;
+                } else {
+                    return Err("--gap-open requires a value".to_string());
+                }
+            }
+            "--gap-extend" => {
+                if i + 1 < args.len() {
+                    chain_options.gap_costs.gap_extend = parse_arg(&args[i + 1], "Invalid gap extend cost")?;
+                    i += 1;
+                } else {
+                    return Err("--gap-extend requires a value".to_string());
+                }
+            }
+            "--keep-nested" => keep_redundant = true,
+            "--min-seed-anchors" => {
+                if i + 1 < args.len() {
+                    min_seed_anchors = parse_arg(&args[i + 1], "Invalid minimum anchor count")?;
+                    i += 1;
+                } else {
+                    return Err("--min-seed-anchors requires a value".to_string());
+                }
+            }
+            "--seed-step" => {
+                if i + 1 < args.len() {
+                    seed_step = parse_arg(&args[i + 1], "Invalid seed step")?;
+                    if seed_step == 0 {
+                        return Err("--seed-step must be positive".to_string());
+                    }
+                    i += 1;
+                } else {
+                    return Err("--seed-step requires a value".to_string());
+                }
+            }
+            "--match-length-histogram" => length_histogram = true,
+            "--orientation-stats" => report_orientation = true,
+            "--diag-hist" => {
+                if i + 1 < args.len() {
+                    let bin_size: usize = parse_arg(&args[i + 1], "Invalid diagonal bin size")?;
+                    diagonal_bin = Some(NonZeroUsize::new(bin_size).ok_or("--diag-hist bin size must be positive")?);
+                    i += 1;
+                } else {
+                    return Err("--diag-hist requires a bin size".to_string());
+                }
+            }
+            "--auto-minlen" => auto_min_len = true,
+            "--mask-low-complexity" => mask_low_complexity = true,
+            "--report-time" => report_time = true,
+            "--max-index-mem" => {
                 if i + 1 < args.len() {
-                    output_format = OutputFormat::from_str(&args[i + 1]).unwrap_or(OutputFormat::Default);
+                    max_index_mem = Some(parse_arg(&args[i + 1], "Invalid index memory budget")?);
                     i += 1;
                 } else {
-                    eprintln!("Error: -f requires a format (delta, paf, sam)");
-                    return;
+                    return Err("--max-index-mem requires a value".to_string());
                 }
             }
+            "--split-on-n" => {
+                if i + 1 < args.len() {
+                    split_on_n = Some(parse_arg(&args[i + 1], "Invalid N run length")?);
+                    i += 1;
+                } else {
+                    return Err("--split-on-n requires a value".to_string());
+                }
+            }
+            "--paired" => {
+                if i + 2 < args.len() {
+                    paired = Some((args[i + 1].clone(), args[i + 2].clone()));
+                    i += 2;
+                } else {
+                    return Err("--paired requires two files".to_string());
+                }
+            }
+            "--sv" => report_sv = true,
+            "--group-repeats" => group_repeats = true,
+            "--best-contig" => report_best_contig = true,
+            "--density" => {
+                if i + 1 < args.len() {
+                    let window: usize = parse_arg(&args[i + 1], "Invalid density window")?;
+                    density_window = Some(NonZeroUsize::new(window).ok_or("--density window must be positive")?);
+                    i += 1;
+                } else {
+                    return Err("--density requires a value".to_string());
+                }
+            }
+            "--strict-bases" => base_options.on_invalid = OnInvalidBase::Error,
+            "--min-gc" => {
+                if i + 1 < args.len() {
+                    min_gc = parse_arg(&args[i + 1], "Invalid minimum GC percentage")?;
+                    i += 1;
+                } else {
+                    return Err("--min-gc requires a value".to_string());
+                }
+            }
+            "--max-gc" => {
+                if i + 1 < args.len() {
+                    max_gc = parse_arg(&args[i + 1], "Invalid maximum GC percentage")?;
+                    i += 1;
+                } else {
+                    return Err("--max-gc requires a value".to_string());
+                }
+            }
+            "--min-query-len" => {
+                if i + 1 < args.len() {
+                    min_query_len = parse_arg(&args[i + 1], "Invalid minimum query length")?;
+                    i += 1;
+                } else {
+                    return Err("--min-query-len requires a value".to_string());
+                }
+            }
+            "--min-called-fraction" => {
+                if i + 1 < args.len() {
+                    min_called_fraction = parse_arg(&args[i + 1], "Invalid called base fraction")?;
+                    i += 1;
+                } else {
+                    return Err("--min-called-fraction requires a value".to_string());
+                }
+            }
+            "--group-by-ref" => group_by_ref = true,
+            "--first-only" => first_only = true,
+            "--max-per-query" => {
+                if i + 1 < args.len() {
+                    let max: usize = parse_arg(&args[i + 1], "Invalid maximum alignments per query")?;
+                    if max == 0 {
+                        return Err("--max-per-query must be positive".to_string());
+                    }
+                    max_per_query = Some(max);
+                    i += 1;
+                } else {
+                    return Err("--max-per-query requires a value".to_string());
+                }
+            }
+            "--diagonal-mask" => {
+                if i + 1 < args.len() {
+                    diagonal_mask = Some(parse_arg(&args[i + 1], "Invalid diagonal mask width")?);
+                    i += 1;
+                } else {
+                    return Err("--diagonal-mask requires a value".to_string());
+                }
+            }
+            "--cumulative" => show_cumulative = true,
             "-stats" | "--stats" => {
                 show_stats = true;
             }
-            arg if !arg.starts_with('-') => {
+            arg if arg == "-" || !arg.starts_with('-') => {
                 if reference_file.is_empty() {
                     reference_file = arg;
                 } else {
@@ -264,45 +901,60 @@ fn run_nucmer(args: Vec<String>) {
                 }
             }
             _ => {
-                eprintln!("Unknown option: {}", args[i]);
                 print_nucmer_usage(&args[0]);
-                return;
+                return Err(format!("Unknown option: {}", args[i]));
             }
         }
         i += 1;
     }
     
-    if query_files.is_empty() {
+    if query_files.is_empty() && paired.is_none() {
         // If no query files provided, treat the second argument as the only query file
         if args.len() >= 3 && !args[2].starts_with('-') {
             query_files.push(args[2].clone());
         } else {
-            eprintln!("Error: No query file provided");
             print_nucmer_usage(&args[0]);
-            return;
+            return Err("No query file provided".to_string());
         }
     }
     
+    output_options.ref_name = ref_name.unwrap_or_else(|| default_ref_name(reference_file));
+    if min_gc > max_gc {
+        return Err(format!("--min-gc ({}) is above --max-gc ({})", min_gc, max_gc));
+    }
+    chain_options.validate()?;
+    
+    // Load every input once, so that any one of them can be read from stdin ("-")
+    let reference_records = read_records(reference_file, base_options)?;
+    let reference_seq = concatenate_records(&reference_records);
+    let (query_records, query_qualities): (Vec<_>, Vec<_>) = query_files
+        .iter()
+        .map(|f| read_query_records(f, min_query_len, (min_gc, max_gc), base_options))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .unzip();
+    warn_if_swapped(&reference_records, &query_files, &query_records);
+    
+    if show_cumulative {
+        print_cumulative_lengths(&reference_records, &query_records)?;
+    }
+    
     // Calculate and print statistics if requested
+    let stats_precision = output_options.precision.unwrap_or(2);
     if show_stats {
-        let ref_sequences = parse_fasta(reference_file);
-        let ref_stats = GenomicStats::new(&ref_sequences);
-        ref_stats.print_stats("Reference");
+        let ref_stats = GenomicStats::new(&non_empty_sequences(&reference_records));
+        ref_stats.print_stats("Reference", stats_precision).map_err(write_error)?;
         
-        for query_file in &query_files {
-            let query_sequences = parse_fasta(query_file);
-            let query_stats = GenomicStats::new(&query_sequences);
-            query_stats.print_stats("Query");
+        for records in &query_records {
+            let query_stats = GenomicStats::new(&non_empty_sequences(records));
+            query_stats.print_stats("Query", stats_precision).map_err(write_error)?;
         }
     }
     
-    // Set number of threads if specified
-    if let Some(threads) = num_threads {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(threads)
-            .build_global()
-            .ok(); // Ignore errors if global pool is already initialized
-    }
+    let query_sequences: Vec<Vec<u8>> = query_records
+        .iter()
+        .map(|records| concatenate_records(records))
+        .collect();
     
     // Create nucmer aligner with options
     let options = NucmerOptions {
@@ -311,6 +963,8 @@ fn run_nucmer(args: Vec<String>) {
         forward_only,
         reverse_only,
         break_len,
+        x_drop,
+        iupac,
         min_cluster,
         diag_diff,
         diag_factor,
@@ -323,96 +977,701 @@ fn run_nucmer(args: Vec<String>) {
         to_seqend: !optimize,  // Inverse of optimize
         do_delta: true,      // Always true for nucmer
         do_shadows: !simplify,  // Inverse of simplify
+        case_sensitive: false,  // Inputs are upper-cased on load
+        min_called_fraction,
+        keep_redundant,
+        seed_step,
+        min_seed_anchors,
+        mask_low_complexity,
+        max_index_mem,
+        split_on_n,
+        quiet,
+        progress_template,
     };
     
-    let reference_seq = read_fasta_file(reference_file);
+    let index_start = Instant::now();
+    let mut aligner = NucmerAligner::new(&reference_seq, options)?;
+    let index_time = index_start.elapsed();
     
-    // Process all query files in parallel
-    let query_sequences: Vec<Vec<u8>> = query_files
-        .iter()
-        .map(|f| read_fasta_file(f))
-        .collect();
+    if auto_min_len {
+        // One threshold is shared by every query, so estimate it from all of them together against
+        // the aligner's own index; the separator keeps matches from running across query boundaries
+        let queries = query_sequences.join(&AUTO_MIN_LEN_SEPARATOR);
+        // A sparse index cannot find anything shorter than its sampling rate
+        let chosen = estimate_min_len(aligner.index(), &queries).max(aligner.index().sampling_rate());
+        aligner.set_min_len(chosen)?;
+        eprintln!("Auto min_len: {}", chosen);
+    }
+    
+    if let Some((first_file, second_file)) = &paired {
+        return print_paired_sam(&aligner, &reference_seq, &output_options.ref_name, (first_file, second_file), base_options);
+    }
+    
+    for (query_file, query_seq) in query_files.iter().zip(&query_sequences) {
+        if is_placeholder_query(query_seq, min_called_fraction) {
+            eprintln!("Skipping query {}: too few called (non-N) bases", query_file);
+        }
+    }
+    
+    if show_stats {
+        print_index_stats(aligner.index(), stats_precision)?;
+    }
+    
+    let contigs = ContigMap::from_records(&reference_records);
+    if report_best_contig {
+        for records in &query_records {
+            let sequences: Vec<&[u8]> = records.iter().map(|(_, sequence)| sequence.as_slice()).collect();
+            // Contigs are ranked by the DP scores of the alignments falling in them
+            let assignments: Vec<(&str, Option<usize>)> = records
+                .iter()
+                .zip(aligner.align_clusters_parallel(&sequences, num_threads)?)
+                .map(|((name, _), alignments)| {
+                    let matches: Vec<Match> = alignments.iter().map(Alignment::to_match).collect();
+                    (name.as_str(), best_contig(&matches, &contigs))
+                })
+                .collect();
+            print_best_contigs(&assignments, &contigs)?;
+        }
+        return Ok(());
+    }
     
     // Align all queries in parallel with progress bar
-    let all_matches = align_multiple_sequences_parallel(
-        &reference_seq,
-        &query_sequences,
-        options,
-        num_threads,
-    ).expect("Could not perform alignments");
+    let align_start = Instant::now();
+    // MAF shows the gapped alignment of each cluster rather than its anchors, and
+    // --max-per-query ranks the clustered alignments by their DP scores
+    let maf = matches!(output_format, OutputFormat::Maf);
+    let mut clusters = (maf || max_per_query.is_some())
+        .then(|| aligner.align_clusters_parallel(&query_sequences, num_threads))
+        .transpose()?;
+    if let (Some(max), Some(clusters)) = (max_per_query, &mut clusters) {
+        for (alignments, records) in clusters.iter_mut().zip(&query_records) {
+            *alignments = best_alignments_per_record(alignments, &ContigMap::from_records(records), max);
+        }
+    }
+    let mut all_matches = match (&clusters, max_per_query) {
+        (Some(clusters), Some(_)) => clusters.iter().map(|alignments| alignments.iter().map(Alignment::to_match).collect()).collect(),
+        _ => aligner.align_parallel(&query_sequences, num_threads)?,
+    };
+    let maf_alignments = clusters.filter(|_| maf);
+    if let Some(width) = diagonal_mask {
+        for matches in &mut all_matches {
+            remove_near_diagonal_matches(matches, width);
+        }
+    }
+    if first_only {
+        for (matches, records) in all_matches.iter_mut().zip(&query_records) {
+            *matches = first_match_per_record(matches, &ContigMap::from_records(records));
+        }
+    }
+    let align_time = align_start.elapsed();
+    
+    if group_by_ref {
+        for matches in &mut all_matches {
+            group_matches_by_reference(matches, &contigs);
+        }
+    }
+    
+    if length_histogram {
+        for (query_file, matches) in query_files.iter().zip(&all_matches) {
+            print_length_histogram(query_file, matches)?;
+        }
+    }
+    if report_orientation {
+        for (query_file, matches) in query_files.iter().zip(&all_matches) {
+            print_orientation_stats(query_file, matches)?;
+        }
+    }
+    if let Some(bin_size) = diagonal_bin {
+        for ((query_file, matches), query_seq) in query_files.iter().zip(&all_matches).zip(&query_sequences) {
+            print_diagonal_histogram(query_file, matches, query_seq.len(), bin_size)?;
+        }
+    }
     
     // Print matches for each query file in the specified format
+    let output_start = Instant::now();
+    let mut printer = MatchPrinter::new(&output_format, &output_options);
+    // Tables from the report options print their column header above the first query's rows
+    let mut table_header = true;
     for (i, matches) in all_matches.iter().enumerate() {
-        print_matches_in_format(matches, &query_files[i], &output_format, &reference_seq, &query_sequences[i]);
+        if let Some(window) = density_window {
+            print_density(matches, &contigs, window)?;
+            continue;
+        }
+        if report_sv {
+            print_structural_variants(&query_files[i], matches, max_gap, std::mem::take(&mut table_header))?;
+            continue;
+        }
+        if group_repeats {
+            print_repeat_families(&query_files[i], matches, std::mem::take(&mut table_header))?;
+            continue;
+        }
+        if best_path {
+            print_best_path(best_chain_with(matches, &ChainOptions { max_gap, ..chain_options }).as_ref(), &query_files[i]).map_err(write_error)?;
+            continue;
+        }
+        if report_gaps {
+            print_gap_report(best_chain_with(matches, &ChainOptions { max_gap, ..chain_options }).as_ref(), &query_files[i], std::mem::take(&mut table_header)).map_err(write_error)?;
+            continue;
+        }
+        if let Some(alignments) = &maf_alignments {
+            write_buffered(io::stdout().lock(), output_options.buffer_size, |out| {
+                write_alignments_maf(out, &alignments[i], &query_files[i], &reference_seq, &query_sequences[i], &output_options)
+            })
+            .and_then(|mut stdout| stdout.flush())
+            .map_err(write_error)?;
+            continue;
+        }
+        printer.print(matches, &query_files[i], &reference_seq, &query_sequences[i], query_qualities[i].as_deref())
+            .map_err(write_error)?;
+    }
+    printer.finish().map_err(write_error)?;
+    
+    if report_time {
+        print_phase_times(index_time, align_time, output_start.elapsed());
     }
+    
+    Ok(())
 }
 
-fn read_fasta_file(filename: &str) -> Vec<u8> {
-    let content = fs::read_to_string(filename)
-        .expect("Could not read file");
+fn run_extract(args: &[String]) -> Result<(), String> {
+    if args.len() != 4 {
+        eprintln!("Usage: {} extract <reference-file> <name:start-end>", args[0]);
+        eprintln!("Prints the 1-based inclusive subsequence as FASTA.");
+        return Err("extract takes a reference file and a region".to_string());
+    }
     
-    let mut sequence = Vec::new();
-    for line in content.lines() {
-        if !line.starts_with('>') {
-            sequence.extend_from_slice(line.as_bytes());
+    let records = read_records(&args[2], BaseOptions::default())?;
+    let sequence = extract_region(&records, &args[3])?;
+    print_buffered(DEFAULT_OUTPUT_BUFFER_SIZE, |out| write_fasta(out, &args[3], sequence, 60)).map_err(write_error)
+}
+
+fn run_complement(args: &[String]) -> Result<(), String> {
+    if args.len() < 3 {
+        eprintln!("Usage: {} --complement-only <file1> [file2] ...", args[0]);
+        eprintln!("Prints the complement (not reversed) of every record as FASTA.");
+        return Err("--complement-only takes at least one sequence file".to_string());
+    }
+    
+    let mut out = BufWriter::with_capacity(DEFAULT_OUTPUT_BUFFER_SIZE, io::stdout().lock());
+    for file in &args[2..] {
+        for (name, sequence) in read_records(file, BaseOptions::default())? {
+            let complement = DnaSequence { sequence, description: name.clone() }.complement();
+            write_fasta(&mut out, &name, &complement.sequence, 60).map_err(write_error)?;
+        }
+    }
+    out.flush().map_err(write_error)
+}
+
+fn run_translate(args: &[String]) -> Result<(), String> {
+    let mut min_len = DEFAULT_MIN_ORF_LEN;
+    let mut files = Vec::new();
+    let mut i = 2;
+    while i < args.len() {
+        if args[i] == "--min-orf" {
+            if i + 1 < args.len() {
+                min_len = parse_arg(&args[i + 1], "Invalid minimum ORF length")?;
+                i += 1;
+            } else {
+                return Err("--min-orf requires a value".to_string());
+            }
+        } else {
+            files.push(&args[i]);
+        }
+        i += 1;
+    }
+    if files.is_empty() {
+        eprintln!("Usage: {} --translate [--min-orf <n>] <file1> [file2] ...", args[0]);
+        eprintln!("Prints the protein of every ORF of at least n amino acids (default: {}) as FASTA.", DEFAULT_MIN_ORF_LEN);
+        return Err("--translate takes at least one sequence file".to_string());
+    }
+
+    let mut out = BufWriter::with_capacity(DEFAULT_OUTPUT_BUFFER_SIZE, io::stdout().lock());
+    for file in files {
+        for (name, sequence) in read_records(file, BaseOptions::default())? {
+            for orf in find_orfs(&sequence, min_len) {
+                let strand = if orf.strand == Strand::Forward { '+' } else { '-' };
+                let orf_name = format!("{}:{}-{}({})", name, orf.start + 1, orf.end, strand);
+                write_fasta(&mut out, &orf_name, &orf.protein, 60).map_err(write_error)?;
+            }
+        }
+    }
+    out.flush().map_err(write_error)
+}
+
+fn run_benchmark(args: &[String]) -> Result<(), String> {
+    if args.len() != 3 {
+        eprintln!("Usage: {} --bench <size>", args[0]);
+        eprintln!("Aligns a synthetic query against a synthetic reference of the given size and reports throughput.");
+        return Err("--bench takes a sequence size".to_string());
+    }
+    let size: usize = parse_arg(&args[2], "Invalid benchmark size")?;
+    let report = run_bench(size)?;
+    print_buffered(DEFAULT_OUTPUT_BUFFER_SIZE, |out| write_bench_report(out, &report)).map_err(write_error)
+}
+
+fn run_best_reference(args: &[String]) -> Result<(), String> {
+    let mut min_len = 20;
+    let mut files = Vec::new();
+    let mut i = 2;
+    while i < args.len() {
+        if args[i] == "-l" {
+            if i + 1 < args.len() {
+                min_len = parse_arg(&args[i + 1], "Invalid minimum length")?;
+                i += 1;
+            } else {
+                return Err("-l requires a value".to_string());
+            }
+        } else {
+            files.push(&args[i]);
+        }
+        i += 1;
+    }
+    if files.len() < 3 {
+        eprintln!("Usage: {} --best-reference [-l <min_len>] <query_file> <reference1> <reference2> ...", args[0]);
+        eprintln!("Reports, for every query record, the reference whose matches cover the most query bases.");
+        return Err("--best-reference takes a query file and at least two reference files".to_string());
+    }
+
+    let mut references = Vec::new();
+    for file in &files[1..] {
+        let records = read_records(file, BaseOptions::default())?;
+        references.push((default_ref_name(file), concatenate_records(&records)));
+    }
+    let reference_set = ReferenceSet::new(&references)?;
+    let queries = read_records(files[0], BaseOptions::default())?;
+    let hits: Vec<(&str, _)> = queries
+        .iter()
+        .map(|(name, sequence)| (name.as_str(), reference_set.best_reference(sequence, min_len)))
+        .collect();
+    print_buffered(DEFAULT_OUTPUT_BUFFER_SIZE, |out| write_reference_hits(out, &hits, &reference_set)).map_err(write_error)
+}
+
+fn run_from_paf(args: &[String]) -> Result<(), String> {
+    let mut output_format = None;
+    let mut reference_file = None;
+    let mut query_file = None;
+    let mut files = Vec::new();
+    let mut i = 2;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-f" | "--format" | "--reference" | "--query" if i + 1 >= args.len() => {
+                return Err(format!("{} requires a value", args[i]));
+            }
+            "-f" | "--format" => output_format = Some(args[i + 1].as_str()),
+            "--reference" => reference_file = Some(&args[i + 1]),
+            "--query" => query_file = Some(&args[i + 1]),
+            _ => {
+                files.push(&args[i]);
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+    }
+    if files.len() != 1 {
+        eprintln!("Usage: {} --from-paf [-f <format>] [--reference <file>] [--query <file>] <paf_file>", args[0]);
+        eprintln!("Re-emits the alignments of a PAF file as coords, BED or another output format.");
+        eprintln!("Sequences missing from the reference and query files are written as N.");
+        eprintln!("Gapped records, with query and target spans of different lengths, need -f coords or -f bed.");
+        return Err("--from-paf takes one PAF file".to_string());
+    }
+    let records = open_paf(files[0]).map_err(|e| format!("Could not read {}: {}", files[0], e))?;
+    let mut out = BufWriter::with_capacity(DEFAULT_OUTPUT_BUFFER_SIZE, io::stdout().lock());
+    let read_error = |e: io::Error| format!("Could not read {}: {}", files[0], e);
+
+    // Coords and BED carry both spans of a record, so they take gapped records too
+    if let Some(format @ ("coords" | "bed")) = output_format {
+        let records: Vec<PafRecord> = records.collect::<io::Result<_>>().map_err(read_error)?;
+        let written = if format == "coords" { write_paf_coords(&mut out, &records) } else { write_paf_bed(&mut out, &records) };
+        return written.and_then(|()| out.flush()).map_err(write_error);
+    }
+    let output_format: OutputFormat = output_format.unwrap_or("default").parse()?;
+
+    let references = match reference_file {
+        Some(file) => read_records(file, BaseOptions::default())?,
+        None => Vec::new(),
+    };
+    let queries = match query_file {
+        Some(file) => read_records(file, BaseOptions::default())?,
+        None => Vec::new(),
+    };
+    let (references, queries) = (sequences_by_name(&references), sequences_by_name(&queries));
+
+    let mut write_group = |group: &[PafRecord]| -> Result<(), String> {
+        let Some(first) = group.first() else { return Ok(()) };
+        let matches = group
+            .iter()
+            .map(|record| {
+                record.to_match().ok_or_else(|| {
+                    format!(
+                        "{} aligns {} query bases to {} bases of {}; gapped records need -f coords or -f bed",
+                        record.query_name,
+                        record.query_end - record.query_start,
+                        record.target_end - record.target_start,
+                        record.target_name
+                    )
+                })
+            })
+            .collect::<Result<Vec<Match>, String>>()?;
+        let options = OutputOptions { ref_name: first.target_name.clone(), ..OutputOptions::default() };
+        let reference_seq = sequence_or_placeholder(&references, &first.target_name, first.target_len);
+        let query_seq = sequence_or_placeholder(&queries, &first.query_name, first.query_len);
+        write_matches_in_format(&mut out, &matches, &first.query_name, &output_format, &reference_seq, &query_seq, None, &options)
+            .map_err(write_error)
+    };
+
+    // Consecutive records of the same query against the same target are written together
+    let mut group: Vec<PafRecord> = Vec::new();
+    for record in records {
+        let record = record.map_err(read_error)?;
+        if group.last().is_some_and(|last| (&last.query_name, &last.target_name) != (&record.query_name, &record.target_name)) {
+            write_group(&group)?;
+            group.clear();
+        }
+        group.push(record);
+    }
+    write_group(&group)?;
+    out.flush().map_err(write_error)
+}
+
+/// Byte between queries that are searched as one sequence; parsed sequences never contain it
+const AUTO_MIN_LEN_SEPARATOR: u8 = 0;
+
+// What mummer mode found for one query file; the files are searched in parallel and printed in input order
+enum QueryHits<'a> {
+    // Too few called bases to be worth searching
+    Placeholder,
+    BestContigs(Vec<(&'a str, Option<usize>)>),
+    Matches { query_seq: Vec<u8>, matches: Vec<Match> },
+}
+
+fn sequences_by_name(records: &[(String, Vec<u8>)]) -> HashMap<&str, &[u8]> {
+    records.iter().map(|(name, sequence)| (name.as_str(), sequence.as_slice())).collect()
+}
+
+// The named sequence, or `len` Ns when it was not supplied
+fn sequence_or_placeholder<'a>(sequences: &HashMap<&str, &'a [u8]>, name: &str, len: usize) -> Cow<'a, [u8]> {
+    sequences.get(name).map_or_else(|| Cow::Owned(vec![b'N'; len]), |&sequence| Cow::Borrowed(sequence))
+}
+
+/// Error message for a failed write to stdout or stderr, such as a closed pipe
+fn write_error(e: io::Error) -> String {
+    format!("Could not write output: {}", e)
+}
+
+/// Parse an option value, naming the value in the error
+fn parse_arg<T: FromStr>(value: &str, message: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} '{}'", message, value))
+}
+
+/// Reference name for output records: the file name without directories or extension
+fn default_ref_name(reference_file: &str) -> String {
+    Path::new(reference_file)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .filter(|_| reference_file != "-")
+        .unwrap_or(DEFAULT_REF_NAME)
+        .to_string()
+}
+
+/// (name, sequence) records of one input file
+type Records = Vec<(String, Vec<u8>)>;
+
+fn read_records(filename: &str, base_options: BaseOptions) -> Result<Records, String> {
+    Ok(read_records_with_quality(filename, base_options)?
+        .into_iter()
+        .map(|(name, sequence, _)| (name, sequence))
+        .collect())
+}
+
+fn read_records_with_quality(filename: &str, base_options: BaseOptions) -> Result<Vec<QualityRecord>, String> {
+    read_sequence_records_with_quality(filename, base_options).map_err(|e| format!("Could not read {}: {}", filename, e))
+}
+
+/// Print an advisory warning for each query file that looks swapped with the reference
+fn warn_if_swapped<S: AsRef<str>>(reference_records: &[(String, Vec<u8>)], query_files: &[S], query_records: &[Records]) {
+    let lengths = |records: &[(String, Vec<u8>)]| -> Vec<usize> { records.iter().map(|(_, sequence)| sequence.len()).collect() };
+    let reference_lengths = lengths(reference_records);
+    for (query_file, records) in query_files.iter().zip(query_records) {
+        if let Some(warning) = swap_warning(&reference_lengths, &lengths(records)) {
+            eprintln!("Warning: {}: {}", query_file.as_ref(), warning);
         }
     }
+}
+
+/// Read and filter the records of a query file, with the concatenated quality string of the
+/// kept records when the file is FASTQ
+fn read_query_records(query_file: &str, min_query_len: usize, (min_gc, max_gc): (f64, f64), base_options: BaseOptions) -> Result<(Records, Option<Vec<u8>>), String> {
+    let (records, qualities): (Vec<_>, Vec<_>) = read_records_with_quality(query_file, base_options)?
+        .into_iter()
+        .map(|(name, sequence, quality)| ((name, sequence), quality))
+        .unzip();
+    // Filtering keeps records in order, so the kept ones are matched back to their qualities
+    let unfiltered = (!qualities.is_empty() && qualities.iter().all(Option::is_some)).then(|| records.clone());
     
-    // Convert to uppercase and validate DNA sequence
-    for base in &mut sequence {
-        *base = match *base {
-            b'a' | b'A' => b'A',
-            b'c' | b'C' => b'C',
-            b'g' | b'G' => b'G',
-            b't' | b'T' => b'T',
-            b'n' | b'N' => b'N',
-            _ => b'N', // Default to N for non-standard bases
-        };
+    let (records, skipped) = filter_records_by_length(records, min_query_len);
+    if skipped > 0 {
+        eprintln!("Skipped {} query record(s) in {} shorter than {} bp", skipped, query_file, min_query_len);
+    }
+    let (records, skipped) = filter_records_by_gc(records, min_gc, max_gc);
+    if skipped > 0 {
+        eprintln!("Skipped {} query record(s) in {} with GC outside {}-{}%", skipped, query_file, min_gc, max_gc);
+    }
+    
+    let quality = unfiltered.map(|unfiltered| {
+        let mut kept = records.iter().peekable();
+        unfiltered
+            .iter()
+            .zip(qualities)
+            .filter(|(record, _)| kept.next_if_eq(record).is_some())
+            .flat_map(|(_, quality)| quality.unwrap_or_default())
+            .collect()
+    });
+    Ok((records, quality))
+}
+
+fn concatenate_records(records: &[(String, Vec<u8>)]) -> Vec<u8> {
+    records.iter().flat_map(|(_, sequence)| sequence.iter().copied()).collect()
+}
+
+fn non_empty_sequences(records: &[(String, Vec<u8>)]) -> Vec<Vec<u8>> {
+    records
+        .iter()
+        .filter(|(_, sequence)| !sequence.is_empty())
+        .map(|(_, sequence)| sequence.clone())
+        .collect()
+}
+
+fn print_cumulative_lengths(reference_records: &[(String, Vec<u8>)], query_records: &[Vec<(String, Vec<u8>)>]) -> Result<(), String> {
+    print_buffered(DEFAULT_OUTPUT_BUFFER_SIZE, |out| {
+        writeln!(out, "#set\trank\tcumulative_length")?;
+        GenomicStats::new(&non_empty_sequences(reference_records)).write_cumulative(out, "Reference")?;
+        for records in query_records {
+            GenomicStats::new(&non_empty_sequences(records)).write_cumulative(out, "Query")?;
+        }
+        writeln!(out)
+    })
+    .map_err(write_error)
+}
+
+fn print_length_histogram(query_file: &str, matches: &[Match]) -> Result<(), String> {
+    let stderr = io::stderr();
+    write_length_histogram(&mut stderr.lock(), query_file, &match_length_histogram(matches)).map_err(write_error)
+}
+
+fn print_orientation_stats(query_file: &str, matches: &[Match]) -> Result<(), String> {
+    let stderr = io::stderr();
+    write_orientation_stats(&mut stderr.lock(), query_file, &orientation_stats(matches)).map_err(write_error)
+}
+
+fn print_diagonal_histogram(query_file: &str, matches: &[Match], query_len: usize, bin_size: NonZeroUsize) -> Result<(), String> {
+    let stderr = io::stderr();
+    write_diagonal_histogram(&mut stderr.lock(), query_file, &diagonal_histogram(matches, query_len, bin_size)).map_err(write_error)
+}
+
+fn print_density(matches: &[Match], contigs: &ContigMap, window: NonZeroUsize) -> Result<(), String> {
+    print_buffered(DEFAULT_OUTPUT_BUFFER_SIZE, |out| write_density(out, &match_density(matches, contigs, window), contigs)).map_err(write_error)
+}
+
+fn print_best_contigs(assignments: &[(&str, Option<usize>)], contigs: &ContigMap) -> Result<(), String> {
+    print_buffered(DEFAULT_OUTPUT_BUFFER_SIZE, |out| write_best_contigs(out, assignments, contigs)).map_err(write_error)
+}
+
+fn print_structural_variants(query_file: &str, matches: &[Match], max_gap: usize, header: bool) -> Result<(), String> {
+    let variants = structural_variants(matches, max_gap, DEFAULT_MIN_SV_SIZE);
+    print_buffered(DEFAULT_OUTPUT_BUFFER_SIZE, |out| {
+        if header {
+            write_structural_variants_header(out)?;
+        }
+        write_structural_variants(out, query_file, &variants)
+    })
+    .map_err(write_error)
+}
+
+fn print_repeat_families(query_file: &str, matches: &[Match], header: bool) -> Result<(), String> {
+    let families = group_repeat_families(matches);
+    print_buffered(DEFAULT_OUTPUT_BUFFER_SIZE, |out| {
+        if header {
+            write_repeat_families_header(out)?;
+        }
+        write_repeat_families(out, query_file, &families)
+    })
+    .map_err(write_error)
+}
+
+/// Align each read pair of two mate files and write it as SAM, mapping each mate where its best-scoring alignment lies
+fn print_paired_sam(aligner: &NucmerAligner, reference_seq: &[u8], ref_name: &str, (first_file, second_file): (&str, &str), base_options: BaseOptions) -> Result<(), String> {
+    let first_records = read_records(first_file, base_options)?;
+    let second_records = read_records(second_file, base_options)?;
+    if first_records.len() != second_records.len() {
+        return Err(format!(
+            "{} has {} reads but {} has {}",
+            first_file, first_records.len(), second_file, second_records.len()
+        ));
     }
     
-    sequence
+    let mut out = BufWriter::with_capacity(DEFAULT_OUTPUT_BUFFER_SIZE, io::stdout().lock());
+    write_sam_header(&mut out, ref_name, reference_seq).map_err(write_error)?;
+    for ((first_name, first_seq), (second_name, second_seq)) in first_records.iter().zip(&second_records) {
+        // Each mate maps where its best-scoring alignment lies
+        let best_alignment = |sequence: &[u8]| aligner.align_clusters(sequence).iter().map(Alignment::to_match).max_by_key(Match::rank_score);
+        let (first_mapping, second_mapping) = (best_alignment(first_seq), best_alignment(second_seq));
+        let first = Mate { name: first_name, sequence: first_seq, mapping: first_mapping.as_ref() };
+        let second = Mate { name: second_name, sequence: second_seq, mapping: second_mapping.as_ref() };
+        write_paired_sam(&mut out, ref_name, &first, &second).map_err(write_error)?;
+    }
+    out.flush().map_err(write_error)
+}
+
+fn print_phase_times(index_time: Duration, align_time: Duration, output_time: Duration) {
+    eprintln!("Time for index construction: {:.3}s", index_time.as_secs_f64());
+    eprintln!("Time for alignment: {:.3}s", align_time.as_secs_f64());
+    eprintln!("Time for output: {:.3}s", output_time.as_secs_f64());
+}
+
+fn print_index_stats(reference_sa: &SparseSuffixArray, precision: usize) -> Result<(), String> {
+    print_buffered(DEFAULT_OUTPUT_BUFFER_SIZE, |out| {
+        writeln!(out, "Reference Index Statistics:")?;
+        writeln!(out, "  Max LCP: {}", reference_sa.max_lcp())?;
+        writeln!(out, "  Average LCP: {:.*}", precision, reference_sa.average_lcp())?;
+        writeln!(out)
+    })
+    .map_err(write_error)
 }
 
 fn print_usage(program: &str) {
-    println!("Usage: {} [options] <reference-file> <query file1> [query file2] ...", program);
-    println!("Options:");
-    println!("  -mum           compute maximal matches that are unique in both sequences");
-    println!("  -mumreference  compute maximal matches that are unique in the reference sequence (default)");
-    println!("  -mumcand       same as -mumreference");
-    println!("  -maxmatch      compute all maximal matches regardless of their uniqueness");
-    println!("  -l <n>         set the minimum length of a match (default: 20)");
-    println!("  -t, --threads <n>  number of threads to use (default: all available cores)");
-    println!("  -f, --format <format>  output format (default, delta, paf, sam)");
-    println!("  -stats         show reference and query sequence statistics (N50, N90, etc.)");
-    println!();
-    println!("Example:");
-    println!("  {} -maxmatch -l 20 -t 4 -f paf reference.fa query.fa", program);
+    eprintln!("Usage: {} [options] <reference-file> <query file1> [query file2] ...", program);
+    eprintln!("Inputs may be FASTA or .2bit; use - as a file name to read from stdin.");
+    eprintln!("Options:");
+    eprintln!("  -mum           compute maximal matches that are unique in both sequences");
+    eprintln!("  -mumreference  compute maximal matches that are unique in the reference sequence (default)");
+    eprintln!("  -mumcand       same as -mumreference");
+    eprintln!("  -maxmatch      compute all maximal matches regardless of their uniqueness");
+    eprintln!("  -mumi          compute reference-unique matches directly from suffix-array intervals (faster)");
+    eprintln!("  -l <n>         set the minimum length of a match (default: 20)");
+    eprintln!("  --keep-nested  keep maximal matches that are nested inside other matches");
+    eprintln!("  --circular     treat the reference as circular, reporting matches across its origin as one");
+    eprintln!("  --seed-step <n>  seed -maxmatch only at every n-th query position; faster, finds fewer short MEMs");
+    eprintln!("  --min-query-len <n>  skip query records shorter than n bases");
+    eprintln!("  --strict-bases     reject inputs with sequence characters other than bases and IUPAC codes");
+    eprintln!("  --min-gc <pct>     skip query records with a lower GC percentage");
+    eprintln!("  --max-gc <pct>     skip query records with a higher GC percentage");
+    eprintln!("  --mask-low-complexity  drop matches lying entirely in low-complexity (DUST) reference regions");
+    eprintln!("  --max-index-mem <bytes>  sample the suffix array sparsely if a dense one would exceed this");
+    eprintln!("  --split-on-n <n>  align the contigs between runs of at least n Ns separately, in scaffold coordinates");
+    eprintln!("  --report-time  print the time spent building the index, aligning and writing output to stderr");
+    eprintln!("  --auto-minlen  choose the minimum match length from a quick first pass (overrides -l)");
+    eprintln!("  -t, --threads <n>  number of threads to use (default: all available cores)");
+    eprintln!("  -f, --format <format>  output format (default, delta, paf, sam, maf; bam when built with the bam feature)");
+    eprintln!("  --delimiter <d>    field delimiter for tabular formats (default: tab)");
+    eprintln!("  --precision <n>    decimal places for the PAF dv tag, E-values and -stats output (default: 2 for -stats)");
+    eprintln!("  --buffer-size <n>  bytes of output to buffer between writes (default: 65536)");
+    eprintln!("  --paf-tags     append NM, dv and tp (primary/secondary) tags to PAF records");
+    eprintln!("  --evalue       add a composition-based E-value to default and PAF (ev tag) records");
+    eprintln!("  --score        add an alignment score to default records and an AS tag to PAF, SAM and BAM records");
+    eprintln!("  --ref-name <name>  reference name in PAF, SAM and MAF records (default: reference file name)");
+    eprintln!("  --reverse-coords <order>  query coordinates of reverse matches: ascending (default) or descending");
+    eprintln!("  --raw-reverse-coords      report reverse matches at their position on the reverse-complemented query");
+    eprintln!("  --group-by-ref order matches by reference contig, then by reference start");
+    eprintln!("  --first-only   keep only the leftmost-reference match of each query record");
+    eprintln!("  --max-per-query <n>  keep only the n best-scoring (longest) matches of each query record");
+    eprintln!("  --diagonal-mask <w>  for self-alignment, drop forward matches within w of the main diagonal");
+    eprintln!("  --best-path    report only the highest-scoring chain of matches per query");
+    eprintln!("  --report-gaps  list the indels between the matches of the best chain per query");
+    eprintln!("  --max-query-gap <n>  longest query gap the best chain may bridge (default: no limit beyond 90)");
+    eprintln!("  --gap-open <n>    chaining cost of opening an indel between matches (default: 0)");
+    eprintln!("  --gap-extend <n>  chaining cost of each indel base between matches (default: 1)");
+    eprintln!("  --density <w>  print match count and covered bases per w-base reference window as TSV");
+    eprintln!("  --sv           print insertions, deletions, inversions and translocations of at least 50bp as TSV");
+    eprintln!("  --group-repeats  with -maxmatch, print query intervals matching several reference positions as TSV");
+    eprintln!("  --best-contig  align each query record on its own and print the reference contig it aligns to best");
+    eprintln!("  --min-called-fraction <f>  skip queries with at most this fraction of non-N bases (default: 0)");
+    eprintln!("  --match-length-histogram  print a log-scale histogram of match lengths to stderr");
+    eprintln!("  --orientation-stats  print forward and reverse match counts and lengths to stderr");
+    eprintln!("  --diag-hist <bin>  print a histogram of match diagonals weighted by match length to stderr");
+    eprintln!("  --cumulative   print the cumulative sequence length curve (longest first) as TSV");
+    eprintln!("  -stats         show reference and query sequence statistics (N50, N90, etc.)");
+    eprintln!();
+    eprintln!("Example:");
+    eprintln!("  {} -maxmatch -l 20 -t 4 -f paf reference.fa query.fa", program);
+    eprintln!("  {} extract reference.fa chr1:100-200", program);
+    eprintln!("  {} --complement-only query.fa", program);
+    eprintln!("  {} --translate --min-orf 100 query.fa", program);
+    eprintln!("  {} --bench 1000000", program);
 }
 
 fn print_nucmer_usage(program: &str) {
-    println!("Usage: {} [options] <reference-file> <query file1> [query file2] ...", program);
-    println!("Options:");
-    println!("  -mum           compute maximal matches that are unique in both sequences");
-    println!("  -mumreference  compute maximal matches that are unique in the reference sequence (default)");
-    println!("  -mumcand       same as -mumreference");
-    println!("  -maxmatch      compute all maximal matches regardless of their uniqueness");
-    println!("  -b, --breaklen <n>      set the distance an alignment extension will attempt to extend poor scoring regions before giving up (default: 200)");
-    println!("  -c, --mincluster <n>    sets the minimum length of a cluster of matches (default: 65)");
-    println!("  -D, --diagdiff <n>      set the maximum diagonal difference between two adjacent anchors in a cluster (default: 5)");
-    println!("  -d, --diagfactor <f>    set the maximum diagonal difference between two adjacent anchors in a cluster as a differential fraction of the gap length (default: 0.12)");
-    println!("  -noextend                do not perform cluster extension step");
-    println!("  -f, --forward           use only the forward strand of the Query sequences");
-    println!("  -g, --maxgap <n>        set the maximum gap between two adjacent matches in a cluster (default: 90)");
-    println!("  -l, --minmatch <n>      set the minimum length of a single exact match (default: 20)");
-    println!("  -L, --minalign <n>      minimum length of an alignment, after clustering and extension");
-    println!("  -nooptimize              no alignment score optimization");
-    println!("  -r, --reverse           use only the reverse complement of the Query sequences");
-    println!("  -nosimplify              don't simplify alignments by removing shadowed clusters");
-    println!("  -banded                  enforce absolute banding of dynamic programming matrix based on diagdiff parameter");
-    println!("  -t, --threads <n>       number of threads to use (default: all available cores)");
-    println!("  -f, --format <format>   output format (default, delta, paf, sam)");
-    println!("  -stats                   show reference and query sequence statistics (N50, N90, etc.)");
-    println!();
-    println!("Example:");
-    println!("  {} -maxmatch -l 20 -t 4 -f sam reference.fa query.fa", program);
+    eprintln!("Usage: {} [options] <reference-file> <query file1> [query file2] ...", program);
+    eprintln!("Inputs may be FASTA or .2bit; use - as a file name to read from stdin.");
+    eprintln!("Options:");
+    eprintln!("  -mum           compute maximal matches that are unique in both sequences");
+    eprintln!("  -mumreference  compute maximal matches that are unique in the reference sequence (default)");
+    eprintln!("  -mumcand       same as -mumreference");
+    eprintln!("  -maxmatch      compute all maximal matches regardless of their uniqueness");
+    eprintln!("  -mumi          compute reference-unique matches directly from suffix-array intervals (faster)");
+    eprintln!("  -b, --breaklen <n>      set the distance an alignment extension will attempt to extend poor scoring regions before giving up (default: 200)");
+    eprintln!("  --xdrop <n>             set how far the extension score may fall below its best before giving up (default: 10)");
+    eprintln!("  --iupac                 keep IUPAC ambiguity codes (R, Y, ...), read as N by default, and let them match their bases during extension");
+    eprintln!("  -c, --mincluster <n>    sets the minimum length of a cluster of matches (default: 65)");
+    eprintln!("  -D, --diagdiff <n>      set the maximum diagonal difference between two adjacent anchors in a cluster (default: 5)");
+    eprintln!("  -d, --diagfactor <f>    set the maximum diagonal difference between two adjacent anchors in a cluster as a differential fraction of the gap length (default: 0.12)");
+    eprintln!("  -noextend                do not perform cluster extension step");
+    eprintln!("  -f, --forward           use only the forward strand of the Query sequences");
+    eprintln!("  -g, --maxgap <n>        set the maximum gap between two adjacent matches in a cluster (default: 90)");
+    eprintln!("  -l, --minmatch <n>      set the minimum length of a single exact match (default: 20)");
+    eprintln!("  --keep-nested           keep maximal matches that are nested inside other matches");
+    eprintln!("  --seed-step <n>         seed -maxmatch only at every n-th query position; faster, finds fewer short MEMs");
+    eprintln!("  --min-seed-anchors <n>  report queries with fewer than n anchors as unmapped (default: 0)");
+    eprintln!("  --min-query-len <n>     skip query records shorter than n bases");
+    eprintln!("  --strict-bases          reject inputs with sequence characters other than bases and IUPAC codes");
+    eprintln!("  --min-gc <pct>          skip query records with a lower GC percentage");
+    eprintln!("  --max-gc <pct>          skip query records with a higher GC percentage");
+    eprintln!("  --mask-low-complexity   drop matches lying entirely in low-complexity (DUST) reference regions");
+    eprintln!("  --max-index-mem <bytes> sample the suffix array sparsely if a dense one would exceed this");
+    eprintln!("  --split-on-n <n>         align the contigs between runs of at least n Ns separately, in scaffold coordinates");
+    eprintln!("  --report-time           print the time spent building the index, aligning and writing output to stderr");
+    eprintln!("  --auto-minlen           choose the minimum match length from a quick first pass (overrides -l)");
+    eprintln!("  -L, --minalign <n>      minimum length of an alignment, after clustering and extension");
+    eprintln!("  -nooptimize              no alignment score optimization");
+    eprintln!("  -r, --reverse           use only the reverse complement of the Query sequences");
+    eprintln!("  -nosimplify              don't simplify alignments by removing shadowed clusters");
+    eprintln!("  -banded                  enforce absolute banding of dynamic programming matrix based on diagdiff parameter");
+    eprintln!("  -t, --threads <n>       number of threads to use (default: all available cores)");
+    eprintln!("  -q, --quiet             do not display the progress bar");
+    eprintln!("  --progress-template <t> custom indicatif template for the progress bar");
+    eprintln!("  --format <format>       output format (default, delta, paf, sam, maf; bam when built with the bam feature)");
+    eprintln!("                          maf writes the gapped alignment of each cluster; the others its exact anchors");
+    eprintln!("  --delimiter <d>         field delimiter for tabular formats (default: tab)");
+    eprintln!("  --precision <n>         decimal places for the PAF dv tag, E-values and -stats output (default: 2 for -stats)");
+    eprintln!("  --buffer-size <n>       bytes of output to buffer between writes (default: 65536)");
+    eprintln!("  --paf-tags              append NM, dv and tp (primary/secondary) tags to PAF records");
+    eprintln!("  --evalue                add a composition-based E-value to default and PAF (ev tag) records");
+    eprintln!("  --score                 add an alignment score to default records and an AS tag to PAF, SAM and BAM records");
+    eprintln!("  --ref-name <name>       reference name in PAF, SAM and MAF records (default: reference file name)");
+    eprintln!("  --reverse-coords <order> query coordinates of reverse matches: ascending (default) or descending");
+    eprintln!("  --raw-reverse-coords    report reverse matches at their position on the reverse-complemented query");
+    eprintln!("  --no-reverse-coordinate-adjustment  same as --raw-reverse-coords; a debugging aid for checking the raw reverse hits");
+    eprintln!("  --group-by-ref           order matches by reference contig, then by reference start");
+    eprintln!("  --first-only             keep only the leftmost-reference match of each query record");
+    eprintln!("  --max-per-query <n>      keep only the n best-scoring alignments of each query record, ranked by DP score");
+    eprintln!("  --diagonal-mask <w>      for self-alignment, drop forward matches within w of the main diagonal");
+    eprintln!("  --best-path              report only the highest-scoring chain of matches per query");
+    eprintln!("  --report-gaps            list the indels between the matches of the best chain per query");
+    eprintln!("  --max-query-gap <n>      longest query gap the best chain may bridge (default: no limit beyond --maxgap)");
+    eprintln!("  --gap-open <n>           chaining cost of opening an indel between matches (default: 0)");
+    eprintln!("  --gap-extend <n>         chaining cost of each indel base between matches (default: 1)");
+    eprintln!("  --density <w>            print match count and covered bases per w-base reference window as TSV");
+    eprintln!("  --sv                     print insertions, deletions, inversions and translocations of at least 50bp as TSV");
+    eprintln!("  --group-repeats          print query intervals aligning at several reference positions as TSV");
+    eprintln!("  --best-contig            align each query record on its own and print the reference contig it aligns to best");
+    eprintln!("  --paired <r1> <r2>       align the reads of two mate files as pairs and write SAM with mate fields");
+    eprintln!("  --min-called-fraction <f> skip queries with at most this fraction of non-N bases (default: 0)");
+    eprintln!("  --match-length-histogram print a log-scale histogram of match lengths to stderr");
+    eprintln!("  --orientation-stats      print forward and reverse match counts and lengths to stderr");
+    eprintln!("  --diag-hist <bin>        print a histogram of match diagonals weighted by match length to stderr");
+    eprintln!("  --cumulative             print the cumulative sequence length curve (longest first) as TSV");
+    eprintln!("  -stats                   show reference and query sequence statistics (N50, N90, etc.)");
+    eprintln!();
+    eprintln!("Example:");
+    eprintln!("  {} -maxmatch -l 20 -t 4 --format sam reference.fa query.fa", program);
 }
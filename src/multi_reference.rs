@@ -0,0 +1,153 @@
+//! Aligning queries against several reference genomes at once to find the one each query
+//! comes from
+//!
+//! The references are concatenated into a single index, and a `ContigMap` tags every indexed
+//! position with the reference it belongs to.
+
+use std::io::{self, Write};
+
+use crate::algorithms::find_all_mems;
+use crate::contigs::ContigMap;
+use crate::error::HelixError;
+use crate::sequence::DnaSequence;
+use crate::suffix_array::SparseSuffixArray;
+
+/// Base placed after every reference in the combined index; queries never contain it, so no
+/// match can span two references
+const REFERENCE_SEPARATOR: u8 = b'$';
+
+/// The reference a query matches best
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReferenceHit {
+    pub reference: usize,
+    /// Query bases covered by at least one match in the reference, on either strand
+    pub matched_bases: usize,
+}
+
+/// Several references in one combined index
+pub struct ReferenceSet {
+    index: SparseSuffixArray,
+    references: ContigMap,
+}
+
+impl ReferenceSet {
+    /// Index (name, sequence) references; each reference may itself be several records joined
+    pub fn new(references: &[(String, Vec<u8>)]) -> Result<Self, HelixError> {
+        if references.iter().all(|(_, sequence)| sequence.is_empty()) {
+            return Err(HelixError::EmptySequence);
+        }
+        let mut combined = Vec::new();
+        let mut map = ContigMap::new();
+        for (name, sequence) in references {
+            combined.extend_from_slice(sequence);
+            combined.push(REFERENCE_SEPARATOR);
+            map.add_contig(name.clone(), sequence.len() + 1);
+        }
+        Ok(Self { index: SparseSuffixArray::new(&combined, 1)?, references: map })
+    }
+
+    pub fn len(&self) -> usize {
+        self.references.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.references.is_empty()
+    }
+
+    /// Name of the reference at `index`
+    pub fn name(&self, index: usize) -> &str {
+        self.references.name(index)
+    }
+
+    /// Query bases covered by maximal exact matches of at least `min_len` in each reference
+    ///
+    /// Both strands of the query are searched; a base counts once per reference however many
+    /// matches cover it.
+    pub fn matched_bases(&self, query: &[u8], min_len: usize) -> Vec<usize> {
+        let reverse = DnaSequence { sequence: query.to_vec(), description: String::new() }
+            .reverse_complement()
+            .sequence;
+        let mut covered: Vec<Vec<(usize, usize)>> = vec![Vec::new(); self.len()];
+        for m in find_all_mems(&self.index, query, min_len) {
+            if let Some((reference, _)) = self.references.locate(m.ref_pos) {
+                covered[reference].push((m.query_pos, m.query_pos + m.len));
+            }
+        }
+        for m in find_all_mems(&self.index, &reverse, min_len) {
+            if let Some((reference, _)) = self.references.locate(m.ref_pos) {
+                let end = query.len() - m.query_pos;
+                covered[reference].push((end - m.len, end));
+            }
+        }
+
+        covered
+            .into_iter()
+            .map(|mut intervals| {
+                intervals.sort_unstable();
+                let mut bases = 0;
+                let mut covered_end = 0;
+                for (start, end) in intervals {
+                    let start = start.max(covered_end);
+                    if end > start {
+                        bases += end - start;
+                        covered_end = end;
+                    }
+                }
+                bases
+            })
+            .collect()
+    }
+
+    /// The reference covering the most query bases; ties go to the earlier reference, and `None`
+    /// means the query matches no reference
+    pub fn best_reference(&self, query: &[u8], min_len: usize) -> Option<ReferenceHit> {
+        self.matched_bases(query, min_len)
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, bases)| bases > 0)
+            .min_by_key(|&(reference, bases)| (std::cmp::Reverse(bases), reference))
+            .map(|(reference, matched_bases)| ReferenceHit { reference, matched_bases })
+    }
+}
+
+/// Write `query reference matched_bases` TSV, with `*` and 0 for queries matching no reference
+pub fn write_reference_hits<W: Write>(
+    out: &mut W,
+    hits: &[(&str, Option<ReferenceHit>)],
+    references: &ReferenceSet,
+) -> io::Result<()> {
+    writeln!(out, "#query\treference\tmatched_bases")?;
+    for (query, hit) in hits {
+        match hit {
+            Some(hit) => writeln!(out, "{}\t{}\t{}", query, references.name(hit.reference), hit.matched_bases)?,
+            None => writeln!(out, "{}\t*\t0", query)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_reported_against_matching_reference() {
+        let references = vec![
+            ("ecoli".to_string(), b"ATGCGTACCTTAGGCAATCGGATCCAGTTACG".to_vec()),
+            ("phage".to_string(), b"TTGACCGTAAGCTAGCATGGTCACGATTCAGA".to_vec()),
+        ];
+        let set = ReferenceSet::new(&references).unwrap();
+
+        // A piece of the phage with one substitution, and the same piece reverse complemented
+        let query = b"CCGTAAGCTAGCGTGGTCACGATT";
+        assert_eq!(set.best_reference(query, 8), Some(ReferenceHit { reference: 1, matched_bases: 23 }));
+        let reverse = b"AATCGTGACCACGCTAGCTTACGG";
+        assert_eq!(set.best_reference(reverse, 8), Some(ReferenceHit { reference: 1, matched_bases: 23 }));
+        assert_eq!(set.best_reference(b"CCCCCCCCCCCC", 8), None);
+
+        let hits = [("read1", set.best_reference(query, 8)), ("read2", None)];
+        let mut out = Vec::new();
+        write_reference_hits(&mut out, &hits, &set).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "#query\treference\tmatched_bases\nread1\tphage\t23\nread2\t*\t0\n");
+    }
+}
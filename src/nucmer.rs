@@ -1,7 +1,11 @@
 use rayon::prelude::*;
-use crate::{SparseSuffixArray, run_mummer_algorithm, MatchType, Match, DnaSequence};
+use crate::{SparseSuffixArray, run_mummer_algorithm_sampled, flag_case_differences, MatchType, Match, QueryCoords, DnaSequence, iupac_matches, Strand, cluster_matches, low_complexity_mask, remove_low_complexity_matches, DUST_WINDOW, DUST_THRESHOLD, HelixError, align_contigs, global_alignment_score, global_alignment_ops, aligned_rows, Scoring, DEFAULT_IDENTITY_BAND};
+use crate::sequence::reverse_complement_preserving_case;
 use indicatif::{ProgressBar, ProgressStyle};
 
+/// Progress bar template used when no custom template is configured
+pub const DEFAULT_PROGRESS_TEMPLATE: &str = "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})";
+
 #[derive(Debug, Clone)]
 pub struct NucmerOptions {
     pub match_type: MatchType,
@@ -9,7 +13,9 @@ pub struct NucmerOptions {
     pub forward_only: bool,
     pub reverse_only: bool,
     // Additional nucmer parameters
-    pub break_len: usize,
+    pub break_len: usize, // bases an extension may go past its best score before giving up
+    pub x_drop: i32,      // score an extension may fall below its best before giving up
+    pub iupac: bool,      // let IUPAC ambiguity codes match their constituent bases during extension
     pub min_cluster: usize,
     pub diag_diff: usize,
     pub diag_factor: f64,
@@ -22,6 +28,17 @@ pub struct NucmerOptions {
     pub to_seqend: bool,
     pub do_delta: bool,
     pub do_shadows: bool,
+    pub case_sensitive: bool, // when false, case is ignored and case-only differences are flagged
+    pub min_called_fraction: f64, // queries with at most this fraction of non-N bases are skipped
+    pub keep_redundant: bool, // keep maximal matches nested inside other matches
+    pub seed_step: usize, // seed the MEM search at every n-th query position only
+    pub min_seed_anchors: usize, // queries with fewer anchors are reported unmapped, skipping clustering
+    pub mask_low_complexity: bool, // drop matches lying entirely in DUST-masked reference regions
+    pub max_index_mem: Option<usize>, // bytes the reference index may use; sparser sampling if needed
+    pub split_on_n: Option<usize>, // align the pieces of a query between N runs at least this long separately
+    // Progress reporting
+    pub quiet: bool,
+    pub progress_template: Option<String>,
 }
 
 impl Default for NucmerOptions {
@@ -32,6 +49,8 @@ impl Default for NucmerOptions {
             forward_only: false,
             reverse_only: false,
             break_len: 200,
+            x_drop: 10,
+            iupac: false,
             min_cluster: 65,
             diag_diff: 5,
             diag_factor: 0.12,
@@ -44,132 +63,823 @@ impl Default for NucmerOptions {
             to_seqend: false,
             do_delta: true,
             do_shadows: false,
+            case_sensitive: false,
+            min_called_fraction: 0.0,
+            keep_redundant: false,
+            seed_step: 1,
+            min_seed_anchors: 0,
+            mask_low_complexity: false,
+            max_index_mem: None,
+            split_on_n: None,
+            quiet: false,
+            progress_template: None,
+        }
+    }
+}
+
+impl NucmerOptions {
+    /// Reject option combinations that cannot produce meaningful alignments
+    pub fn validate(&self) -> Result<(), HelixError> {
+        let invalid = HelixError::InvalidOptions;
+        if self.forward_only && self.reverse_only {
+            return Err(invalid("forward_only and reverse_only cannot both be set".to_string()));
+        }
+        if self.min_cluster < self.min_len {
+            return Err(invalid(format!(
+                "min_cluster ({}) must be at least min_len ({})",
+                self.min_cluster, self.min_len
+            )));
+        }
+        if self.seed_step == 0 {
+            return Err(invalid("seed_step must be positive".to_string()));
+        }
+        if self.split_on_n == Some(0) {
+            return Err(invalid("split_on_n must be positive".to_string()));
+        }
+        if self.x_drop < 0 {
+            return Err(invalid(format!("x_drop must be non-negative, got {}", self.x_drop)));
+        }
+        if self.diag_factor < 0.0 || self.diag_factor.is_nan() {
+            return Err(invalid(format!("diag_factor must be non-negative, got {}", self.diag_factor)));
         }
+        if !(0.0..=1.0).contains(&self.min_called_fraction) {
+            return Err(invalid(format!(
+                "min_called_fraction must be between 0 and 1, got {}",
+                self.min_called_fraction
+            )));
+        }
+        if let Some(template) = &self.progress_template {
+            ProgressStyle::with_template(template)
+                .map_err(|e| invalid(format!("Invalid progress template: {}", e)))?;
+        }
+        Ok(())
+    }
+}
+
+/// A clustered and extended alignment between the reference and a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alignment {
+    pub ref_start: usize,   // 0-based start in the reference
+    pub ref_end: usize,     // 0-based exclusive end in the reference
+    pub query_start: usize, // 0-based start in the query (forward strand)
+    pub query_end: usize,   // 0-based exclusive end in the query (forward strand)
+    pub strand: Strand,
+    pub anchors: Vec<Match>, // anchors of the cluster, in reference order
+    pub score: i64,          // banded global alignment score of the spans, with the default `Scoring`
+}
+
+impl Alignment {
+    /// The alignment as one ungapped match from its start, ranked by its DP score
+    ///
+    /// A match has a single length, so an alignment with indels is cut to the shorter of its
+    /// reference and query spans; the match always lies within both sequences.
+    pub fn to_match(&self) -> Match {
+        let len = (self.ref_end - self.ref_start).min(self.query_end - self.query_start);
+        // On the reverse strand the reference start pairs with the end of the query span
+        let query_pos = match self.strand {
+            Strand::Forward => self.query_start,
+            Strand::Reverse => self.query_end - len,
+        };
+        Match::new(self.ref_start, query_pos, len)
+            .with_strand(self.strand)
+            .with_score(self.score)
+    }
+
+    /// Gapped rows of the alignment: its reference span and its query span on its own strand,
+    /// laid out by the same banded global alignment that gives its score
+    pub fn aligned_rows(&self, reference: &[u8], query: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let ref_span = &reference[self.ref_start..self.ref_end];
+        let query_span = match self.strand {
+            Strand::Forward => query[self.query_start..self.query_end].to_vec(),
+            Strand::Reverse => reverse_complement_preserving_case(&query[self.query_start..self.query_end]),
+        };
+        let ops = global_alignment_ops(ref_span, &query_span, &Scoring::default(), DEFAULT_IDENTITY_BAND);
+        aligned_rows(ref_span, &query_span, &ops)
+    }
+}
+
+/// Run the clustering and extension stages on precomputed anchors, skipping seeding
+///
+/// Anchors are clustered per strand with the `max_gap`, `diag_diff` and `diag_factor` options.
+/// Clusters whose anchors cover less than `min_cluster` bases are dropped, and when `extend` is
+/// set each remaining cluster is extended into its flanks with `x_drop_extension`.
+///
+/// `min_seed_anchors` is applied by `NucmerAligner::align` when the anchors are found, not here.
+pub fn align_from_anchors(anchors: Vec<Match>, reference: &[u8], query: &[u8], options: &NucmerOptions) -> Vec<Alignment> {
+    let mut alignments = Vec::new();
+    for strand in [Strand::Forward, Strand::Reverse] {
+        // Work in the coordinates of the strand the anchors were found on
+        let strand_query = match strand {
+            Strand::Forward => query.to_vec(),
+            Strand::Reverse => reverse_complement_preserving_case(query),
+        };
+        let to_strand = |m: &Match| match strand {
+            Strand::Forward => m.clone(),
+            Strand::Reverse => Match { query_pos: query.len() - m.query_pos - m.len, ..m.clone() },
+        };
+        let strand_anchors: Vec<Match> = anchors.iter().filter(|m| m.strand == strand).map(to_strand).collect();
+
+        for cluster in cluster_matches(&strand_anchors, options.max_gap, options.diag_diff, options.diag_factor) {
+            let covered: usize = cluster.iter().map(|m| m.len).sum();
+            if covered < options.min_cluster {
+                continue;
+            }
+
+            // A loose diagonal tolerance can cluster anchors out of query order, so each axis
+            // spans all of them rather than the first and last by reference position
+            let mut ref_start = cluster.iter().map(|m| m.ref_pos).min().unwrap_or(0);
+            let mut ref_end = cluster.iter().map(|m| m.ref_pos + m.len).max().unwrap_or(0);
+            let mut query_start = cluster.iter().map(|m| m.query_pos).min().unwrap_or(0);
+            let mut query_end = cluster.iter().map(|m| m.query_pos + m.len).max().unwrap_or(0);
+
+            if options.extend {
+                let left = x_drop_extension(
+                    reference[..ref_start].iter().rev().zip(strand_query[..query_start].iter().rev()),
+                    options,
+                );
+                let right = x_drop_extension(
+                    reference[ref_end..].iter().zip(&strand_query[query_end..]),
+                    options,
+                );
+                (ref_start, query_start) = (ref_start - left, query_start - left);
+                (ref_end, query_end) = (ref_end + right, query_end + right);
+            }
+
+            let score = global_alignment_score(
+                &reference[ref_start..ref_end],
+                &strand_query[query_start..query_end],
+                &Scoring::default(),
+                DEFAULT_IDENTITY_BAND,
+            );
+
+            // Report query coordinates on the forward strand
+            if strand == Strand::Reverse {
+                (query_start, query_end) = (query.len() - query_end, query.len() - query_start);
+            }
+            alignments.push(Alignment {
+                ref_start,
+                ref_end,
+                query_start,
+                query_end,
+                strand,
+                anchors: cluster.iter().map(to_strand).collect(),
+                score,
+            });
+        }
+    }
+
+    alignments
+}
+
+/// Length of the best-scoring ungapped extension over aligned (reference, query) base pairs
+///
+/// Matches score +1 and mismatches -1; with `iupac` set an ambiguity code matches any base it
+/// stands for. Extension stops once the score falls more than `x_drop` below the best seen so
+/// far, or runs `break_len` bases past it; an `x_drop` of 0 extends over matching bases only.
+fn x_drop_extension<'a>(pairs: impl Iterator<Item = (&'a u8, &'a u8)>, options: &NucmerOptions) -> usize {
+    let (mut score, mut best_score, mut best_len) = (0i64, 0i64, 0);
+    for (len, (r, q)) in pairs.enumerate() {
+        let matched = if options.iupac { iupac_matches(*r, *q) } else { r.eq_ignore_ascii_case(q) };
+        score += if matched { 1 } else { -1 };
+        if score > best_score {
+            (best_score, best_len) = (score, len + 1);
+        } else if best_score - score > options.x_drop as i64 || len + 1 - best_len > options.break_len {
+            break;
+        }
+    }
+    best_len
+}
+
+/// Fraction of a sequence made of called (non-N) bases; 0 for an empty sequence
+pub fn called_base_fraction(sequence: &[u8]) -> f64 {
+    if sequence.is_empty() {
+        return 0.0;
     }
+    let called = sequence.iter().filter(|&&base| base != b'N' && base != b'n').count();
+    called as f64 / sequence.len() as f64
+}
+
+/// Whether a query is a placeholder (e.g. all N) that cannot produce useful matches
+pub fn is_placeholder_query(query: &[u8], min_called_fraction: f64) -> bool {
+    called_base_fraction(query) <= min_called_fraction
 }
 
 pub struct NucmerAligner {
     reference_sa: SparseSuffixArray,
+    // Original reference when case folding changed it, needed to flag case-only differences
+    original_reference: Option<Vec<u8>>,
+    // Low-complexity reference bases, when masking is enabled
+    low_complexity_mask: Option<Vec<bool>>,
     options: NucmerOptions,
 }
 
 impl NucmerAligner {
-    pub fn new(reference: &[u8], options: NucmerOptions) -> Result<Self, String> {
-        let reference_sa = SparseSuffixArray::new(reference, 1)?;
+    pub fn new(reference: &[u8], options: NucmerOptions) -> Result<Self, HelixError> {
+        options.validate()?;
+        
+        let folded = if options.case_sensitive { None } else { Some(reference.to_ascii_uppercase()) };
+        let indexed = folded.as_deref().unwrap_or(reference);
+        let reference_sa = match options.max_index_mem {
+            Some(max_bytes) => SparseSuffixArray::with_memory_budget(indexed, max_bytes, options.min_len)?,
+            None => SparseSuffixArray::new(indexed, 1)?,
+        };
+        let original_reference = match folded {
+            Some(folded) if folded != reference => Some(reference.to_vec()),
+            _ => None,
+        };
+        let low_complexity_mask = options
+            .mask_low_complexity
+            .then(|| low_complexity_mask(reference_sa.sequence(), DUST_WINDOW, DUST_THRESHOLD));
         
         Ok(Self {
             reference_sa,
+            original_reference,
+            low_complexity_mask,
             options,
         })
     }
 
+    /// The suffix array of the reference
+    pub fn index(&self) -> &SparseSuffixArray {
+        &self.reference_sa
+    }
+
+    /// Change the minimum anchor length once the index is built, e.g. to one estimated against it
+    /// The options are validated again with the new length.
+    pub fn set_min_len(&mut self, min_len: usize) -> Result<(), HelixError> {
+        let options = NucmerOptions { min_len, ..self.options.clone() };
+        options.validate()?;
+        self.options = options;
+        Ok(())
+    }
+
+    /// Anchors of a query on both strands, or none when there are fewer than `min_seed_anchors`,
+    /// so that `align_clusters` does not cluster an unmapped query
     pub fn align(&self, query: &[u8]) -> Vec<Match> {
+        // Skip the suffix-array work entirely for placeholder queries
+        if is_placeholder_query(query, self.options.min_called_fraction) {
+            return Vec::new();
+        }
+
+        let mut all_matches = match self.options.split_on_n {
+            Some(min_run) => align_contigs(query, min_run, |contig| self.find_anchors(contig)),
+            None => self.find_anchors(query),
+        };
+
+        // Too few anchors on either strand: report the query unmapped
+        if all_matches.len() < self.options.min_seed_anchors {
+            all_matches.clear();
+        }
+        all_matches
+    }
+
+    // Matches of a query (or one contig of it) on both strands, in canonical coordinates
+    fn find_anchors(&self, query: &[u8]) -> Vec<Match> {
         let mut all_matches = Vec::new();
+        let original_query = query;
+        let folded_query;
+        let query = if self.options.case_sensitive {
+            query
+        } else {
+            folded_query = query.to_ascii_uppercase();
+            &folded_query[..]
+        };
+        let original_reference = self.original_reference.as_deref().unwrap_or(self.reference_sa.sequence());
 
         // Forward alignment
         if !self.options.reverse_only {
-            let forward_matches = run_mummer_algorithm(
+            let mut forward_matches = run_mummer_algorithm_sampled(
                 &self.reference_sa,
                 query,
                 self.options.match_type.clone(),
-                self.options.min_len
+                self.options.min_len,
+                self.options.keep_redundant,
+                self.options.seed_step,
             );
+            if !self.options.case_sensitive {
+                flag_case_differences(&mut forward_matches, original_reference, original_query);
+            }
+            if let Some(mask) = &self.low_complexity_mask {
+                remove_low_complexity_matches(&mut forward_matches, mask);
+            }
             all_matches.extend(forward_matches);
         }
 
         // Reverse complement alignment
         if !self.options.forward_only {
-            // Create a sequence object to use the reverse_complement method
-            let query_seq = DnaSequence::new(std::str::from_utf8(query).unwrap_or(""), "query".to_string());
-            let rev_query_seq = query_seq.reverse_complement();
-            let rev_query = rev_query_seq.sequence;
-            
-            let reverse_matches = run_mummer_algorithm(
+            // Complement ambiguity codes too (R to Y, ...), so they still match on the reverse strand
+            let rev_query = reverse_complement_preserving_case(query);
+
+            let mut reverse_matches = run_mummer_algorithm_sampled(
                 &self.reference_sa,
                 &rev_query,
                 self.options.match_type.clone(),
-                self.options.min_len
+                self.options.min_len,
+                self.options.keep_redundant,
+                self.options.seed_step,
             );
+            if !self.options.case_sensitive {
+                let original_rev_query = reverse_complement_preserving_case(original_query);
+                flag_case_differences(&mut reverse_matches, original_reference, &original_rev_query);
+            }
+            if let Some(mask) = &self.low_complexity_mask {
+                remove_low_complexity_matches(&mut reverse_matches, mask);
+            }
             
-            // Adjust reverse matches to original query coordinates
+            // Reverse matches were found on the reverse complement; report them canonically
             let adjusted_reverse_matches: Vec<Match> = reverse_matches
                 .into_iter()
-                .map(|mut m| {
-                    // Convert reverse query position back to original query position
-                    m.query_pos = query.len() - m.query_pos - m.len;
-                    m
+                .map(|m| {
+                    m.with_strand(Strand::Reverse)
+                        .with_query_coords(QueryCoords::ReverseComplement)
+                        .canonicalize(query.len())
                 })
                 .collect();
                 
             all_matches.extend(adjusted_reverse_matches);
         }
-
         all_matches
     }
 
+    /// Cluster, extend and score the anchors of a query, as `align_from_anchors` does
+    pub fn align_clusters(&self, query: &[u8]) -> Vec<Alignment> {
+        align_from_anchors(self.align(query), self.reference_sa.sequence(), query, &self.options)
+    }
+
     // Parallel version of align that processes multiple query sequences in parallel with progress bar
-    pub fn align_parallel(&self, queries: &[Vec<u8>], num_threads: Option<usize>) -> Vec<Vec<Match>> {
-        if let Some(threads) = num_threads {
-            rayon::ThreadPoolBuilder::new()
-                .num_threads(threads)
-                .build_global()
-                .ok(); // Ignore errors if global pool is already initialized
-        }
-
-        // Create progress bar
-        let pb = ProgressBar::new(queries.len() as u64);
-        pb.set_style(ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
-            .unwrap()
-            .progress_chars("#>-"));
-
-        let results: Vec<Vec<Match>> = queries
-            .par_iter()
-            .map(|query| {
-                let result = self.align(query);
-                pb.inc(1);
-                result
-            })
-            .collect();
+    pub fn align_parallel<Q: AsRef<[u8]> + Sync>(&self, queries: &[Q], num_threads: Option<usize>) -> Result<Vec<Vec<Match>>, HelixError> {
+        self.map_parallel(queries, num_threads, |query| self.align(query))
+    }
+
+    /// Parallel version of `align_clusters`
+    pub fn align_clusters_parallel<Q: AsRef<[u8]> + Sync>(&self, queries: &[Q], num_threads: Option<usize>) -> Result<Vec<Vec<Alignment>>, HelixError> {
+        self.map_parallel(queries, num_threads, |query| self.align_clusters(query))
+    }
+
+    // Run `align` on every query in parallel, with a progress bar
+    fn map_parallel<Q: AsRef<[u8]> + Sync, T: Send>(&self, queries: &[Q], num_threads: Option<usize>, align: impl Fn(&[u8]) -> T + Sync + Send) -> Result<Vec<T>, HelixError> {
+        let pb = progress_bar(queries.len(), &self.options);
+
+        let results: Vec<T> = with_thread_pool(num_threads, || {
+            queries
+                .par_iter()
+                .map(|query| {
+                    let result = align(query.as_ref());
+                    pb.inc(1);
+                    result
+                })
+                .collect()
+        })?;
 
         pb.finish_with_message("Alignment completed");
-        results
+        Ok(results)
+    }
+}
+
+// Build the progress bar shared by the parallel aligners, hidden in quiet mode
+fn progress_bar(len: usize, options: &NucmerOptions) -> ProgressBar {
+    if options.quiet {
+        return ProgressBar::hidden();
     }
+
+    let template = options.progress_template.as_deref().unwrap_or(DEFAULT_PROGRESS_TEMPLATE);
+    let style = ProgressStyle::with_template(template)
+        .unwrap_or_else(|_| ProgressStyle::default_bar())
+        .progress_chars("#>-");
+
+    let pb = ProgressBar::new(len as u64);
+    pb.set_style(style);
+    pb
 }
 
 // Function to align multiple query sequences in parallel with progress bar
-pub fn align_multiple_sequences_parallel(
+pub fn align_multiple_sequences_parallel<Q: AsRef<[u8]> + Sync>(
     reference: &[u8],
-    queries: &[Vec<u8>],
+    queries: &[Q],
     options: NucmerOptions,
     num_threads: Option<usize>,
-) -> Result<Vec<Vec<Match>>, String> {
-    if let Some(threads) = num_threads {
-        rayon::ThreadPoolBuilder::new()
-            .num_threads(threads)
-            .build_global()
-            .ok(); // Ignore errors if global pool is already initialized
-    }
-
-    let aligner = NucmerAligner::new(reference, options)?;
-    
-    // Create progress bar
-    let pb = ProgressBar::new(queries.len() as u64);
-    pb.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
-        .unwrap()
-        .progress_chars("#>-"));
-
-    let results: Vec<Vec<Match>> = queries
-        .par_iter()
-        .map(|query| {
-            let result = aligner.align(query);
-            pb.inc(1);
-            result
-        })
-        .collect();
+) -> Result<Vec<Vec<Match>>, HelixError> {
+    NucmerAligner::new(reference, options)?.align_parallel(queries, num_threads)
+}
 
-    pb.finish_with_message("Alignment completed");
-    
-    Ok(results)
+/// Align in-memory queries against an in-memory reference, without reading any files
+///
+/// Runs `align_multiple_sequences_parallel` on the current thread pool: one `NucmerAligner` is
+/// built for the reference and `align_parallel` gives the anchors of each query. The nucmer
+/// command line does not call this; it keeps its own aligner so that clustering and the exact
+/// matches share one index.
+pub fn align_sequences(reference: &DnaSequence, queries: &[DnaSequence], options: NucmerOptions) -> Result<Vec<Vec<Match>>, HelixError> {
+    align_multiple_sequences_parallel(&reference.sequence, queries, options, None)
+}
+
+/// Run `work` on a pool of its own with `num_threads` threads, or on the current pool when no
+/// count is given
+/// The global pool is left alone, so every call gets the count it asks for. A pool that cannot
+/// be started is an error rather than a silent fallback to the current pool.
+pub fn with_thread_pool<T: Send>(num_threads: Option<usize>, work: impl FnOnce() -> T + Send) -> Result<T, HelixError> {
+    match num_threads {
+        Some(threads) => {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().map_err(HelixError::ThreadPool)?;
+            Ok(pool.install(work))
+        }
+        None => Ok(work()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::synthetic_sequence;
+
+    #[test]
+    fn test_default_options_are_valid() {
+        assert!(NucmerOptions::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_forward_and_reverse_only() {
+        let options = NucmerOptions {
+            forward_only: true,
+            reverse_only: true,
+            ..NucmerOptions::default()
+        };
+        assert_eq!(
+            options.validate().unwrap_err().to_string(),
+            "forward_only and reverse_only cannot both be set"
+        );
+        assert!(matches!(NucmerAligner::new(b"ACGT", options), Err(HelixError::InvalidOptions(_))));
+        assert!(matches!(NucmerAligner::new(b"", NucmerOptions::default()), Err(HelixError::EmptySequence)));
+    }
+
+    #[test]
+    fn test_validate_min_cluster_below_min_len() {
+        let options = NucmerOptions {
+            min_len: 30,
+            min_cluster: 20,
+            ..NucmerOptions::default()
+        };
+        assert_eq!(
+            options.validate().unwrap_err().to_string(),
+            "min_cluster (20) must be at least min_len (30)"
+        );
+    }
+
+    #[test]
+    fn test_validate_progress_template() {
+        let options = NucmerOptions {
+            progress_template: Some("{pos}/{len} {bar:notacolor".to_string()),
+            ..NucmerOptions::default()
+        };
+        assert!(options.validate().unwrap_err().to_string().starts_with("Invalid progress template"));
+    }
+
+    #[test]
+    fn test_quiet_mode_hides_progress() {
+        let options = NucmerOptions {
+            quiet: true,
+            ..NucmerOptions::default()
+        };
+        assert!(progress_bar(3, &options).is_hidden());
+
+        let options = NucmerOptions {
+            quiet: true,
+            forward_only: true,
+            min_len: 4,
+            ..NucmerOptions::default()
+        };
+        let aligner = NucmerAligner::new(b"ACGTACGGTT", options).unwrap();
+        let results = aligner.align_parallel(&[b"ACGTA".to_vec()], None).unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_thread_count_is_honored_on_every_call() {
+        assert_eq!(with_thread_pool(Some(2), rayon::current_num_threads).unwrap(), 2);
+        assert_eq!(with_thread_pool(Some(3), rayon::current_num_threads).unwrap(), 3);
+
+        let options = NucmerOptions {
+            quiet: true,
+            min_len: 4,
+            ..NucmerOptions::default()
+        };
+        let aligner = NucmerAligner::new(b"ACGTACGGTTCAGT", options).unwrap();
+        let queries = vec![b"ACGTACGG".to_vec(), b"GGTTCAGT".to_vec(), b"CCCCCCCC".to_vec()];
+        let one = aligner.align_parallel(&queries, Some(1)).unwrap();
+        let four = aligner.align_parallel(&queries, Some(4)).unwrap();
+        assert_eq!(one.len(), 3);
+        assert_eq!(one, four);
+    }
+
+    #[test]
+    fn test_reverse_match_coordinates_with_and_without_adjustment() {
+        use crate::{write_matches_in_format, OutputFormat, OutputOptions};
+
+        let reference = b"GATTACAGGCTTAGCATCGAACTGG";
+        // Query bases 2..12 are the reverse complement of reference bases 5..15
+        let query = b"GGGCTAAGCCTGCCCCCCC";
+        let options = NucmerOptions {
+            min_len: 8,
+            reverse_only: true,
+            ..NucmerOptions::default()
+        };
+        let matches = NucmerAligner::new(reference, options).unwrap().align(query);
+        assert_eq!(matches, vec![Match::new(5, 2, 10).with_strand(Strand::Reverse)]);
+
+        let write = |raw_reverse_coords| {
+            let options = OutputOptions { raw_reverse_coords, ..OutputOptions::default() };
+            let mut out = Vec::new();
+            write_matches_in_format(&mut out, &matches, "q", &OutputFormat::Default, reference, query, None, &options).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        // On the reverse complement of the 19-base query the match starts at 19 - 2 - 10 = 7
+        assert_eq!(write(false), "> Query: q\n  Ref: 6  Query: 3  Len: 10  Strand: -  RC Query: 8\n");
+        assert_eq!(write(true), "> Query: q\n  Ref: 6  Query: 8  Len: 10  Strand: -  RC Query: 8\n");
+    }
+
+    #[test]
+    fn test_split_on_n_maps_contig_matches_to_scaffold() {
+        use crate::{split_on_n, synthetic_sequence};
+
+        let first = synthetic_sequence(40, 1);
+        let second = synthetic_sequence(40, 2);
+        let reference = [&first[..], &synthetic_sequence(30, 3), &second[..]].concat();
+        // A scaffold of the two contigs joined by a 100-N gap
+        let scaffold = [&first[..], &[b'N'; 100], &second[..]].concat();
+        assert_eq!(split_on_n(&scaffold, 100), vec![(0, 40), (140, 180)]);
+
+        let options = NucmerOptions {
+            min_len: 20,
+            forward_only: true,
+            split_on_n: Some(100),
+            ..NucmerOptions::default()
+        };
+        let matches = NucmerAligner::new(&reference, options).unwrap().align(&scaffold);
+        assert_eq!(matches, vec![Match::new(0, 0, 40), Match::new(70, 140, 40)]);
+
+        let invalid = NucmerOptions { split_on_n: Some(0), ..NucmerOptions::default() };
+        assert!(NucmerAligner::new(&reference, invalid).is_err());
+    }
+
+    #[test]
+    fn test_align_sequences_in_memory() {
+        let reference = DnaSequence::new("TTTTGATTACAGGCTTAGCATCCCC", "ref".to_string());
+        let queries = vec![
+            DnaSequence::new("AAGATTACAGGCTTAGAA", "q1".to_string()),
+            DnaSequence::new("GGGGGGGG", "q2".to_string()),
+        ];
+        let options = NucmerOptions { min_len: 10, forward_only: true, quiet: true, ..NucmerOptions::default() };
+        let results = align_sequences(&reference, &queries, options).unwrap();
+        assert_eq!(results, vec![vec![Match::new(4, 2, 14)], Vec::new()]);
+
+        let empty = DnaSequence::new("", "empty".to_string());
+        assert!(align_sequences(&empty, &queries, NucmerOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_case_only_difference_is_flagged() {
+        let options = NucmerOptions {
+            min_len: 4,
+            forward_only: true,
+            ..NucmerOptions::default()
+        };
+        let aligner = NucmerAligner::new(b"ATCG", options.clone()).unwrap();
+        let matches = aligner.align(b"atcg");
+        assert_eq!(matches.len(), 1);
+        assert_eq!((matches[0].ref_pos, matches[0].query_pos, matches[0].len), (0, 0, 4));
+        assert!(matches[0].case_difference);
+
+        let matches = aligner.align(b"ATCG");
+        assert!(!matches[0].case_difference);
+
+        let case_sensitive = NucmerOptions { case_sensitive: true, ..options };
+        let aligner = NucmerAligner::new(b"ATCG", case_sensitive).unwrap();
+        assert!(aligner.align(b"atcg").is_empty());
+    }
+
+    #[test]
+    fn test_all_n_query_is_skipped() {
+        let options = NucmerOptions {
+            min_len: 4,
+            ..NucmerOptions::default()
+        };
+        let aligner = NucmerAligner::new(b"NNNNACGTNNNN", options).unwrap();
+
+        let all_n = b"NNNNNNNNNNNN";
+        assert!(is_placeholder_query(all_n, 0.0));
+        assert!(aligner.align(all_n).is_empty());
+
+        // A query with any called base is aligned by default
+        assert!(!is_placeholder_query(b"NNNNACGTNNNN", 0.0));
+        assert!(!aligner.align(b"NNNNACGTNNNN").is_empty());
+        assert!(is_placeholder_query(b"NNNNACGTNNNN", 0.5));
+    }
+
+    #[test]
+    fn test_set_min_len() {
+        let options = NucmerOptions { min_len: 4, forward_only: true, quiet: true, ..NucmerOptions::default() };
+        let mut aligner = NucmerAligner::new(b"GGGGACGTACGTAA", options).unwrap();
+        assert_eq!(aligner.align(b"ACGTAC"), vec![Match::new(4, 0, 6)]);
+
+        aligner.set_min_len(8).unwrap();
+        assert!(aligner.align(b"ACGTAC").is_empty());
+        // The new length is validated against the other options
+        assert!(aligner.set_min_len(100).is_err());
+    }
+
+    #[test]
+    fn test_align_from_anchors_spans_out_of_order_cluster() {
+        let reference = synthetic_sequence(80, 3);
+        let query = synthetic_sequence(40, 5);
+        let options = NucmerOptions {
+            min_cluster: 0,
+            max_gap: 50,
+            diag_diff: 100,
+            extend: false,
+            ..NucmerOptions::default()
+        };
+
+        // The anchor reaching furthest along the reference ends before the first one starts in the query
+        let anchors = vec![
+            Match::new(0, 20, 5),
+            Match::new(1, 0, 5),
+            Match::new(3, 30, 5),
+            Match::new(50, 2, 10),
+        ];
+        let alignments = align_from_anchors(anchors, &reference, &query, &options);
+        assert_eq!(alignments.len(), 1);
+
+        let alignment = &alignments[0];
+        assert_eq!(alignment.anchors.len(), 4);
+        assert_eq!((alignment.ref_start, alignment.ref_end), (0, 60));
+        assert_eq!((alignment.query_start, alignment.query_end), (0, 35));
+    }
+
+    #[test]
+    fn test_align_from_anchors() {
+        let reference = b"GGGGACGTTGCAAGTCTCATGATTTTCCAGGTACCGTTGGG";
+        let query = b"TTACGTTGCAAGTCCCATGATTTTCCAGGTACCGTTAA";
+        let options = NucmerOptions {
+            min_len: 10,
+            min_cluster: 20,
+            ..NucmerOptions::default()
+        };
+
+        // Two hand-built anchors on the same diagonal either side of a mismatch, plus a stray one
+        let anchors = vec![
+            Match::new(6, 4, 10),
+            Match::new(17, 15, 12),
+            Match::new(38, 0, 3),
+        ];
+        let alignments = align_from_anchors(anchors, reference, query, &options);
+        assert_eq!(alignments.len(), 1);
+
+        let alignment = &alignments[0];
+        assert_eq!(alignment.anchors.len(), 2);
+        assert_eq!(alignment.strand, Strand::Forward);
+        // Extended over the exactly matching flanks on both sides
+        assert_eq!((alignment.ref_start, alignment.ref_end), (4, 38));
+        assert_eq!((alignment.query_start, alignment.query_end), (2, 36));
+        // 34 aligned bases with one mismatch
+        assert_eq!(alignment.score, 32);
+        assert_eq!(alignment.to_match().rank_score(), 32);
+
+        let no_extend = NucmerOptions { extend: false, ..options };
+        let anchors = vec![Match::new(6, 4, 10), Match::new(17, 15, 12)];
+        let alignments = align_from_anchors(anchors, reference, query, &no_extend);
+        assert_eq!((alignments[0].ref_start, alignments[0].ref_end), (6, 29));
+    }
+
+    #[test]
+    fn test_align_clusters_scores_gapped_alignment() {
+        // The reference carries two extra bases between the two halves of the query
+        let (left, right) = (b"ACGTTGCAAGTCCAT", b"GGACCTTGATCGAAC");
+        let query = [&left[..], right].concat();
+        let reference = [&b"GG"[..], left, b"TT", right, b"GG"].concat();
+        let options = NucmerOptions { min_len: 10, min_cluster: 20, ..NucmerOptions::default() };
+        let alignments = NucmerAligner::new(&reference, options).unwrap().align_clusters(&query);
+        assert_eq!(alignments.len(), 1);
+
+        let alignment = &alignments[0];
+        assert_eq!((alignment.ref_start, alignment.ref_end, alignment.query_start, alignment.query_end), (2, 34, 0, 30));
+        // 30 matching bases and a 2-base gap
+        assert_eq!(alignment.score, 26);
+        // As one match it keeps to the shorter, query span
+        assert_eq!(alignment.to_match(), Match::new(2, 0, 30).with_score(26));
+    }
+
+    #[test]
+    fn test_reverse_alignment_to_match_starts_at_query_end() {
+        // The query carries two extra bases, and aligns to the reference on the reverse strand
+        let (left, right) = (b"ACGTTGCAAGTCCAT", b"GGACCTTGATCGAAC");
+        let query = reverse_complement_preserving_case(&[&left[..], b"TT", right].concat());
+        let reference = [&b"GG"[..], left, right, b"GG"].concat();
+        let options = NucmerOptions { min_len: 10, min_cluster: 20, ..NucmerOptions::default() };
+        let alignments = NucmerAligner::new(&reference, options).unwrap().align_clusters(&query);
+        assert_eq!(alignments.len(), 1);
+
+        let alignment = &alignments[0];
+        assert_eq!(alignment.strand, Strand::Reverse);
+        assert_eq!((alignment.ref_start, alignment.ref_end, alignment.query_start, alignment.query_end), (2, 32, 0, 32));
+        // The reference start pairs with the end of the longer query span
+        let m = alignment.to_match();
+        assert_eq!((m.ref_pos, m.query_pos, m.len), (2, 2, 30));
+    }
+
+    #[test]
+    fn test_min_seed_anchors_reports_unmapped() {
+        let reference = synthetic_sequence(400, 1);
+        // An unrelated query shares only a few short chance anchors with the reference
+        let random = synthetic_sequence(200, 2);
+        let options = NucmerOptions { min_len: 6, min_cluster: 6, ..NucmerOptions::default() };
+        let anchors = NucmerAligner::new(&reference, options.clone()).unwrap().align(&random);
+        assert!(!anchors.is_empty());
+
+        let gated = NucmerOptions { min_seed_anchors: anchors.len() + 1, ..options.clone() };
+        let aligner = NucmerAligner::new(&reference, gated).unwrap();
+        assert!(aligner.align(&random).is_empty());
+        // A diverged copy of the reference is seeded with an anchor between each pair of mutations
+        let mut related = reference[100..300].to_vec();
+        for base in related.iter_mut().step_by(20) {
+            *base = if *base == b'A' { b'C' } else { b'A' };
+        }
+        assert!(!aligner.align(&related).is_empty());
+
+        // The gate runs once, on the anchors, before any clustering
+        assert!(aligner.align_clusters(&random).is_empty());
+        assert!(!aligner.align_clusters(&related).is_empty());
+        let anchors = vec![Match::new(100, 0, 30), Match::new(140, 40, 30)];
+        let gated = NucmerOptions { min_seed_anchors: 3, ..options };
+        assert_eq!(align_from_anchors(anchors, &reference, &reference[100..200], &gated).len(), 1);
+    }
+
+    #[test]
+    fn test_x_drop_controls_extension_across_poor_region() {
+        // Anchor, then 5 matches, 3 mismatches and 10 more matches
+        let reference = b"ACGTTGCAAGTCCATGGACTAGGCATTCAGGACCTTGA";
+        let query = b"ACGTTGCAAGTCCATGGACTTCCCATTCAGGACCTTGA";
+        let anchors = vec![Match::new(0, 0, 15)];
+        let align_with = |x_drop| {
+            let options = NucmerOptions { min_len: 10, min_cluster: 10, x_drop, ..NucmerOptions::default() };
+            align_from_anchors(anchors.clone(), reference, query, &options)[0].ref_end
+        };
+
+        assert_eq!(align_with(0), 20);
+        assert_eq!(align_with(2), 20);
+        assert_eq!(align_with(5), 38);
+    }
+
+    #[test]
+    fn test_iupac_extension() {
+        // Anchor, then an R in the query facing the given reference base, then 9 matches
+        let query = b"ACGTTGCAAGTCCATRGGACCTTGA";
+        let extended_end = |ref_base: u8, iupac| {
+            let mut reference = query.to_vec();
+            reference[15] = ref_base;
+            let options = NucmerOptions { min_len: 10, min_cluster: 10, x_drop: 0, iupac, ..NucmerOptions::default() };
+            align_from_anchors(vec![Match::new(0, 0, 15)], &reference, query, &options)[0].ref_end
+        };
+
+        assert_eq!(extended_end(b'A', true), 25);
+        assert_eq!(extended_end(b'G', true), 25);
+        assert_eq!(extended_end(b'C', true), 15);
+        assert_eq!(extended_end(b'A', false), 15);
+    }
+
+    #[test]
+    fn test_reverse_strand_of_non_utf8_query() {
+        assert_eq!(reverse_complement_preserving_case(b"ACRtn"), b"naYGT");
+
+        // A byte that is not valid UTF-8 after the reverse complement of part of the reference
+        let reference = b"TTTTTGCATGCCAGTATCGATCAAGGTTTTT";
+        let query = b"CCTTGATCGATACTGGCATGC\xff";
+        let options = NucmerOptions { min_len: 15, ..NucmerOptions::default() };
+        let matches = NucmerAligner::new(reference, options).unwrap().align(query);
+        assert_eq!(matches, vec![Match::new(5, 0, 21).with_strand(Strand::Reverse)]);
+    }
+
+    #[test]
+    fn test_case_sensitive_reverse_strand_keeps_case() {
+        // Soft-masked (lower case) bases keep their case on the reverse strand
+        let reference = b"TTTTTgcatgccagtatcgatcaaggTTTTT";
+        let query = b"ccttgatcgatactggcatgc";
+        let options = NucmerOptions { min_len: 15, case_sensitive: true, ..NucmerOptions::default() };
+        let matches = NucmerAligner::new(reference, options.clone()).unwrap().align(query);
+        assert_eq!(matches, vec![Match::new(5, 0, 21).with_strand(Strand::Reverse)]);
+
+        // So they no longer match the same bases in upper case
+        let upper = reference.to_ascii_uppercase();
+        assert!(NucmerAligner::new(&upper, options).unwrap().align(query).is_empty());
+    }
+
+    #[test]
+    fn test_validate_negative_diag_factor() {
+        let options = NucmerOptions {
+            diag_factor: -0.5,
+            ..NucmerOptions::default()
+        };
+        assert_eq!(
+            options.validate().unwrap_err().to_string(),
+            "diag_factor must be non-negative, got -0.5"
+        );
+    }
 }
@@ -1,4 +1,7 @@
-use crate::Match;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+
+use crate::{base_frequencies, match_evalue, split_at_origin, Alignment, Chain, GapKind, Match, Scoring, Strand};
 
 #[derive(Debug, Clone)]
 pub enum OutputFormat {
@@ -6,60 +9,373 @@ pub enum OutputFormat {
     Delta,
     Paf,
     Sam,
+    Maf,
+    #[cfg(feature = "bam")]
+    Bam,
+}
+
+/// Names accepted by `OutputFormat::from_str`, as listed in error messages
+#[cfg(not(feature = "bam"))]
+pub const OUTPUT_FORMAT_NAMES: &str = "default, delta, paf, sam, maf";
+/// Names accepted by `OutputFormat::from_str`, as listed in error messages
+#[cfg(feature = "bam")]
+pub const OUTPUT_FORMAT_NAMES: &str = "default, delta, paf, sam, maf, bam";
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "default" => Ok(OutputFormat::Default),
+            "delta" => Ok(OutputFormat::Delta),
+            "paf" => Ok(OutputFormat::Paf),
+            "sam" => Ok(OutputFormat::Sam),
+            "maf" => Ok(OutputFormat::Maf),
+            #[cfg(feature = "bam")]
+            "bam" => Ok(OutputFormat::Bam),
+            _ => Err(format!("Unknown output format '{}', expected one of: {}", s, OUTPUT_FORMAT_NAMES)),
+        }
+    }
+}
+
+/// Order in which the query start and end of reverse-strand matches are printed
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReverseCoords {
+    #[default]
+    Ascending,
+    /// Start at the higher coordinate, as the match reads along the reverse strand
+    Descending,
 }
 
-impl OutputFormat {
-    pub fn from_str(s: &str) -> Option<Self> {
+impl ReverseCoords {
+    pub fn parse(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
-            "delta" => Some(OutputFormat::Delta),
-            "paf" => Some(OutputFormat::Paf),
-            "sam" => Some(OutputFormat::Sam),
+            "ascending" => Some(ReverseCoords::Ascending),
+            "descending" => Some(ReverseCoords::Descending),
             _ => None,
         }
     }
 }
 
-pub fn print_matches_in_format(matches: &[Match], query_file: &str, format: &OutputFormat, reference_seq: &[u8], query_seq: &[u8]) {
+/// Field formatting shared by the output formats
+#[derive(Debug, Clone)]
+pub struct OutputOptions {
+    pub delimiter: String,
+    pub precision: Option<usize>, // decimal places of the PAF dv tag and of E-values; None keeps the default float formatting
+    pub buffer_size: usize,       // bytes of output collected before each write to stdout
+    pub reverse_coords: ReverseCoords, // Default and delta formats only
+    pub raw_reverse_coords: bool, // Default and delta formats only: reverse matches at their position on the reverse complement
+    pub paf_tags: bool, // append minimap2-style NM, dv and tp tags to PAF records
+    pub ref_name: String, // target name in PAF, SAM and MAF records
+    pub evalue: bool, // report a composition-based E-value in default and PAF records
+    pub score: bool,  // report an alignment score in default records and as the PAF, SAM and BAM AS tag
+}
+
+/// Name given to the reference when none is supplied
+pub const DEFAULT_REF_NAME: &str = "reference";
+
+/// Default output buffer capacity
+pub const DEFAULT_OUTPUT_BUFFER_SIZE: usize = 64 * 1024;
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: "\t".to_string(),
+            precision: None,
+            buffer_size: DEFAULT_OUTPUT_BUFFER_SIZE,
+            reverse_coords: ReverseCoords::default(),
+            raw_reverse_coords: false,
+            paf_tags: false,
+            ref_name: DEFAULT_REF_NAME.to_string(),
+            evalue: false,
+            score: false,
+        }
+    }
+}
+
+impl OutputOptions {
+    /// Parse a delimiter argument, accepting names for characters that are awkward on a shell
+    pub fn parse_delimiter(s: &str) -> Option<String> {
+        match s {
+            "tab" | "\\t" | "\t" => Some("\t".to_string()),
+            "comma" => Some(",".to_string()),
+            "space" => Some(" ".to_string()),
+            "" => None,
+            other => Some(other.to_string()),
+        }
+    }
+
+    /// 1-based inclusive query start and end of a canonical match, swapped for descending reverse
+    /// matches
+    ///
+    /// With `raw_reverse_coords`, a reverse match is instead placed on the reverse complement of
+    /// the `query_len`-base query, where it was found, and is always ascending. This undoes the
+    /// conversion `NucmerAligner::align` applies, which makes it a debugging aid for raw hits.
+    fn query_coords(&self, m: &Match, query_len: usize) -> (usize, usize) {
+        if m.strand == Strand::Reverse && self.raw_reverse_coords {
+            let query_pos = query_len - m.query_pos - m.len;
+            return (query_pos + 1, query_pos + m.len);
+        }
+        let (start, end) = (m.query_pos + 1, m.query_pos + m.len);
+        if m.strand == Strand::Reverse && self.reverse_coords == ReverseCoords::Descending {
+            (end, start)
+        } else {
+            (start, end)
+        }
+    }
+
+    fn format_float(&self, value: f64) -> String {
+        match self.precision {
+            Some(precision) => format!("{:.*}", precision, value),
+            None => value.to_string(),
+        }
+    }
+
+    /// E-values span many orders of magnitude, so they are always written in scientific notation
+    fn format_evalue(&self, value: f64) -> String {
+        format!("{:.*e}", self.precision.unwrap_or(2), value)
+    }
+
+    fn join(&self, fields: &[String]) -> String {
+        fields.join(&self.delimiter)
+    }
+}
+
+pub fn print_matches_in_format(matches: &[Match], query_file: &str, format: &OutputFormat, reference_seq: &[u8], query_seq: &[u8], query_qual: Option<&[u8]>, options: &OutputOptions) -> io::Result<()> {
+    print_buffered(options.buffer_size, |out| {
+        write_matches_in_format(out, matches, query_file, format, reference_seq, query_seq, query_qual, options)
+    })
+}
+
+/// Prints the matches of every query in a run to stdout
+///
+/// Formats with a file-level header and trailer, like BAM, write them once for the whole run
+/// rather than once per query. Nothing is printed until the first query's matches are.
+pub struct MatchPrinter<'a> {
+    format: &'a OutputFormat,
+    options: &'a OutputOptions,
+    #[cfg(feature = "bam")]
+    bam: Option<crate::bam::BamWriter<BufWriter<io::StdoutLock<'static>>>>,
+}
+
+impl<'a> MatchPrinter<'a> {
+    pub fn new(format: &'a OutputFormat, options: &'a OutputOptions) -> Self {
+        Self {
+            format,
+            options,
+            #[cfg(feature = "bam")]
+            bam: None,
+        }
+    }
+
+    pub fn print(&mut self, matches: &[Match], query_file: &str, reference_seq: &[u8], query_seq: &[u8], query_qual: Option<&[u8]>) -> io::Result<()> {
+        #[cfg(feature = "bam")]
+        if let OutputFormat::Bam = self.format {
+            let matches = matches_for_format(matches, self.format, reference_seq.len(), query_seq.len())?;
+            let bam = match &mut self.bam {
+                Some(bam) => bam,
+                None => {
+                    let stdout = BufWriter::with_capacity(self.options.buffer_size, io::stdout().lock());
+                    self.bam.insert(crate::bam::BamWriter::new(stdout, &self.options.ref_name, reference_seq)?)
+                }
+            };
+            return bam.write_matches(&matches, query_file, reference_seq, query_seq, query_qual, self.options);
+        }
+        write_buffered(io::stdout().lock(), self.options.buffer_size, |out| {
+            write_matches_in_format(out, matches, query_file, self.format, reference_seq, query_seq, query_qual, self.options)
+        })?
+        .flush()
+    }
+
+    /// Write the trailer of the format, if anything was printed
+    pub fn finish(self) -> io::Result<()> {
+        #[cfg(feature = "bam")]
+        if let Some(bam) = self.bam {
+            bam.finish()?.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Run `write` against stdout through a buffer of `buffer_size` bytes and flush it
+/// Writes are batched instead of locking and flushing stdout once per line.
+pub fn print_buffered(buffer_size: usize, write: impl FnOnce(&mut BufWriter<io::StdoutLock<'static>>) -> io::Result<()>) -> io::Result<()> {
+    write_buffered(io::stdout().lock(), buffer_size, write)?.flush()
+}
+
+/// Run `write` against `inner` through a buffer of `buffer_size` bytes, returning `inner` once
+/// everything is flushed to it
+pub fn write_buffered<W: Write>(inner: W, buffer_size: usize, write: impl FnOnce(&mut BufWriter<W>) -> io::Result<()>) -> io::Result<W> {
+    let mut out = BufWriter::with_capacity(buffer_size, inner);
+    write(&mut out)?;
+    out.into_inner().map_err(|e| e.into_error())
+}
+
+/// Write matches in the given format
+///
+/// `query_qual` holds the FASTQ quality string of `query_seq`, when it has one; only SAM uses it.
+/// A match that runs past the end of the query, or starts past the end of the reference, is
+/// an `InvalidInput` error. One running past the end of a circular reference wraps to its start:
+/// the default format shows it whole, and every other format as two records split at the origin.
+#[allow(clippy::too_many_arguments)]
+pub fn write_matches_in_format<W: Write>(out: &mut W, matches: &[Match], query_file: &str, format: &OutputFormat, reference_seq: &[u8], query_seq: &[u8], query_qual: Option<&[u8]>, options: &OutputOptions) -> io::Result<()> {
+    // Every writer below assumes canonical query coordinates
+    let canonical = matches_for_format(matches, format, reference_seq.len(), query_seq.len())?;
+    let matches = &canonical[..];
+    let evalues = options.evalue.then(|| {
+        let base_freqs = base_frequencies(reference_seq);
+        matches.iter().map(|m| match_evalue(m.len, reference_seq.len(), query_seq.len(), &base_freqs)).collect::<Vec<f64>>()
+    });
+    let evalues = evalues.as_deref();
+    let scores = match_scores(matches, reference_seq, query_seq, options);
+    let scores = scores.as_deref();
     match format {
-        OutputFormat::Default => print_matches_default(matches, query_file),
-        OutputFormat::Delta => print_matches_delta(matches, query_file, reference_seq, query_seq),
-        OutputFormat::Paf => print_matches_paf(matches, query_file, reference_seq, query_seq),
-        OutputFormat::Sam => print_matches_sam(matches, query_file, reference_seq, query_seq),
+        OutputFormat::Default => write_matches_default(out, matches, query_file, query_seq.len(), evalues, scores, options),
+        OutputFormat::Delta => write_matches_delta(out, matches, query_file, reference_seq, query_seq, options),
+        OutputFormat::Paf => write_matches_paf(out, matches, query_file, reference_seq, query_seq, evalues, scores, options),
+        OutputFormat::Sam => write_matches_sam(out, matches, query_file, reference_seq, query_seq, query_qual, scores, options),
+        OutputFormat::Maf => write_matches_maf(out, matches, query_file, reference_seq, query_seq, options),
+        #[cfg(feature = "bam")]
+        OutputFormat::Bam => crate::bam::write_matches_bam(out, matches, query_file, reference_seq, query_seq, query_qual, scores, options),
     }
 }
 
-fn print_matches_default(matches: &[Match], query_file: &str) {
-    println!("> Query: {}", query_file);
-    for m in matches {
-        println!("  Ref: {}  Query: {}  Len: {}", m.ref_pos + 1, m.query_pos + 1, m.len);
+/// Matches as `format` writes them: canonical, and split at the origin of a circular reference
+/// unless the format is the default one
+fn matches_for_format(matches: &[Match], format: &OutputFormat, ref_len: usize, query_len: usize) -> io::Result<Vec<Match>> {
+    let canonical = canonical_matches(matches, ref_len, query_len)?;
+    Ok(match format {
+        OutputFormat::Default => canonical,
+        _ => split_at_origin(&canonical, ref_len),
+    })
+}
+
+/// Matches in canonical query coordinates, or an `InvalidInput` error for the first one that does
+/// not fit the sequences
+fn canonical_matches(matches: &[Match], ref_len: usize, query_len: usize) -> io::Result<Vec<Match>> {
+    if let Some(m) = matches.iter().find(|m| !within_sequences(m, ref_len, query_len)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "match of {} bases at reference {} and query {} does not fit a {}-base reference and {}-base query",
+                m.len, m.ref_pos + 1, m.query_pos + 1, ref_len, query_len
+            ),
+        ));
+    }
+    Ok(matches.iter().map(|m| m.canonicalize(query_len)).collect())
+}
+
+/// Whether a match lies within the query, in either query coordinate system, and starts within
+/// the reference, spanning it at most once
+fn within_sequences(m: &Match, ref_len: usize, query_len: usize) -> bool {
+    m.query_pos + m.len <= query_len && m.ref_pos < ref_len && m.len <= ref_len
+}
+
+/// Write a FASTA record with the sequence wrapped at `width` bases per line
+pub fn write_fasta<W: Write>(out: &mut W, name: &str, sequence: &[u8], width: usize) -> io::Result<()> {
+    writeln!(out, ">{}", name)?;
+    for line in sequence.chunks(width.max(1)) {
+        out.write_all(line)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Print the best chain for a query as a single alignment block
+pub fn print_best_path(chain: Option<&Chain>, query_file: &str) -> io::Result<()> {
+    print_buffered(DEFAULT_OUTPUT_BUFFER_SIZE, |out| write_best_path(out, chain, query_file))
+}
+
+pub fn write_best_path<W: Write>(out: &mut W, chain: Option<&Chain>, query_file: &str) -> io::Result<()> {
+    writeln!(out, "> Query: {}", query_file)?;
+    if let Some(chain) = chain {
+        writeln!(out, "  Ref: {}-{}  Query: {}-{}  Anchors: {}  Score: {}",
+                 chain.ref_start() + 1, chain.ref_end(),
+                 chain.query_start() + 1, chain.query_end(),
+                 chain.matches.len(), chain.score)?;
     }
+    Ok(())
+}
+
+/// Print a table of the indels in the best chain for a query, after the column header when
+/// `header` is set
+/// The header is printed once per run, before the first query's rows.
+pub fn print_gap_report(chain: Option<&Chain>, query_file: &str, header: bool) -> io::Result<()> {
+    print_buffered(DEFAULT_OUTPUT_BUFFER_SIZE, |out| {
+        if header {
+            write_gap_report_header(out)?;
+        }
+        write_gap_report(out, chain, query_file)
+    })
 }
 
-fn print_matches_delta(matches: &[Match], query_file: &str, reference_seq: &[u8], _query_seq: &[u8]) {
+pub fn write_gap_report_header<W: Write>(out: &mut W) -> io::Result<()> {
+    writeln!(out, "#query\tref_pos\tquery_pos\ttype\tlength")
+}
+
+pub fn write_gap_report<W: Write>(out: &mut W, chain: Option<&Chain>, query_file: &str) -> io::Result<()> {
+    for gap in chain.map(|c| c.gaps()).unwrap_or_default() {
+        let kind = match gap.kind {
+            GapKind::Insertion => "INS",
+            GapKind::Deletion => "DEL",
+        };
+        // 1-based position of the first base after the preceding match
+        writeln!(out, "{}\t{}\t{}\t{}\t{}", query_file, gap.ref_pos + 1, gap.query_pos + 1, kind, gap.len)?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_matches_default<W: Write>(out: &mut W, matches: &[Match], query_file: &str, query_len: usize, evalues: Option<&[f64]>, scores: Option<&[i64]>, options: &OutputOptions) -> io::Result<()> {
+    writeln!(out, "> Query: {}", query_file)?;
+    for (i, m) in matches.iter().enumerate() {
+        let (query_start, _) = options.query_coords(m, query_len);
+        let strand = if m.strand == Strand::Reverse { '-' } else { '+' };
+        write!(out, "  Ref: {}  Query: {}  Len: {}  Strand: {}", m.ref_pos + 1, query_start, m.len, strand)?;
+        if m.strand == Strand::Reverse {
+            // 1-based start on the reverse complement of the query, where the match reads forward
+            write!(out, "  RC Query: {}", query_len - m.query_pos - m.len + 1)?;
+        }
+        if let Some(scores) = scores {
+            write!(out, "  Score: {}", scores[i])?;
+        }
+        if let Some(evalues) = evalues {
+            write!(out, "  E: {}", options.format_evalue(evalues[i]))?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn write_matches_delta<W: Write>(out: &mut W, matches: &[Match], _query_file: &str, reference_seq: &[u8], _query_seq: &[u8], options: &OutputOptions) -> io::Result<()> {
     // Print header for delta format
-    println!("NUCMER");
-    println!("NUCMER");
+    writeln!(out, "NUCMER")?;
+    writeln!(out, "NUCMER")?;
     
     // Print reference and query file names
-    println!("> Reference Query");
+    writeln!(out, "> Reference Query")?;
     
     for m in matches {
         // Delta format: ref_start ref_end query_start query_end ref_len query_len match_len
         let ref_start = m.ref_pos + 1;  // 1-based indexing
         let ref_end = m.ref_pos + m.len;
-        let query_start = m.query_pos + 1;  // 1-based indexing
-        let query_end = m.query_pos + m.len;
-        
         let ref_len = reference_seq.len();
         let query_len = _query_seq.len();
+        let (query_start, query_end) = options.query_coords(m, query_len);
         
-        println!("{} {} {} {} {} {} {}", 
-                 ref_start, ref_end, query_start, query_end, ref_len, query_len, m.len);
+        writeln!(out, "{} {} {} {} {} {} {}", 
+                 ref_start, ref_end, query_start, query_end, ref_len, query_len, m.len)?;
+        // Signed offsets to each indel end with 0; an exact match has none
+        writeln!(out, "0")?;
     }
+    Ok(())
 }
 
-fn print_matches_paf(matches: &[Match], query_file: &str, reference_seq: &[u8], query_seq: &[u8]) {
-    for m in matches {
+#[allow(clippy::too_many_arguments)]
+fn write_matches_paf<W: Write>(out: &mut W, matches: &[Match], query_file: &str, reference_seq: &[u8], query_seq: &[u8], evalues: Option<&[f64]>, scores: Option<&[i64]>, options: &OutputOptions) -> io::Result<()> {
+    let primary = if options.paf_tags { primary_matches(matches) } else { Vec::new() };
+    
+    for (i, m) in matches.iter().enumerate() {
         // PAF format: query_name, query_length, query_start, query_end, 
         // strand, ref_name, ref_length, ref_start, ref_end, 
         // matching_bases, alignment_length, mapping_quality
@@ -69,45 +385,742 @@ fn print_matches_paf(matches: &[Match], query_file: &str, reference_seq: &[u8],
         let query_start = m.query_pos;
         let query_end = m.query_pos + m.len;
         
-        let strand = "+"; // For simplicity, assuming forward strand
+        let strand = match m.strand {
+            Strand::Forward => "+",
+            Strand::Reverse => "-",
+        };
         
-        let ref_name = "reference"; // Using a generic name
+        let ref_name = &options.ref_name;
         let ref_length = reference_seq.len();
         let ref_start = m.ref_pos;
         let ref_end = m.ref_pos + m.len;
         
-        let matching_bases = m.len; // Assuming all bases match for simplicity
+        let edit_distance = mismatches(m, reference_seq, query_seq);
+        let matching_bases = m.len - edit_distance;
         let alignment_length = m.len;
         let mapping_quality = 60; // Default mapping quality
         
-        println!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}", 
-                 query_name, query_length, query_start, query_end,
-                 strand, ref_name, ref_length, ref_start, ref_end,
-                 matching_bases, alignment_length, mapping_quality);
+        let mut fields = vec![
+            query_name.to_string(), query_length.to_string(), query_start.to_string(), query_end.to_string(),
+            strand.to_string(), ref_name.to_string(), ref_length.to_string(), ref_start.to_string(), ref_end.to_string(),
+            matching_bases.to_string(), alignment_length.to_string(), mapping_quality.to_string(),
+        ];
+        if options.paf_tags {
+            let divergence = edit_distance as f64 / m.len.max(1) as f64;
+            fields.push(format!("NM:i:{}", edit_distance));
+            fields.push(format!("dv:f:{}", options.format_float(divergence)));
+            fields.push(format!("tp:A:{}", if primary[i] { 'P' } else { 'S' }));
+        }
+        if let Some(scores) = scores {
+            fields.push(format!("AS:i:{}", scores[i]));
+        }
+        if let Some(evalues) = evalues {
+            fields.push(format!("ev:f:{}", options.format_evalue(evalues[i])));
+        }
+        writeln!(out, "{}", options.join(&fields))?;
+    }
+    Ok(())
+}
+
+/// Reference bases covered by a match, continuing from the start of a circular reference when
+/// the match runs past its end
+fn reference_span<'a>(m: &Match, reference_seq: &'a [u8]) -> std::borrow::Cow<'a, [u8]> {
+    if m.ref_pos + m.len <= reference_seq.len() {
+        return std::borrow::Cow::Borrowed(&reference_seq[m.ref_pos..m.ref_pos + m.len]);
     }
+    let ref_len = reference_seq.len().max(1);
+    (m.ref_pos..m.ref_pos + m.len).map(|pos| reference_seq[pos % ref_len]).collect()
+}
+
+/// Score of each match when `options.score` is set: `match_score` per matching base and
+/// `mismatch` per mismatching one, with the default `Scoring`; matches are ungapped
+pub(crate) fn match_scores(matches: &[Match], reference_seq: &[u8], query_seq: &[u8], options: &OutputOptions) -> Option<Vec<i64>> {
+    let scoring = Scoring::default();
+    options.score.then(|| {
+        matches
+            .iter()
+            .map(|m| {
+                let mismatched = mismatches(m, reference_seq, query_seq) as i64;
+                (m.len as i64 - mismatched) * scoring.match_score + mismatched * scoring.mismatch
+            })
+            .collect()
+    })
 }
 
-fn print_matches_sam(matches: &[Match], query_file: &str, reference_seq: &[u8], query_seq: &[u8]) {
+/// Mismatching bases between the reference span of a match and the query span it aligns to
+/// Matches are ungapped, so this is their edit distance
+fn mismatches(m: &Match, reference_seq: &[u8], query_seq: &[u8]) -> usize {
+    let ref_span = reference_span(m, reference_seq);
+    let aligned: Vec<u8> = match m.strand {
+        Strand::Forward => query_seq[m.query_pos..m.query_pos + m.len].to_vec(),
+        Strand::Reverse => m.revcomp_query_slice(query_seq),
+    };
+    ref_span.iter().zip(&aligned).filter(|(r, q)| !r.eq_ignore_ascii_case(q)).count()
+}
+
+/// Whether each match is primary: the best-ranked match for its region of the query
+///
+/// Matches are taken in decreasing `rank_score` order; one is secondary when at least half of its query span is
+/// covered by a primary match already chosen, as with minimap2's default mask level.
+fn primary_matches(matches: &[Match]) -> Vec<bool> {
+    let mut order: Vec<usize> = (0..matches.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(matches[i].rank_score()));
+    
+    let mut primary = vec![false; matches.len()];
+    let mut chosen: Vec<&Match> = Vec::new();
+    for i in order {
+        let m = &matches[i];
+        let shadowed = chosen.iter().any(|p| {
+            let overlap = (m.query_pos + m.len).min(p.query_pos + p.len).saturating_sub(m.query_pos.max(p.query_pos));
+            2 * overlap >= m.len
+        });
+        if !shadowed {
+            primary[i] = true;
+            chosen.push(m);
+        }
+    }
+    primary
+}
+
+/// The fields of an unpaired SAM record that vary between matches; every record is on the one
+/// reference, with no mate (RNEXT `*`, PNEXT 0, TLEN 0)
+pub(crate) struct SamRecord<'a> {
+    pub(crate) qname: &'a str,          // Query template NAME
+    pub(crate) flag: u16,               // bitwise FLAG (0 for the primary record, unmated)
+    pub(crate) pos: usize,              // 1-based leftmost mapping POSition
+    pub(crate) mapq: u8,                // MAPping Quality
+    pub(crate) cigar: String,           // CIGAR string
+    pub(crate) seq: &'a [u8],           // full read SEQuence, flanks are soft-clipped
+    pub(crate) qual: Option<&'a [u8]>,  // Phred+33 base QUALities covering SEQ, when known
+    pub(crate) score: Option<i64>,      // alignment score for the AS tag, when requested
+}
+
+/// SAM records for matches of one query, as written by the SAM and BAM writers
+/// `scores` gives the AS tag of each record, as computed by `match_scores`
+pub(crate) fn sam_records<'a>(matches: &[Match], query_file: &'a str, query_seq: &'a [u8], query_qual: Option<&'a [u8]>, scores: Option<&[i64]>) -> Vec<SamRecord<'a>> {
+    let qual = query_qual.filter(|qual| qual.len() == query_seq.len());
+    // Only the best-ranked match (the first of equals) is primary; the rest are secondary
+    let primary = matches.iter().enumerate().rev().max_by_key(|(_, m)| m.rank_score()).map(|(i, _)| i);
+    matches
+        .iter()
+        .enumerate()
+        .map(|(i, m)| SamRecord {
+            qname: query_file,
+            flag: if Some(i) == primary { 0 } else { FLAG_SECONDARY },
+            pos: m.ref_pos + 1,
+            mapq: 60,
+            cigar: soft_clipped_cigar(m, query_seq.len()),
+            seq: query_seq,
+            qual,
+            score: scores.map(|scores| scores[i]),
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_matches_sam<W: Write>(out: &mut W, matches: &[Match], query_file: &str, reference_seq: &[u8], query_seq: &[u8], query_qual: Option<&[u8]>, scores: Option<&[i64]>, options: &OutputOptions) -> io::Result<()> {
     // Print SAM header if this is the first output
-    println!("@HD\tVN:1.6");
-    println!("@SQ\tSN:reference\tLN:{}", reference_seq.len());
+    write_sam_header(out, &options.ref_name, reference_seq)?;
     
-    for m in matches {
+    for record in sam_records(matches, query_file, query_seq, query_qual, scores) {
         // SAM format: QNAME, FLAG, RNAME, POS, MAPQ, CIGAR, RNEXT, PNEXT, TLEN, SEQ, QUAL
+        let seq = String::from_utf8_lossy(record.seq);
+        // ASCII of Phred-scaled base QUALity+33, covering SEQ including its soft-clipped flanks
+        let qual = record.qual.map_or("*".into(), String::from_utf8_lossy);
         
-        let qname = query_file; // Query template NAME
-        let flag = 0; // bitwise FLAG (0 for forward strand, unmated)
-        let rname = "reference"; // Reference sequence NAME
-        let pos = m.ref_pos + 1; // 1-based leftmost mapping POSition
-        let mapq = 60; // MAPping Quality
-        let cigar = format!("{}M", m.len); // CIGAR string
-        let rnext = "*"; // Ref. name of the mate/next read
-        let pnext = 0; // Position of the mate/next read
-        let tlen = 0; // observed Template LENgth
-        let seq = String::from_utf8_lossy(&query_seq[m.query_pos..m.query_pos + m.len]); // segment SEQuence
-        let qual = "*"; // ASCII of Phred-scaled base QUALity+33
-        
-        println!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}", 
-                 qname, flag, rname, pos, mapq, cigar, rnext, pnext, tlen, seq, qual);
+        write!(out, "{}\t{}\t{}\t{}\t{}\t{}\t*\t0\t0\t{}\t{}", 
+               record.qname, record.flag, options.ref_name, record.pos, record.mapq, record.cigar, seq, qual)?;
+        if let Some(score) = record.score {
+            write!(out, "\tAS:i:{}", score)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Write one MAF alignment block per match, reference row first
+///
+/// Matches are exact, so the aligned rows of a block carry no gaps; `write_alignments_maf` writes
+/// gapped blocks for clustered alignments. Reverse-strand matches give the query row on the `-`
+/// strand, with its start counted from the end of the query.
+fn write_matches_maf<W: Write>(out: &mut W, matches: &[Match], query_file: &str, reference_seq: &[u8], query_seq: &[u8], options: &OutputOptions) -> io::Result<()> {
+    writeln!(out, "##maf version=1")?;
+    writeln!(out)?;
+
+    for m in matches {
+        let ref_text = String::from_utf8_lossy(&reference_span(m, reference_seq)).into_owned();
+        let (query_start, strand, query_text) = match m.strand {
+            Strand::Forward => (m.query_pos, '+', query_seq[m.query_pos..m.query_pos + m.len].to_vec()),
+            Strand::Reverse => (query_seq.len() - m.query_pos - m.len, '-', m.revcomp_query_slice(query_seq)),
+        };
+
+        writeln!(out, "a score={}", m.len)?;
+        writeln!(out, "s {} {} {} + {} {}", options.ref_name, m.ref_pos, m.len, reference_seq.len(), ref_text)?;
+        writeln!(out, "s {} {} {} {} {} {}",
+                 query_file, query_start, m.len, strand, query_seq.len(), String::from_utf8_lossy(&query_text))?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Write one MAF alignment block per clustered alignment, with gapped rows
+///
+/// The rows come from `Alignment::aligned_rows`; as with matches, a reverse-strand alignment
+/// gives the query row on the `-` strand, with its start counted from the end of the query.
+pub fn write_alignments_maf<W: Write>(out: &mut W, alignments: &[Alignment], query_file: &str, reference_seq: &[u8], query_seq: &[u8], options: &OutputOptions) -> io::Result<()> {
+    writeln!(out, "##maf version=1")?;
+    writeln!(out)?;
+
+    for alignment in alignments {
+        let (ref_row, query_row) = alignment.aligned_rows(reference_seq, query_seq);
+        let query_start = match alignment.strand {
+            Strand::Forward => alignment.query_start,
+            Strand::Reverse => query_seq.len() - alignment.query_end,
+        };
+        let strand = if alignment.strand == Strand::Reverse { '-' } else { '+' };
+
+        writeln!(out, "a score={}", alignment.score)?;
+        writeln!(out, "s {} {} {} + {} {}",
+                 options.ref_name, alignment.ref_start, alignment.ref_end - alignment.ref_start, reference_seq.len(), String::from_utf8_lossy(&ref_row))?;
+        writeln!(out, "s {} {} {} {} {} {}",
+                 query_file, query_start, alignment.query_end - alignment.query_start, strand, query_seq.len(), String::from_utf8_lossy(&query_row))?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+pub fn write_sam_header<W: Write>(out: &mut W, ref_name: &str, reference_seq: &[u8]) -> io::Result<()> {
+    writeln!(out, "@HD\tVN:1.6")?;
+    writeln!(out, "@SQ\tSN:{}\tLN:{}", ref_name, reference_seq.len())
+}
+
+/// One read of a pair, with the match it maps with if any
+#[derive(Debug, Clone)]
+pub struct Mate<'a> {
+    pub name: &'a str,
+    pub sequence: &'a [u8],
+    pub mapping: Option<&'a Match>,
+}
+
+// SAM FLAG bits
+const FLAG_PAIRED: u16 = 0x1;
+const FLAG_PROPER_PAIR: u16 = 0x2;
+const FLAG_UNMAPPED: u16 = 0x4;
+const FLAG_MATE_UNMAPPED: u16 = 0x8;
+const FLAG_REVERSE: u16 = 0x10;
+const FLAG_MATE_REVERSE: u16 = 0x20;
+const FLAG_FIRST: u16 = 0x40;
+const FLAG_SECOND: u16 = 0x80;
+const FLAG_SECONDARY: u16 = 0x100;
+
+/// Write the two SAM records of a read pair, each pointing at its mate with RNEXT/PNEXT
+///
+/// A pair is proper when both mates map, on opposite strands. Reverse-strand mates are written
+/// reverse complemented, as SAM requires; an unmapped mate takes its partner's position.
+pub fn write_paired_sam<W: Write>(out: &mut W, ref_name: &str, first: &Mate, second: &Mate) -> io::Result<()> {
+    let first_mapping = first.mapping.map(|m| m.canonicalize(first.sequence.len()));
+    let second_mapping = second.mapping.map(|m| m.canonicalize(second.sequence.len()));
+    let first = &Mate { mapping: first_mapping.as_ref(), ..first.clone() };
+    let second = &Mate { mapping: second_mapping.as_ref(), ..second.clone() };
+    let qname = first.name.strip_suffix("/1").unwrap_or(first.name);
+    let proper = match (first.mapping, second.mapping) {
+        (Some(a), Some(b)) => a.strand != b.strand,
+        _ => false,
+    };
+    // Observed template length, from the leftmost mapped base to the rightmost
+    let template_len = match (first.mapping, second.mapping) {
+        (Some(a), Some(b)) => {
+            let start = a.ref_pos.min(b.ref_pos);
+            let end = (a.ref_pos + a.len).max(b.ref_pos + b.len);
+            (end - start) as i64
+        }
+        _ => 0,
+    };
+
+    for (mate, other, order_flag) in [(first, second, FLAG_FIRST), (second, first, FLAG_SECOND)] {
+        let mut flag = FLAG_PAIRED | order_flag;
+        if proper {
+            flag |= FLAG_PROPER_PAIR;
+        }
+        match mate.mapping {
+            Some(m) if m.strand == Strand::Reverse => flag |= FLAG_REVERSE,
+            Some(_) => {}
+            None => flag |= FLAG_UNMAPPED,
+        }
+        match other.mapping {
+            Some(m) if m.strand == Strand::Reverse => flag |= FLAG_MATE_REVERSE,
+            Some(_) => {}
+            None => flag |= FLAG_MATE_UNMAPPED,
+        }
+
+        let (rname, pos) = match (mate.mapping, other.mapping) {
+            (Some(m), _) | (None, Some(m)) => (ref_name, m.ref_pos + 1),
+            (None, None) => ("*", 0),
+        };
+        let (rnext, pnext) = match (mate.mapping, other.mapping) {
+            (_, Some(m)) => ("=", m.ref_pos + 1),
+            (Some(m), None) => ("=", m.ref_pos + 1),
+            (None, None) => ("*", 0),
+        };
+        let tlen = match (mate.mapping, other.mapping) {
+            (Some(m), Some(o)) if (m.ref_pos, order_flag) <= (o.ref_pos, FLAG_FIRST) => template_len,
+            (Some(_), Some(_)) => -template_len,
+            _ => 0,
+        };
+
+        let (cigar, seq) = match mate.mapping {
+            Some(m) if m.strand == Strand::Reverse => {
+                // Read the clipped flanks off the reverse-complemented read
+                let on_strand = Match::new(m.ref_pos, mate.sequence.len() - m.query_pos - m.len, m.len);
+                // SEQ is the whole read reverse complemented
+                let whole_read = Match::new(m.ref_pos, 0, mate.sequence.len());
+                (soft_clipped_cigar(&on_strand, mate.sequence.len()), whole_read.revcomp_query_slice(mate.sequence))
+            }
+            Some(m) => (soft_clipped_cigar(m, mate.sequence.len()), mate.sequence.to_vec()),
+            None => ("*".to_string(), mate.sequence.to_vec()),
+        };
+        let mapq = if mate.mapping.is_some() { 60 } else { 0 };
+
+        writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t*",
+                 qname, flag, rname, pos, mapq, cigar, rnext, pnext, tlen, String::from_utf8_lossy(&seq))?;
+    }
+    Ok(())
+}
+
+/// Build a CIGAR for a match, soft-clipping the unaligned query prefix and suffix
+fn soft_clipped_cigar(m: &Match, query_len: usize) -> String {
+    let leading = m.query_pos;
+    let trailing = query_len.saturating_sub(m.query_pos + m.len);
+    
+    let mut cigar = String::new();
+    if leading > 0 {
+        cigar.push_str(&format!("{}S", leading));
+    }
+    cigar.push_str(&format!("{}M", m.len));
+    if trailing > 0 {
+        cigar.push_str(&format!("{}S", trailing));
+    }
+    cigar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QueryCoords;
+
+    fn render(format: &OutputFormat, options: &OutputOptions) -> String {
+        let matches = vec![Match::new(2, 1, 4)];
+        let mut out = Vec::new();
+        write_matches_in_format(&mut out, &matches, "q", format, b"AAACGTAA", b"TACGTT", None, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_output_format_from_str() {
+        assert!(matches!(OutputFormat::from_str("PAF"), Ok(OutputFormat::Paf)));
+        assert!(matches!(OutputFormat::from_str("default"), Ok(OutputFormat::Default)));
+        let err = OutputFormat::from_str("psf").unwrap_err();
+        assert!(err.contains("'psf'"));
+        assert!(err.contains(OUTPUT_FORMAT_NAMES));
+        #[cfg(feature = "bam")]
+        assert!(err.ends_with(", bam"));
+    }
+
+    #[test]
+    fn test_evalue_column() {
+        let options = OutputOptions { evalue: true, ..OutputOptions::default() };
+        // 4bp match, p = 0.625^2 + 0.125^2 + 0.125^2 + 0.125^2 = 0.4375, 8 * 6 * p^4 = 1.76
+        assert!(render(&OutputFormat::Default, &options).ends_with("Len: 4  Strand: +  E: 1.76e0\n"));
+        assert!(render(&OutputFormat::Paf, &options).ends_with("\tev:f:1.76e0\n"));
+        assert!(!render(&OutputFormat::Default, &OutputOptions::default()).contains("E:"));
+    }
+
+    #[test]
+    fn test_score_of_exact_match() {
+        let reference = b"GGATCCTTAGCATGCAAGTCTAGCTACGGATCGTTAC";
+        let query = &reference[4..34];
+        let matches = vec![Match::new(4, 0, 30)];
+        let options = OutputOptions { score: true, ..OutputOptions::default() };
+        let write = |format: &OutputFormat| {
+            let mut out = Vec::new();
+            write_matches_in_format(&mut out, &matches, "q", format, reference, query, None, &options).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(write(&OutputFormat::Default), "> Query: q\n  Ref: 5  Query: 1  Len: 30  Strand: +  Score: 30\n");
+        assert!(write(&OutputFormat::Paf).ends_with("\tAS:i:30\n"));
+        let sam = write(&OutputFormat::Sam);
+        let record = sam.lines().find(|l| !l.starts_with('@')).unwrap();
+        assert_eq!(record.split('\t').nth(11), Some("AS:i:30"));
+        assert!(!render(&OutputFormat::Sam, &OutputOptions::default()).contains("AS:i:"));
+    }
+
+    #[test]
+    fn test_paf_delimiter() {
+        let options = OutputOptions {
+            delimiter: OutputOptions::parse_delimiter(",").unwrap(),
+            ..OutputOptions::default()
+        };
+        let paf = render(&OutputFormat::Paf, &options);
+        assert_eq!(paf, "q,6,1,5,+,reference,8,2,6,4,4,60\n");
+    }
+
+    #[test]
+    fn test_ref_name_in_paf_and_sam() {
+        let options = OutputOptions { ref_name: "chrX".to_string(), ..OutputOptions::default() };
+        let paf = render(&OutputFormat::Paf, &options);
+        assert_eq!(paf.split('\t').nth(5), Some("chrX"));
+
+        let sam = render(&OutputFormat::Sam, &options);
+        assert!(sam.contains("@SQ\tSN:chrX\tLN:8\n"));
+        let record = sam.lines().find(|line| !line.starts_with('@')).unwrap();
+        assert_eq!(record.split('\t').nth(2), Some("chrX"));
+        assert!(!sam.contains(DEFAULT_REF_NAME));
+    }
+
+    #[test]
+    fn test_paf_tags_mark_primary_and_secondary() {
+        // Two reference copies of the query region: the full-length hit is primary
+        let reference = b"GGACGTACGTCCCCACGTACCTAA";
+        let query = b"TACGTACGTAA";
+        let matches = vec![Match::new(2, 1, 8), Match::new(14, 1, 5), Match::new(21, 8, 3)];
+        let options = OutputOptions { paf_tags: true, ..OutputOptions::default() };
+
+        let mut out = Vec::new();
+        write_matches_in_format(&mut out, &matches, "q", &OutputFormat::Paf, reference, query, None, &options).unwrap();
+        let tags: Vec<Vec<String>> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.split('\t').skip(12).map(str::to_string).collect())
+            .collect();
+        assert_eq!(tags[0], vec!["NM:i:0", "dv:f:0", "tp:A:P"]);
+        assert_eq!(tags[1], vec!["NM:i:0", "dv:f:0", "tp:A:S"]);
+        // Mostly outside the primary hit, so primary for its own region
+        assert_eq!(tags[2], vec!["NM:i:0", "dv:f:0", "tp:A:P"]);
+
+        let untagged = render(&OutputFormat::Paf, &OutputOptions::default());
+        assert_eq!(untagged.trim_end().split('\t').count(), 12);
+    }
+
+    #[test]
+    fn test_paf_precision() {
+        let options = OutputOptions {
+            precision: Some(3),
+            paf_tags: true,
+            evalue: true,
+            ..OutputOptions::default()
+        };
+        let paf = render(&OutputFormat::Paf, &options);
+        let fields: Vec<&str> = paf.trim_end().split('\t').collect();
+        assert_eq!(fields[13], "dv:f:0.000");
+        assert_eq!(fields[15], "ev:f:1.759e0");
+        // Integer columns are unaffected
+        assert_eq!(fields[..12].join("\t"), render(&OutputFormat::Paf, &OutputOptions::default()).trim_end());
+    }
+
+    #[test]
+    fn test_reverse_coords_descending() {
+        let matches = vec![Match::new(2, 1, 4).with_strand(Strand::Reverse), Match::new(0, 0, 3)];
+        let options = OutputOptions { reverse_coords: ReverseCoords::Descending, ..OutputOptions::default() };
+
+        let mut out = Vec::new();
+        write_matches_in_format(&mut out, &matches, "q", &OutputFormat::Default, b"AAACGTAA", b"TACGTT", None, &options).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "> Query: q\n  Ref: 3  Query: 5  Len: 4  Strand: -  RC Query: 2\n  Ref: 1  Query: 1  Len: 3  Strand: +\n");
+
+        let mut out = Vec::new();
+        write_matches_in_format(&mut out, &matches, "q", &OutputFormat::Delta, b"AAACGTAA", b"TACGTT", None, &options).unwrap();
+        let delta = String::from_utf8(out).unwrap();
+        assert!(delta.contains("\n3 6 5 2 8 6 4\n0\n"));
+        assert!(delta.ends_with("\n1 3 1 3 8 6 3\n0\n"));
+
+        let ascending = render(&OutputFormat::Default, &OutputOptions::default());
+        assert!(ascending.contains("Query: 2"));
+    }
+
+    #[test]
+    fn test_raw_reverse_coords() {
+        // ACGT at query 0..4 matches the reference on the reverse strand; on the reverse
+        // complement of the 7-base query it lies at 3..7
+        let matches = vec![Match::new(2, 0, 4).with_strand(Strand::Reverse), Match::new(0, 4, 3)];
+        let raw = OutputOptions { raw_reverse_coords: true, ..OutputOptions::default() };
+        let write = |format: &OutputFormat, options: &OutputOptions| {
+            let mut out = Vec::new();
+            write_matches_in_format(&mut out, &matches, "q", format, b"AAACGTAA", b"ACGTGGG", None, options).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let remapped = write(&OutputFormat::Default, &OutputOptions::default());
+        assert_eq!(remapped, "> Query: q\n  Ref: 3  Query: 1  Len: 4  Strand: -  RC Query: 4\n  Ref: 1  Query: 5  Len: 3  Strand: +\n");
+        assert_eq!(write(&OutputFormat::Default, &raw), "> Query: q\n  Ref: 3  Query: 4  Len: 4  Strand: -  RC Query: 4\n  Ref: 1  Query: 5  Len: 3  Strand: +\n");
+        assert!(write(&OutputFormat::Delta, &OutputOptions::default()).contains("\n3 6 1 4 8 7 4\n"));
+        assert!(write(&OutputFormat::Delta, &raw).contains("\n3 6 4 7 8 7 4\n"));
+    }
+
+    #[test]
+    fn test_reverse_match_conventions_format_identically() {
+        let forward_coords = vec![Match::new(2, 1, 4).with_strand(Strand::Reverse)];
+        let rev_comp_coords = vec![Match::new(2, 1, 4)
+            .with_strand(Strand::Reverse)
+            .with_query_coords(QueryCoords::ReverseComplement)];
+
+        for format in [OutputFormat::Default, OutputFormat::Delta, OutputFormat::Paf, OutputFormat::Sam, OutputFormat::Maf] {
+            let write = |matches: &[Match]| {
+                let mut out = Vec::new();
+                write_matches_in_format(&mut out, matches, "q", &format, b"AAACGTAA", b"TACGTT", None, &OutputOptions::default()).unwrap();
+                String::from_utf8(out).unwrap()
+            };
+            assert_eq!(write(&forward_coords), write(&rev_comp_coords), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_maf_block() {
+        let maf = render(&OutputFormat::Maf, &OutputOptions::default());
+        assert!(maf.starts_with("##maf version=1\n"));
+
+        let rows: Vec<Vec<&str>> = maf
+            .lines()
+            .filter(|line| line.starts_with("s "))
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], vec!["s", "reference", "2", "4", "+", "8", "ACGT"]);
+        assert_eq!(rows[1], vec!["s", "q", "1", "4", "+", "6", "ACGT"]);
+        assert_eq!(rows[0][6].len(), rows[1][6].len());
+
+        // A reverse match is reported on the query's minus strand
+        let reverse = vec![Match::new(0, 0, 3).with_strand(Strand::Reverse)];
+        let mut out = Vec::new();
+        write_matches_in_format(&mut out, &reverse, "q", &OutputFormat::Maf, b"GTAAA", b"TACGTT", None, &OutputOptions::default()).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("s q 3 3 - 6 GTA\n"));
+    }
+
+    #[test]
+    fn test_gapped_maf_block() {
+        // The reference carries two extra bases between the two halves of the query
+        let (left, right) = (b"ACGTTGCAAGTCCAT", b"GGACCTTGATCGAAC");
+        let query = [&left[..], right].concat();
+        let reference = [&b"GG"[..], left, b"TT", right, b"GG"].concat();
+        let alignment = Alignment {
+            ref_start: 2,
+            ref_end: 34,
+            query_start: 0,
+            query_end: 30,
+            strand: Strand::Forward,
+            anchors: Vec::new(),
+            score: 26,
+        };
+        let mut out = Vec::new();
+        write_alignments_maf(&mut out, &[alignment], "q", &reference, &query, &OutputOptions::default()).unwrap();
+        let maf = String::from_utf8(out).unwrap();
+        assert!(maf.contains("a score=26\n"));
+
+        let rows: Vec<Vec<&str>> = maf
+            .lines()
+            .filter(|line| line.starts_with("s "))
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][..6], ["s", "reference", "2", "32", "+", "36"]);
+        assert_eq!(rows[1][..6], ["s", "q", "0", "30", "+", "30"]);
+        assert_eq!(rows[0][6].len(), rows[1][6].len());
+        assert_eq!(rows[0][6].as_bytes(), &reference[2..34]);
+        assert_eq!(rows[1][6].matches('-').count(), 2);
+    }
+
+    #[test]
+    fn test_match_across_origin_is_split() {
+        // The last 2 and first 3 bases of a circular reference
+        let matches = vec![Match::new(5, 0, 5)];
+        let write = |format: &OutputFormat| {
+            let mut out = Vec::new();
+            write_matches_in_format(&mut out, &matches, "q", format, b"GTAAACC", b"CCGTA", None, &OutputOptions::default()).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let paf = write(&OutputFormat::Paf);
+        let spans: Vec<Vec<&str>> = paf.lines().map(|line| line.split('\t').skip(2).take(7).collect()).collect();
+        assert_eq!(spans, vec![
+            vec!["0", "2", "+", "reference", "7", "5", "7"],
+            vec!["2", "5", "+", "reference", "7", "0", "3"],
+        ]);
+        let maf = write(&OutputFormat::Maf);
+        assert!(maf.contains("s reference 5 2 + 7 CC\ns q 0 2 + 5 CC\n"));
+        assert!(maf.contains("s reference 0 3 + 7 GTA\ns q 2 3 + 5 GTA\n"));
+
+        // The default format shows the match whole
+        assert!(write(&OutputFormat::Default).contains("Ref: 6  Query: 1  Len: 5"));
+    }
+
+    #[test]
+    fn test_sam_soft_clips() {
+        let sam = render(&OutputFormat::Sam, &OutputOptions::default());
+        let record = sam.lines().find(|l| !l.starts_with('@')).unwrap();
+        let fields: Vec<&str> = record.split('\t').collect();
+        assert_eq!(fields[5], "1S4M1S");
+        assert_eq!(fields[9], "TACGTT");
+    }
+
+    #[test]
+    fn test_paired_sam_flags_and_mates() {
+        // Reference: TTACGTACGGATCCGGTTCAAGCTTGG
+        let first_read = b"ACGTACGG";
+        let second_read = b"CAAGCTTGACC"; // reverse complement of ref 17..26, then two unaligned bases
+        let first_match = Match::new(2, 0, 8);
+        let second_match = Match::new(17, 0, 9).with_strand(Strand::Reverse);
+        let first = Mate { name: "pair/1", sequence: first_read, mapping: Some(&first_match) };
+        let second = Mate { name: "pair/2", sequence: second_read, mapping: Some(&second_match) };
+
+        let mut out = Vec::new();
+        write_paired_sam(&mut out, DEFAULT_REF_NAME, &first, &second).unwrap();
+        let records: Vec<Vec<String>> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.split('\t').map(str::to_string).collect())
+            .collect();
+        assert_eq!(records.len(), 2);
+
+        // QNAME FLAG RNAME POS MAPQ CIGAR RNEXT PNEXT TLEN
+        assert_eq!(records[0][..9], ["pair", "99", "reference", "3", "60", "8M", "=", "18", "24"]);
+        assert_eq!(records[1][..9], ["pair", "147", "reference", "18", "60", "2S9M", "=", "3", "-24"]);
+        assert_eq!(records[1][9], "GGTCAAGCTTG");
+
+        // Without a mapping for the second mate, both records sit at the first mate's position
+        let unmapped = Mate { mapping: None, ..second };
+        let mut out = Vec::new();
+        write_paired_sam(&mut out, DEFAULT_REF_NAME, &first, &unmapped).unwrap();
+        let sam = String::from_utf8(out).unwrap();
+        let flags: Vec<&str> = sam.lines().map(|line| line.split('\t').nth(1).unwrap()).collect();
+        assert_eq!(flags, vec!["73", "133"]);
+        assert!(sam.lines().all(|line| line.split('\t').nth(3) == Some("3")));
+    }
+
+    #[test]
+    fn test_sam_qual_from_fastq() {
+        let fastq = crate::parse_fastq_bytes(b"@read\nTTACGTG\n+\n#+5?IIA\n");
+        let (_, query, quality) = &fastq[0];
+        let mut out = Vec::new();
+        write_matches_in_format(&mut out, &[Match::new(2, 2, 4)], "q", &OutputFormat::Sam, b"AAACGTAA", query, Some(quality), &OutputOptions::default()).unwrap();
+        let sam = String::from_utf8(out).unwrap();
+        let fields: Vec<&str> = sam.lines().last().unwrap().split('\t').collect();
+        assert_eq!(fields[5], "2S4M1S");
+        assert_eq!((fields[9], fields[10]), ("TTACGTG", "#+5?IIA"));
+
+        // The aligned segment's qualities sit under its bases
+        let (seq, qual) = (fields[9].as_bytes(), fields[10].as_bytes());
+        assert_eq!((&seq[2..6], &qual[2..6]), (&b"ACGT"[..], &b"5?II"[..]));
+    }
+
+    #[test]
+    fn test_sam_no_clip_for_full_length_match() {
+        let mut out = Vec::new();
+        write_matches_in_format(&mut out, &[Match::new(2, 0, 4)], "q", &OutputFormat::Sam, b"AAACGTAA", b"ACGT", None, &OutputOptions::default()).unwrap();
+        let sam = String::from_utf8(out).unwrap();
+        assert!(sam.lines().any(|l| l.split('\t').nth(5) == Some("4M")));
+    }
+
+    #[test]
+    fn test_sam_single_primary_record() {
+        let matches = vec![Match::new(0, 0, 3), Match::new(2, 1, 4), Match::new(5, 2, 3)];
+        let mut out = Vec::new();
+        write_matches_in_format(&mut out, &matches, "q", &OutputFormat::Sam, b"AAACGTAACGT", b"TACGTT", None, &OutputOptions::default()).unwrap();
+        let sam = String::from_utf8(out).unwrap();
+        let flags: Vec<u16> = sam
+            .lines()
+            .filter(|l| !l.starts_with('@'))
+            .map(|l| l.split('\t').nth(1).unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(flags.len(), 3);
+        assert_eq!(flags.iter().filter(|&&flag| flag & FLAG_SECONDARY == 0).count(), 1);
+        assert_eq!(flags, vec![FLAG_SECONDARY, 0, FLAG_SECONDARY]);
+    }
+
+    #[test]
+    fn test_default_format_strand_column() {
+        // One forward and one reverse match of a 6-base query
+        let matches = vec![Match::new(2, 1, 4), Match::new(3, 2, 3).with_strand(Strand::Reverse)];
+        let mut out = Vec::new();
+        write_matches_in_format(&mut out, &matches, "q", &OutputFormat::Default, b"AATACGTA", b"TACGTT", None, &OutputOptions::default()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().skip(1).collect();
+        assert_eq!(lines, vec!["  Ref: 3  Query: 2  Len: 4  Strand: +", "  Ref: 4  Query: 3  Len: 3  Strand: -  RC Query: 2"]);
+    }
+
+    #[test]
+    fn test_write_buffered_into_vec() {
+        let matches = vec![Match::new(0, 0, 4), Match::new(6, 2, 3)];
+        // A buffer smaller than the output still delivers all of it, in order
+        let out = write_buffered(Vec::new(), 8, |out| {
+            write_matches_in_format(out, &matches, "q", &OutputFormat::Default, b"ACGTACCGT", b"ACGTAC", None, &OutputOptions::default())
+        })
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "> Query: q\n  Ref: 1  Query: 1  Len: 4  Strand: +\n  Ref: 7  Query: 3  Len: 3  Strand: +\n"
+        );
+    }
+
+    #[test]
+    fn test_scored_alignments_rank_by_score() {
+        let flags = |matches: &[Match]| -> Vec<u16> {
+            sam_records(matches, "q", b"ACGTACGTACGTACGTACGT", None, None).iter().map(|r| r.flag).collect()
+        };
+        // Without scores the longer match is primary
+        let exact = vec![Match::new(0, 0, 12), Match::new(40, 12, 8)];
+        assert_eq!(flags(&exact), vec![0, FLAG_SECONDARY]);
+
+        // As extended alignments, the shorter one has the better DP score
+        let extended = vec![Match::new(0, 0, 12).with_score(4), Match::new(40, 12, 8).with_score(8)];
+        assert_eq!(flags(&extended), vec![FLAG_SECONDARY, 0]);
+        assert_eq!(primary_matches(&extended), vec![true, true]);
+        let overlapping = vec![Match::new(0, 0, 12).with_score(4), Match::new(40, 4, 8).with_score(8)];
+        assert_eq!(primary_matches(&overlapping), vec![false, true]);
+    }
+
+    #[test]
+    fn test_best_path_block() {
+        let chain = Chain {
+            matches: vec![Match::new(10, 0, 5), Match::new(20, 10, 5)],
+            score: 10,
+        };
+        let mut out = Vec::new();
+        write_best_path(&mut out, Some(&chain), "q").unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "> Query: q\n  Ref: 11-25  Query: 1-15  Anchors: 2  Score: 10\n"
+        );
+    }
+
+    #[test]
+    fn test_gap_report_single_deletion() {
+        let chain = Chain {
+            matches: vec![Match::new(0, 0, 10), Match::new(13, 10, 10)],
+            score: 17,
+        };
+        let mut out = Vec::new();
+        write_gap_report(&mut out, Some(&chain), "q").unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "q\t11\t11\tDEL\t3\n");
+    }
+
+    #[test]
+    fn test_write_fasta_wraps() {
+        let mut out = Vec::new();
+        write_fasta(&mut out, "chr1:1-10", b"ACGTACGTAC", 4).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), ">chr1:1-10\nACGT\nACGT\nAC\n");
+    }
+
+    #[test]
+    fn test_buffered_output_matches_unbuffered() {
+        let matches: Vec<Match> = (0..200).map(|i| Match::new(i % 5, i % 3, 1)).collect();
+        let options = OutputOptions::default();
+
+        for format in [OutputFormat::Default, OutputFormat::Paf, OutputFormat::Sam] {
+            let mut direct = Vec::new();
+            write_matches_in_format(&mut direct, &matches, "q", &format, b"ACGTACGT", b"ACGT", None, &options).unwrap();
+
+            let mut buffered = BufWriter::with_capacity(64, Vec::new());
+            write_matches_in_format(&mut buffered, &matches, "q", &format, b"ACGTACGT", b"ACGT", None, &options).unwrap();
+            assert_eq!(buffered.into_inner().unwrap(), direct);
+        }
     }
 }
@@ -0,0 +1,227 @@
+//! Streaming reader for PAF alignments written by HelixAlign or other tools, so they can be
+//! re-emitted in the other output formats
+//!
+//! Only the twelve mandatory columns are read; optional SAM-style tags are ignored. A record
+//! keeps its query and target spans separately, as an aligner with gaps reports spans of
+//! different lengths. Only ungapped records, with equal spans, convert to a `Match`; the coords
+//! and BED writers here take any record.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+use crate::suffix_array::{Match, Strand};
+
+/// One PAF line, with 0-based half-open query and target intervals
+#[derive(Debug, Clone, PartialEq)]
+pub struct PafRecord {
+    pub query_name: String,
+    pub query_len: usize,
+    pub query_start: usize,
+    pub query_end: usize,
+    pub strand: Strand,
+    pub target_name: String,
+    pub target_len: usize,
+    pub target_start: usize,
+    pub target_end: usize,
+    pub matching_bases: usize,
+    pub alignment_len: usize, // alignment block length, including gaps
+    pub mapq: u8,
+}
+
+impl PafRecord {
+    /// The record as a match in canonical coordinates, or `None` when its query and target spans
+    /// differ, which an ungapped match cannot represent
+    pub fn to_match(&self) -> Option<Match> {
+        let len = self.target_end - self.target_start;
+        (self.query_end - self.query_start == len)
+            .then(|| Match::new(self.target_start, self.query_start, len).with_strand(self.strand))
+    }
+
+    /// Percent identity of the alignment block, as matching bases over its length
+    pub fn identity(&self) -> f64 {
+        100.0 * self.matching_bases as f64 / self.alignment_len.max(1) as f64
+    }
+}
+
+/// Iterator over the records of PAF text, skipping blank and `#` comment lines
+pub struct PafReader<R: BufRead> {
+    input: R,
+    line_number: usize,
+    line: String,
+}
+
+impl<R: BufRead> PafReader<R> {
+    pub fn new(input: R) -> Self {
+        Self { input, line_number: 0, line: String::new() }
+    }
+}
+
+impl<R: BufRead> Iterator for PafReader<R> {
+    type Item = io::Result<PafRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.input.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            self.line_number += 1;
+            let line = self.line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            return Some(parse_paf_line(line).map_err(|message| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("PAF line {}: {}", self.line_number, message))
+            }));
+        }
+    }
+}
+
+/// Parse one tab-separated PAF line
+pub fn parse_paf_line(line: &str) -> Result<PafRecord, String> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() < 12 {
+        return Err(format!("expected at least 12 columns, found {}", fields.len()));
+    }
+    let number = |column: usize| -> Result<usize, String> {
+        fields[column]
+            .parse()
+            .map_err(|_| format!("invalid number '{}' in column {}", fields[column], column + 1))
+    };
+    let strand = match fields[4] {
+        "+" => Strand::Forward,
+        "-" => Strand::Reverse,
+        other => return Err(format!("invalid strand '{}'", other)),
+    };
+    let (query_len, query_start, query_end) = (number(1)?, number(2)?, number(3)?);
+    let (target_len, target_start, target_end) = (number(6)?, number(7)?, number(8)?);
+    if query_start > query_end || query_end > query_len || target_start > target_end || target_end > target_len {
+        return Err("coordinates out of range".to_string());
+    }
+    Ok(PafRecord {
+        query_name: fields[0].to_string(),
+        query_len,
+        query_start,
+        query_end,
+        strand,
+        target_name: fields[5].to_string(),
+        target_len,
+        target_start,
+        target_end,
+        matching_bases: number(9)?,
+        alignment_len: number(10)?,
+        mapq: fields[11].parse().map_err(|_| format!("invalid mapping quality '{}'", fields[11]))?,
+    })
+}
+
+/// Stream the records of a PAF file; a path of "-" reads from stdin
+pub fn open_paf(path: &str) -> io::Result<PafReader<Box<dyn BufRead>>> {
+    let input: Box<dyn BufRead> = if path == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+    Ok(PafReader::new(input))
+}
+
+/// Read every record of a PAF file; a path of "-" reads from stdin
+pub fn parse_paf(path: &str) -> io::Result<Vec<PafRecord>> {
+    open_paf(path)?.collect()
+}
+
+/// Write records as show-coords style TSV, 1-based and inclusive, with the query start and end
+/// swapped on the reverse strand
+pub fn write_paf_coords<W: Write>(out: &mut W, records: &[PafRecord]) -> io::Result<()> {
+    writeln!(out, "#ref_start\tref_end\tquery_start\tquery_end\tref_span\tquery_span\tidentity\tref_name\tquery_name")?;
+    for record in records {
+        let (query_start, query_end) = match record.strand {
+            Strand::Forward => (record.query_start + 1, record.query_end),
+            Strand::Reverse => (record.query_end, record.query_start + 1),
+        };
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{:.2}\t{}\t{}",
+            record.target_start + 1,
+            record.target_end,
+            query_start,
+            query_end,
+            record.target_end - record.target_start,
+            record.query_end - record.query_start,
+            record.identity(),
+            record.target_name,
+            record.query_name
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the target interval of each record as BED6, named after the query and scored with
+/// its mapping quality
+pub fn write_paf_bed<W: Write>(out: &mut W, records: &[PafRecord]) -> io::Result<()> {
+    for record in records {
+        let strand = if record.strand == Strand::Reverse { '-' } else { '+' };
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            record.target_name, record.target_start, record.target_end, record.query_name, record.mapq, strand
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{write_matches_in_format, OutputFormat, OutputOptions};
+
+    #[test]
+    fn test_paf_line_to_coords() {
+        let paf = "# from another aligner\n\
+                   read1\t40\t5\t17\t-\tchr2\t1000\t100\t112\t12\t12\t60\ttp:A:P\n\n";
+        let records: Vec<PafRecord> = PafReader::new(paf.as_bytes()).collect::<io::Result<_>>().unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!((record.query_name.as_str(), record.target_name.as_str()), ("read1", "chr2"));
+        let alignment = record.to_match().unwrap();
+        assert_eq!(alignment, Match::new(100, 5, 12).with_strand(Strand::Reverse));
+
+        let options = OutputOptions { ref_name: record.target_name.clone(), ..OutputOptions::default() };
+        let (reference, query) = (vec![b'N'; record.target_len], vec![b'N'; record.query_len]);
+        let mut out = Vec::new();
+        write_matches_in_format(&mut out, &[alignment], &record.query_name, &OutputFormat::Default, &reference, &query, None, &options).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "> Query: read1\n  Ref: 101  Query: 6  Len: 12  Strand: -  RC Query: 24\n");
+
+        let mut out = Vec::new();
+        write_paf_coords(&mut out, &records).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap().lines().nth(1),
+            Some("101\t112\t17\t6\t12\t12\t100.00\tchr2\tread1")
+        );
+
+        let err = PafReader::new("read1\t40\t5\n".as_bytes()).next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "PAF line 1: expected at least 12 columns, found 3");
+    }
+
+    #[test]
+    fn test_gapped_paf_record() {
+        // A 2-base deletion from the query: 10 query bases against 12 target bases
+        let record = parse_paf_line("q1\t10\t0\t10\t+\tchr\t20\t0\t12\t10\t12\t60").unwrap();
+        assert_eq!(record.to_match(), None);
+
+        let mut out = Vec::new();
+        write_paf_coords(&mut out, std::slice::from_ref(&record)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().nth(1), Some("1\t12\t1\t10\t12\t10\t83.33\tchr\tq1"));
+        let mut out = Vec::new();
+        write_paf_bed(&mut out, &[record]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "chr\t0\t12\tq1\t60\t+\n");
+
+        // Forcing the target span onto the query is rejected rather than read past its end
+        let (reference, query) = (vec![b'A'; 20], vec![b'A'; 10]);
+        for format in [OutputFormat::Paf, OutputFormat::Sam, OutputFormat::Maf] {
+            let result = write_matches_in_format(&mut Vec::new(), &[Match::new(0, 0, 12)], "q1", &format, &reference, &query, None, &OutputOptions::default());
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
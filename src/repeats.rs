@@ -0,0 +1,425 @@
+//! Repeat detection on top of the suffix and LCP arrays
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::sequence::reverse_complement_preserving_case;
+use crate::suffix_array::{Match, SparseSuffixArray, Strand};
+
+/// Two arms of a sequence that are reverse complements of each other, e.g. the stem of a hairpin
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvertedRepeat {
+    pub left_start: usize,  // 0-based start of the upstream arm
+    pub right_start: usize, // 0-based start of the downstream arm
+    pub arm_len: usize,
+    pub spacer: usize,      // bases between the two arms
+}
+
+/// Window length of the DUST low-complexity scan
+pub const DUST_WINDOW: usize = 64;
+
+/// DUST score above which a window is low complexity (a DUST level of 20)
+pub const DUST_THRESHOLD: f64 = 2.0;
+
+/// A tandem repeat: consecutive copies of a unit starting at `start`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TandemRepeat {
+    pub start: usize,    // 0-based start in the sequence
+    pub unit_len: usize, // length of the repeated unit
+    pub copies: usize,   // number of complete copies of the unit
+    pub len: usize,      // length of the whole region, including a trailing partial copy
+}
+
+impl TandemRepeat {
+    /// The repeated unit itself
+    pub fn unit<'a>(&self, sequence: &'a [u8]) -> &'a [u8] {
+        &sequence[self.start..self.start + self.unit_len]
+    }
+}
+
+/// Find tandem repeats with a unit of at least `min_unit` bases repeated at least `min_copies` times
+///
+/// Two suffixes that are adjacent in the suffix array and start `p` bases apart, with an LCP of
+/// at least `p`, spell a periodic region of period `p` that is `lcp + p` bases long. Regions
+/// contained in a larger region of the same (or a dividing) period are dropped.
+///
+/// A sparse index, or one built without its LCP array, is rebuilt densely, since the scan needs
+/// every suffix and its LCP.
+pub fn find_tandem_repeats(index: &SparseSuffixArray, min_unit: usize, min_copies: usize) -> Vec<TandemRepeat> {
+    if index.sampling_rate() > 1 || !index.has_lcp() {
+        return match SparseSuffixArray::new(index.sequence(), 1) {
+            Ok(dense) => find_tandem_repeats(&dense, min_unit, min_copies),
+            Err(_) => Vec::new(),
+        };
+    }
+    let suffix_array = index.suffix_array();
+    let lcp_array = index.lcp_array();
+    let min_unit = min_unit.max(1);
+
+    let mut candidates = Vec::new();
+    for i in 1..suffix_array.len() {
+        let (a, b) = (suffix_array[i - 1], suffix_array[i]);
+        let unit_len = a.abs_diff(b);
+        let lcp = lcp_array[i];
+        if unit_len < min_unit || lcp < unit_len {
+            continue;
+        }
+
+        let len = lcp + unit_len;
+        let copies = len / unit_len;
+        if copies >= min_copies.max(2) {
+            candidates.push(TandemRepeat {
+                start: a.min(b),
+                unit_len,
+                copies,
+                len,
+            });
+        }
+    }
+
+    // Longest regions first so that contained ones can be dropped in one pass
+    candidates.sort_by(|x, y| y.len.cmp(&x.len).then_with(|| x.start.cmp(&y.start)));
+
+    let mut repeats: Vec<TandemRepeat> = Vec::new();
+    for candidate in candidates {
+        let is_contained = repeats.iter().any(|r| {
+            candidate.unit_len % r.unit_len == 0
+                && r.start <= candidate.start
+                && r.start + r.len >= candidate.start + candidate.len
+        });
+        if !is_contained {
+            repeats.push(candidate);
+        }
+    }
+
+    repeats.sort_by(|x, y| x.start.cmp(&y.start).then_with(|| x.unit_len.cmp(&y.unit_len)));
+    repeats
+}
+
+/// Find inverted repeats with arms of at least `min_len` bases separated by at most `max_spacer`
+///
+/// The sequence is matched against its own reverse complement with the MEM search. A match
+/// between `sequence[p..p + len]` and the reverse complement of `sequence[r..r + len]` is an
+/// inverted repeat when the arms do not overlap; a match of a region with itself is a
+/// reverse-complement palindrome and is split into two halves with no spacer.
+pub fn find_inverted_repeats(sequence: &[u8], min_len: usize, max_spacer: usize) -> Vec<InvertedRepeat> {
+    let n = sequence.len();
+    let index = match SparseSuffixArray::new(sequence, 1) {
+        Ok(index) => index,
+        Err(_) => return Vec::new(),
+    };
+    let rev_comp = reverse_complement_preserving_case(sequence);
+
+    let mut repeats = Vec::new();
+    for m in crate::find_all_mems(&index, &rev_comp, min_len.max(1)) {
+        let (left_start, right_start) = (m.ref_pos, n - m.query_pos - m.len);
+        let (arm_len, spacer) = if left_start == right_start {
+            (m.len / 2, m.len % 2)
+        } else if left_start + m.len <= right_start {
+            (m.len, right_start - left_start - m.len)
+        } else {
+            // Overlapping arms, or the mirror image of a pair already reported
+            continue;
+        };
+        if arm_len >= min_len && spacer <= max_spacer {
+            repeats.push(InvertedRepeat {
+                left_start,
+                right_start: left_start + arm_len + spacer,
+                arm_len,
+                spacer,
+            });
+        }
+    }
+
+    repeats.sort_by_key(|r| (r.left_start, r.right_start));
+    repeats
+}
+
+/// Flag the bases of low-complexity regions with a DUST-like triplet score
+///
+/// Each `window`-base window scores `sum(c * (c - 1) / 2) / (l - 1)` over the counts `c` of its
+/// `l` ACGT triplets: near 0.5 for random sequence, about 15 for a dinucleotide repeat and
+/// `l / 2` for a homopolymer. Every base of a window scoring above `threshold` is masked.
+/// Triplets containing other characters (e.g. N) are not counted.
+pub fn low_complexity_mask(sequence: &[u8], window: usize, threshold: f64) -> Vec<bool> {
+    let n = sequence.len();
+    let window = window.min(n);
+    if window < 3 {
+        return vec![false; n];
+    }
+
+    let code = |base: u8| match base.to_ascii_uppercase() {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    };
+    let triplets: Vec<Option<usize>> = sequence
+        .windows(3)
+        .map(|t| Some(code(t[0])? * 16 + code(t[1])? * 4 + code(t[2])?))
+        .collect();
+
+    // +1 where a masked window starts and -1 where it ends
+    let mut depth = vec![0i64; n + 1];
+    let mut counter = TripletCounter::default();
+    for &triplet in &triplets[..window - 2] {
+        counter.add(triplet);
+    }
+    for start in 0..=n - window {
+        if start > 0 {
+            counter.remove(triplets[start - 1]);
+            counter.add(triplets[start + window - 3]);
+        }
+        if counter.score() > threshold {
+            depth[start] += 1;
+            depth[start + window] -= 1;
+        }
+    }
+
+    let mut covering = 0;
+    depth[..n]
+        .iter()
+        .map(|&change| {
+            covering += change;
+            covering > 0
+        })
+        .collect()
+}
+
+/// Triplet counts of the current DUST window, with the running number of identical pairs
+struct TripletCounter {
+    counts: [usize; 64],
+    pairs: usize,
+    counted: usize,
+}
+
+impl Default for TripletCounter {
+    fn default() -> Self {
+        Self { counts: [0; 64], pairs: 0, counted: 0 }
+    }
+}
+
+impl TripletCounter {
+    fn add(&mut self, triplet: Option<usize>) {
+        if let Some(t) = triplet {
+            self.pairs += self.counts[t];
+            self.counts[t] += 1;
+            self.counted += 1;
+        }
+    }
+
+    fn remove(&mut self, triplet: Option<usize>) {
+        if let Some(t) = triplet {
+            self.counts[t] -= 1;
+            self.pairs -= self.counts[t];
+            self.counted -= 1;
+        }
+    }
+
+    fn score(&self) -> f64 {
+        if self.counted < 2 {
+            0.0
+        } else {
+            self.pairs as f64 / (self.counted - 1) as f64
+        }
+    }
+}
+
+/// Drop matches whose reference span lies entirely within masked bases
+pub fn remove_low_complexity_matches(matches: &mut Vec<Match>, mask: &[bool]) {
+    matches.retain(|m| {
+        let end = (m.ref_pos + m.len).min(mask.len());
+        m.ref_pos >= end || !mask[m.ref_pos..end].iter().all(|&masked| masked)
+    });
+}
+
+/// Drop forward matches whose diagonal is within `width` of the main one, `|ref_pos - query_pos|
+/// <= width`, as a sequence aligned against itself matches there trivially or through slightly
+/// offset copies of itself; a width of 0 drops only the exact self-match
+///
+/// Reverse matches are kept: on the main diagonal they are reverse-complement palindromes.
+pub fn remove_near_diagonal_matches(matches: &mut Vec<Match>, width: usize) {
+    matches.retain(|m| m.strand == Strand::Reverse || m.ref_pos.abs_diff(m.query_pos) > width);
+}
+
+/// A query interval that matches the reference equally well at several places
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepeatFamily {
+    pub query_pos: usize, // 0-based start of the interval in the query
+    pub len: usize,
+    pub strand: Strand,
+    pub ref_positions: Vec<usize>, // 0-based reference starts, ascending
+}
+
+/// Group matches covering the same query interval on the same strand into repeat families
+///
+/// Only intervals placed at two or more reference positions form a family; matches must come
+/// from a MEM search for repeated placements to be reported at all.
+pub fn group_repeat_families(matches: &[Match]) -> Vec<RepeatFamily> {
+    let mut groups: BTreeMap<(usize, usize, bool), Vec<usize>> = BTreeMap::new();
+    for m in matches {
+        groups
+            .entry((m.query_pos, m.len, m.strand == Strand::Reverse))
+            .or_default()
+            .push(m.ref_pos);
+    }
+    groups
+        .into_iter()
+        .filter_map(|((query_pos, len, reverse), mut ref_positions)| {
+            ref_positions.sort_unstable();
+            ref_positions.dedup();
+            (ref_positions.len() > 1).then_some(RepeatFamily {
+                query_pos,
+                len,
+                strand: if reverse { Strand::Reverse } else { Strand::Forward },
+                ref_positions,
+            })
+        })
+        .collect()
+}
+
+/// Write the column header of `write_repeat_families`, once per table
+pub fn write_repeat_families_header<W: Write>(out: &mut W) -> io::Result<()> {
+    writeln!(out, "#query\tquery_start\tquery_end\tstrand\tcopies\tref_positions")
+}
+
+/// Write `query query_start query_end strand copies ref_positions` TSV, 1-based, with the
+/// reference positions comma-separated
+pub fn write_repeat_families<W: Write>(out: &mut W, query_name: &str, families: &[RepeatFamily]) -> io::Result<()> {
+    for family in families {
+        let positions: Vec<String> = family.ref_positions.iter().map(|p| (p + 1).to_string()).collect();
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            query_name,
+            family.query_pos + 1,
+            family.query_pos + family.len,
+            if family.strand == Strand::Reverse { '-' } else { '+' },
+            family.ref_positions.len(),
+            positions.join(",")
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench::synthetic_sequence;
+
+    #[test]
+    fn test_low_complexity_matches_suppressed() {
+        let left = synthetic_sequence(200, 7);
+        let right = synthetic_sequence(200, 11);
+        let reference = [left.clone(), vec![b'A'; 100], right.clone()].concat();
+
+        let mask = low_complexity_mask(&reference, DUST_WINDOW, DUST_THRESHOLD);
+        assert!(mask[200..300].iter().all(|&masked| masked));
+        assert!(mask[..100].iter().all(|&masked| !masked));
+        assert!(mask[350..].iter().all(|&masked| !masked));
+
+        let query = [vec![b'A'; 40], b"NN".to_vec(), right[100..140].to_vec()].concat();
+        let index = SparseSuffixArray::new(&reference, 1).unwrap();
+        let mut matches = crate::find_all_mems(&index, &query, 20);
+        assert!(matches.iter().any(|m| m.ref_pos >= 200 && m.ref_pos + m.len <= 300));
+
+        remove_low_complexity_matches(&mut matches, &mask);
+        assert_eq!(matches, vec![Match::new(400, 42, 40)]);
+    }
+
+    #[test]
+    fn test_near_diagonal_matches_removed() {
+        let mut matches = vec![
+            Match::new(0, 0, 100), // the self-match
+            Match::new(3, 0, 40), // a short period, offset by 3
+            Match::new(0, 5, 40), // and the same on the other side
+            Match::new(500, 100, 60), // a dispersed repeat
+            Match::new(10, 10, 30).with_strand(Strand::Reverse), // a palindrome
+        ];
+        remove_near_diagonal_matches(&mut matches, 5);
+        assert_eq!(matches, vec![Match::new(500, 100, 60), Match::new(10, 10, 30).with_strand(Strand::Reverse)]);
+
+        let mut matches = vec![Match::new(0, 0, 100), Match::new(3, 0, 40)];
+        remove_near_diagonal_matches(&mut matches, 0);
+        assert_eq!(matches, vec![Match::new(3, 0, 40)]);
+    }
+
+    #[test]
+    fn test_find_inverted_repeats() {
+        // Hairpin: arm, 6-base loop, reverse complement of the arm
+        let arm = b"TTGACCATGCAG";
+        let stem_end = b"CTGCATGGTCAA";
+        let sequence = [b"GCGTAGCTAC".as_slice(), arm, b"AGAGAG", stem_end, b"AGCTTCCGAT"].concat();
+
+        let repeats = find_inverted_repeats(&sequence, 10, 20);
+        assert_eq!(repeats, vec![InvertedRepeat { left_start: 10, right_start: 28, arm_len: 12, spacer: 6 }]);
+        assert_eq!(&sequence[28..40], stem_end);
+
+        // A loop longer than allowed is not reported
+        assert!(find_inverted_repeats(&sequence, 10, 5).is_empty());
+
+        // An EcoRI-like palindrome splits into two abutting arms
+        let palindrome = find_inverted_repeats(b"TTTCAGAATTCTGCCC", 4, 0);
+        assert_eq!(palindrome, vec![InvertedRepeat { left_start: 3, right_start: 8, arm_len: 5, spacer: 0 }]);
+
+        // A byte that is not valid UTF-8 outside the arms does not hide the repeat
+        let mut non_utf8 = sequence.clone();
+        non_utf8[0] = 0xff;
+        assert_eq!(find_inverted_repeats(&non_utf8, 10, 20), repeats);
+    }
+
+    #[test]
+    fn test_dinucleotide_low_complexity() {
+        let mask = low_complexity_mask(&b"AT".repeat(40), DUST_WINDOW, DUST_THRESHOLD);
+        assert!(mask.iter().all(|&masked| masked));
+    }
+
+    #[test]
+    fn test_dinucleotide_repeat() {
+        let sequence = b"ATATATAT";
+        let index = SparseSuffixArray::new(sequence, 1).unwrap();
+
+        let repeats = find_tandem_repeats(&index, 2, 2);
+        assert_eq!(repeats[0], TandemRepeat { start: 0, unit_len: 2, copies: 4, len: 8 });
+        assert_eq!(repeats[0].unit(sequence), b"AT");
+        assert!(repeats.iter().all(|r| r.unit_len == 2));
+    }
+
+    #[test]
+    fn test_repeat_in_context() {
+        let sequence = b"GCCAGTCAGTCAGTCAGTTGA";
+        let index = SparseSuffixArray::new(sequence, 1).unwrap();
+
+        let repeats = find_tandem_repeats(&index, 3, 3);
+        assert_eq!(repeats.len(), 1);
+        assert_eq!(repeats[0].start, 2);
+        assert_eq!(repeats[0].unit_len, 4);
+        assert_eq!(repeats[0].copies, 4);
+
+        assert!(find_tandem_repeats(&index, 3, 5).is_empty());
+    }
+
+    #[test]
+    fn test_segment_in_three_places_forms_one_family() {
+        let segment: &[u8] = b"GATTACAGGC";
+        let reference = [b"TT", segment, b"CC", segment, b"AA", segment, b"GG"].concat();
+        let query = [b"G", segment, b"T"].concat();
+        let index = SparseSuffixArray::new(&reference, 1).unwrap();
+        let matches = crate::algorithms::find_all_mems(&index, &query, 8);
+
+        let families = group_repeat_families(&matches);
+        assert_eq!(
+            families,
+            vec![RepeatFamily { query_pos: 1, len: 10, strand: Strand::Forward, ref_positions: vec![2, 14, 26] }]
+        );
+
+        let mut out = Vec::new();
+        write_repeat_families_header(&mut out).unwrap();
+        write_repeat_families(&mut out, "read1", &families).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "#query\tquery_start\tquery_end\tstrand\tcopies\tref_positions\nread1\t2\t11\t+\t3\t3,15,27\n"
+        );
+    }
+}
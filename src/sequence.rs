@@ -2,6 +2,8 @@
 
 use std::fmt;
 
+use crate::suffix_array::Strand;
+
 /// A DNA sequence that can be efficiently processed
 #[derive(Debug, Clone, PartialEq)]
 pub struct DnaSequence {
@@ -68,22 +70,162 @@ impl DnaSequence {
 
     /// Reverse complement of the DNA sequence
     pub fn reverse_complement(&self) -> Self {
-        let mut complement = Vec::with_capacity(self.sequence.len());
-        for &base in self.sequence.iter().rev() {
-            let comp_base = match base {
-                b'A' | b'a' => b'T',
-                b'T' | b't' => b'A',
-                b'G' | b'g' => b'C',
-                b'C' | b'c' => b'G',
-                _ => base, // Keep non-standard bases as is
-            };
-            complement.push(comp_base);
-        }
         DnaSequence {
-            sequence: complement,
+            sequence: self.sequence.iter().rev().map(|&base| complement_base(base)).collect(),
             description: format!("reverse complement of {}", self.description),
         }
     }
+
+    /// Complement of the DNA sequence, in the original order
+    pub fn complement(&self) -> Self {
+        DnaSequence {
+            sequence: self.sequence.iter().map(|&base| complement_base(base)).collect(),
+            description: format!("complement of {}", self.description),
+        }
+    }
+
+    /// Protein translation of the sequence in frame 0, with the standard genetic code
+    ///
+    /// Stop codons become `*` and codons with any base other than A, C, G, T or U become `X`;
+    /// trailing bases that do not fill a codon are ignored.
+    pub fn translate(&self) -> Vec<u8> {
+        self.sequence.chunks_exact(3).map(translate_codon).collect()
+    }
+}
+
+/// The standard genetic code, indexed by codon with bases ordered T, C, A, G
+const GENETIC_CODE: &[u8; 64] = b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+
+fn translate_codon(codon: &[u8]) -> u8 {
+    let mut index = 0;
+    for &base in codon {
+        let code = match base.to_ascii_uppercase() {
+            b'T' | b'U' => 0,
+            b'C' => 1,
+            b'A' => 2,
+            b'G' => 3,
+            _ => return b'X',
+        };
+        index = index * 4 + code;
+    }
+    GENETIC_CODE[index]
+}
+
+/// An open reading frame: a start codon and the codons up to the first in-frame stop codon
+#[derive(Debug, Clone, PartialEq)]
+pub struct Orf {
+    pub start: usize, // 0-based start on the forward strand
+    pub end: usize,   // exclusive end on the forward strand, including the stop codon
+    pub strand: Strand,
+    pub protein: Vec<u8>, // translation from the start codon, without the stop
+}
+
+/// Shortest ORF reported by default, in amino acids
+pub const DEFAULT_MIN_ORF_LEN: usize = 100;
+
+/// ORFs of at least `min_len` amino acids in all six reading frames, ordered by start
+///
+/// An ORF begins at the first ATG after the previous stop codon of its frame, so ORFs in one
+/// frame never nest; a reading frame that reaches the end of the sequence without a stop codon
+/// is not an ORF.
+pub fn find_orfs(sequence: &[u8], min_len: usize) -> Vec<Orf> {
+    let forward = DnaSequence { sequence: sequence.to_vec(), description: String::new() };
+    let reverse = forward.reverse_complement();
+    let len = sequence.len();
+
+    let mut orfs = Vec::new();
+    for (strand, strand_seq) in [(Strand::Forward, &forward), (Strand::Reverse, &reverse)] {
+        for frame in 0..3.min(len) {
+            let protein = DnaSequence { sequence: strand_seq.sequence[frame..].to_vec(), description: String::new() }.translate();
+            let mut orf_start = None;
+            for (i, &amino_acid) in protein.iter().enumerate() {
+                match (orf_start, amino_acid) {
+                    (None, b'M') => orf_start = Some(i),
+                    (Some(first), b'*') => {
+                        if i - first >= min_len {
+                            let (begin, end) = (frame + 3 * first, frame + 3 * (i + 1));
+                            let (start, end) = match strand {
+                                Strand::Forward => (begin, end),
+                                Strand::Reverse => (len - end, len - begin),
+                            };
+                            orfs.push(Orf { start, end, strand, protein: protein[first..i].to_vec() });
+                        }
+                        orf_start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    orfs.sort_by_key(|orf| orf.start);
+    orfs
+}
+
+/// Complement of a base or IUPAC code; complemented bases come out upper case
+pub(crate) fn complement_base(base: u8) -> u8 {
+    match base {
+        b'A' | b'a' => b'T',
+        b'T' | b't' => b'A',
+        b'G' | b'g' => b'C',
+        b'C' | b'c' => b'G',
+        b'R' | b'r' => b'Y',
+        b'Y' | b'y' => b'R',
+        b'K' | b'k' => b'M',
+        b'M' | b'm' => b'K',
+        b'B' | b'b' => b'V',
+        b'V' | b'v' => b'B',
+        b'D' | b'd' => b'H',
+        b'H' | b'h' => b'D',
+        _ => base, // Keep non-standard (and self-complementary S, W, N) bases as is
+    }
+}
+
+/// IUPAC-aware reverse complement of raw bytes that keeps lower case bases lower case
+/// Unlike `DnaSequence::reverse_complement`, any byte sequence is accepted, UTF-8 or not.
+pub(crate) fn reverse_complement_preserving_case(sequence: &[u8]) -> Vec<u8> {
+    sequence
+        .iter()
+        .rev()
+        .map(|&base| {
+            let complement = complement_base(base);
+            if base.is_ascii_lowercase() { complement.to_ascii_lowercase() } else { complement }
+        })
+        .collect()
+}
+
+/// Set of bases an IUPAC nucleotide code stands for, as a bitmask (A=1, C=2, G=4, T=8)
+/// Bytes that are not IUPAC codes stand for no base
+fn iupac_bases(code: u8) -> u8 {
+    match code.to_ascii_uppercase() {
+        b'A' => 0b0001,
+        b'C' => 0b0010,
+        b'G' => 0b0100,
+        b'T' | b'U' => 0b1000,
+        b'R' => 0b0101, // A or G
+        b'Y' => 0b1010, // C or T
+        b'S' => 0b0110, // C or G
+        b'W' => 0b1001, // A or T
+        b'K' => 0b1100, // G or T
+        b'M' => 0b0011, // A or C
+        b'B' => 0b1110, // not A
+        b'D' => 0b1101, // not C
+        b'H' => 0b1011, // not G
+        b'V' => 0b0111, // not T
+        b'N' => 0b1111,
+        _ => 0,
+    }
+}
+
+/// Whether two bases can be the same nucleotide, treating IUPAC ambiguity codes as the set of
+/// bases they stand for (so R matches A and G but not C); case is ignored
+pub fn iupac_matches(a: u8, b: u8) -> bool {
+    a.eq_ignore_ascii_case(&b) || iupac_bases(a) & iupac_bases(b) != 0
+}
+
+impl AsRef<[u8]> for DnaSequence {
+    fn as_ref(&self) -> &[u8] {
+        &self.sequence
+    }
 }
 
 impl fmt::Display for DnaSequence {
@@ -122,6 +264,12 @@ impl SequenceCollection {
     }
 }
 
+impl Default for SequenceCollection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -143,6 +291,44 @@ mod tests {
         assert_eq!(String::from_utf8_lossy(&rev_comp.sequence), "CGAT");
     }
 
+    #[test]
+    fn test_complement() {
+        let seq = DnaSequence::new("ATCG", "test_sequence".to_string());
+        assert_eq!(String::from_utf8_lossy(&seq.complement().sequence), "TAGC");
+        assert_eq!(seq.complement().reverse_complement().sequence, b"GCTA");
+    }
+
+    #[test]
+    fn test_translate() {
+        let seq = DnaSequence::new("ATGGCcTTTaaaTGAGG", "test_sequence".to_string());
+        assert_eq!(seq.translate(), b"MAFK*");
+        assert_eq!(DnaSequence::new("ATGNNN", String::new()).translate(), b"MX");
+    }
+
+    #[test]
+    fn test_single_long_orf() {
+        // A 120-codon ORF between flanks that contain no other start codon
+        let coding: Vec<u8> = (0..119).flat_map(|i| if i % 2 == 0 { *b"GCT" } else { *b"CTG" }).collect();
+        let sequence = [&b"CCCCC"[..], b"ATG", &coding, b"TAA", b"CCCCC"].concat();
+
+        let orfs = find_orfs(&sequence, 100);
+        assert_eq!(orfs.len(), 1);
+        assert_eq!(orfs[0].protein.len(), 120);
+        assert_eq!(orfs[0].protein[..3], *b"MAL");
+        assert_eq!((orfs[0].start, orfs[0].end, orfs[0].strand), (5, 5 + 3 * 121, Strand::Forward));
+        assert!(find_orfs(&sequence, 121).is_empty());
+    }
+
+    #[test]
+    fn test_iupac_matches() {
+        assert!(iupac_matches(b'R', b'A'));
+        assert!(iupac_matches(b'r', b'G'));
+        assert!(!iupac_matches(b'R', b'C'));
+        assert!(iupac_matches(b'N', b'T'));
+        assert!(!iupac_matches(b'A', b'C'));
+        assert!(!iupac_matches(b'-', b'A'));
+    }
+
     #[test]
     fn test_char_to_code() {
         assert_eq!(DnaSequence::char_to_code(b'A'), Some(0));
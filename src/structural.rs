@@ -0,0 +1,400 @@
+//! Structural differences between a reference and a query, read off the layout of their matches
+
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::suffix_array::{Match, Strand};
+
+/// Smallest event reported as a structural variant by default
+pub const DEFAULT_MIN_SV_SIZE: usize = 50;
+
+/// Kind of structural difference, relative to the reference
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SvType {
+    Insertion,     // extra bases in the query between two collinear blocks
+    Deletion,      // reference bases missing from the query between two collinear blocks
+    Inversion,     // a block aligned on the opposite strand to the rest of the query
+    Translocation, // a block out of order with the rest of the query
+}
+
+impl fmt::Display for SvType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SvType::Insertion => "INS",
+            SvType::Deletion => "DEL",
+            SvType::Inversion => "INV",
+            SvType::Translocation => "TRA",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A structural variant with 0-based, half-open intervals in both sequences
+///
+/// An insertion has an empty reference interval and a deletion an empty query interval, each
+/// placed at the breakpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructuralVariant {
+    pub kind: SvType,
+    pub ref_start: usize,
+    pub ref_end: usize,
+    pub query_start: usize,
+    pub query_end: usize,
+    pub size: usize,
+}
+
+/// Run of matches on one strand that are collinear without a large indel between them
+#[derive(Debug, Clone)]
+struct Block {
+    strand: Strand,
+    ref_start: usize,
+    ref_end: usize,
+    query_start: usize,
+    query_end: usize,
+    aligned: usize, // bases covered by the block's matches
+}
+
+impl Block {
+    fn new(m: &Match) -> Self {
+        Self {
+            strand: m.strand,
+            ref_start: m.ref_pos,
+            ref_end: m.ref_pos + m.len,
+            query_start: m.query_pos,
+            query_end: m.query_pos + m.len,
+            aligned: m.len,
+        }
+    }
+
+    /// Reference and query gaps from the end of the block to `m`, following the block's strand
+    fn gaps_to(&self, m: &Match) -> (i64, i64) {
+        let ref_gap = match self.strand {
+            Strand::Forward => m.ref_pos as i64 - self.ref_end as i64,
+            Strand::Reverse => self.ref_start as i64 - (m.ref_pos + m.len) as i64,
+        };
+        (ref_gap, m.query_pos as i64 - self.query_end as i64)
+    }
+
+    fn extend(&mut self, m: &Match) {
+        self.ref_start = self.ref_start.min(m.ref_pos);
+        self.ref_end = self.ref_end.max(m.ref_pos + m.len);
+        self.query_end = self.query_end.max(m.query_pos + m.len);
+        self.aligned += m.len;
+    }
+
+    fn variant(&self, kind: SvType, size: usize) -> StructuralVariant {
+        StructuralVariant {
+            kind,
+            ref_start: self.ref_start,
+            ref_end: self.ref_end,
+            query_start: self.query_start,
+            query_end: self.query_end,
+            size,
+        }
+    }
+}
+
+/// Split matches, in query order, into collinear blocks
+///
+/// A match joins the current block when it is on the same strand, does not step backwards in
+/// the reference, lies at most `max_gap` bases past the block in both sequences, and the two
+/// gaps differ by less than `min_size`.
+fn collinear_blocks(matches: &[Match], max_gap: usize, min_size: usize) -> Vec<Block> {
+    let mut sorted = matches.to_vec();
+    sorted.sort_by_key(|m| (m.query_pos, m.ref_pos));
+
+    let mut blocks: Vec<Block> = Vec::new();
+    for m in &sorted {
+        if let Some(block) = blocks.last_mut() {
+            let (ref_gap, query_gap) = block.gaps_to(m);
+            let in_order = match block.strand {
+                Strand::Forward => m.ref_pos >= block.ref_start,
+                Strand::Reverse => m.ref_pos + m.len <= block.ref_end,
+            };
+            if m.strand == block.strand
+                && in_order
+                && ref_gap <= max_gap as i64
+                && query_gap <= max_gap as i64
+                && ref_gap.abs_diff(query_gap) < min_size as u64
+            {
+                block.extend(m);
+                continue;
+            }
+        }
+        blocks.push(Block::new(m));
+    }
+    blocks
+}
+
+/// Classify the large-scale differences implied by a set of matches between one query and the reference
+///
+/// Matches are grouped into collinear blocks. The strand carrying most aligned bases is taken
+/// as the query's orientation, and blocks on the other strand are inversions. Among the rest, the
+/// heaviest chain of blocks whose reference order agrees with their query order forms the
+/// backbone; blocks off it are translocations. Indels are read from the gaps between consecutive
+/// backbone blocks, not counting the translocated blocks inside them. Events shorter than
+/// `min_size` are dropped; the result is ordered by reference position.
+pub fn structural_variants(matches: &[Match], max_gap: usize, min_size: usize) -> Vec<StructuralVariant> {
+    let blocks = collinear_blocks(matches, max_gap, min_size);
+    let aligned_on = |strand| blocks.iter().filter(|b| b.strand == strand).map(|b| b.aligned).sum::<usize>();
+    let strand = if aligned_on(Strand::Reverse) > aligned_on(Strand::Forward) { Strand::Reverse } else { Strand::Forward };
+
+    let mut variants: Vec<StructuralVariant> = blocks
+        .iter()
+        .filter(|b| b.strand != strand)
+        .map(|b| b.variant(SvType::Inversion, b.ref_end - b.ref_start))
+        .collect();
+
+    // Heaviest chain of blocks in agreeing reference and query order
+    let oriented: Vec<&Block> = blocks.iter().filter(|b| b.strand == strand).collect();
+    let follows = |prev: &Block, next: &Block| match strand {
+        Strand::Forward => next.ref_start >= prev.ref_start,
+        Strand::Reverse => next.ref_start <= prev.ref_start,
+    };
+    let mut weights: Vec<usize> = oriented.iter().map(|b| b.aligned).collect();
+    let mut previous: Vec<Option<usize>> = vec![None; oriented.len()];
+    for j in 0..oriented.len() {
+        for i in 0..j {
+            if follows(oriented[i], oriented[j]) && weights[i] + oriented[j].aligned > weights[j] {
+                weights[j] = weights[i] + oriented[j].aligned;
+                previous[j] = Some(i);
+            }
+        }
+    }
+    let mut on_backbone = vec![false; oriented.len()];
+    let mut current = (0..oriented.len()).max_by_key(|&j| (weights[j], std::cmp::Reverse(j)));
+    while let Some(j) = current {
+        on_backbone[j] = true;
+        current = previous[j];
+    }
+
+    let translocated: Vec<&Block> = oriented
+        .iter()
+        .zip(&on_backbone)
+        .filter(|(_, on)| !**on)
+        .map(|(b, _)| *b)
+        .collect();
+    variants.extend(translocated.iter().map(|b| b.variant(SvType::Translocation, b.query_end - b.query_start)));
+
+    let backbone: Vec<&Block> = oriented
+        .iter()
+        .zip(&on_backbone)
+        .filter(|(_, on)| **on)
+        .map(|(b, _)| *b)
+        .collect();
+    for pair in backbone.windows(2) {
+        let (prev, next) = (pair[0], pair[1]);
+        let (ref_from, ref_to) = match strand {
+            Strand::Forward => (prev.ref_end, next.ref_start),
+            Strand::Reverse => (next.ref_end, prev.ref_start),
+        };
+        let (query_from, query_to) = (prev.query_end, next.query_start);
+        let moved_out = |b: &&Block| -> i64 {
+            let in_ref = b.ref_start >= ref_from && b.ref_end <= ref_to;
+            let in_query = b.query_start >= query_from && b.query_end <= query_to;
+            (in_ref as i64 * (b.ref_end - b.ref_start) as i64) - (in_query as i64 * (b.query_end - b.query_start) as i64)
+        };
+        // Reference bases in the gap minus query bases in the gap, ignoring translocated blocks
+        let shift: i64 = (ref_to as i64 - ref_from as i64) - (query_to as i64 - query_from as i64)
+            - translocated.iter().map(moved_out).sum::<i64>();
+        let size = shift.unsigned_abs() as usize;
+        if size == 0 {
+            continue;
+        }
+        let kind = if shift > 0 { SvType::Deletion } else { SvType::Insertion };
+        let (ref_start, ref_end) = match kind {
+            SvType::Deletion => (ref_from, ref_to.max(ref_from)),
+            _ => (ref_from, ref_from),
+        };
+        let (query_start, query_end) = match kind {
+            SvType::Insertion => (query_from, query_to.max(query_from)),
+            _ => (query_from, query_from),
+        };
+        variants.push(StructuralVariant { kind, ref_start, ref_end, query_start, query_end, size });
+    }
+
+    variants.retain(|v| v.size >= min_size);
+    variants.sort_by_key(|v| (v.ref_start, v.query_start));
+    variants
+}
+
+/// Write the column header of `write_structural_variants`, once per table
+pub fn write_structural_variants_header<W: Write>(out: &mut W) -> io::Result<()> {
+    writeln!(out, "#query\ttype\tref_start\tref_end\tquery_start\tquery_end\tsize")
+}
+
+/// Write structural variants as TSV: type, 1-based inclusive reference and query intervals, size
+pub fn write_structural_variants<W: Write>(out: &mut W, query_name: &str, variants: &[StructuralVariant]) -> io::Result<()> {
+    for v in variants {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            query_name, v.kind, v.ref_start + 1, v.ref_end, v.query_start + 1, v.query_end, v.size
+        )?;
+    }
+    Ok(())
+}
+
+/// A collinear run of matches shared by the reference and a query, numbered from 1 in
+/// reference order
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntenyBlock {
+    pub id: usize,
+    pub strand: Strand,
+    pub ref_start: usize,
+    pub ref_end: usize,
+    pub query_start: usize,
+    pub query_end: usize,
+}
+
+impl SyntenyBlock {
+    /// Block id signed by orientation: negative when the query holds the block reverse complemented
+    pub fn signed_id(&self) -> i64 {
+        match self.strand {
+            Strand::Forward => self.id as i64,
+            Strand::Reverse => -(self.id as i64),
+        }
+    }
+}
+
+/// Synteny blocks of a query: collinear blocks of matches (split as in `structural_variants`)
+/// covering at least `min_len` aligned bases
+pub fn synteny_blocks(matches: &[Match], max_gap: usize, min_size: usize, min_len: usize) -> Vec<SyntenyBlock> {
+    let mut blocks: Vec<Block> = collinear_blocks(matches, max_gap, min_size)
+        .into_iter()
+        .filter(|b| b.aligned >= min_len)
+        .collect();
+    blocks.sort_by_key(|b| (b.ref_start, b.query_start));
+    blocks
+        .into_iter()
+        .enumerate()
+        .map(|(i, b)| SyntenyBlock {
+            id: i + 1,
+            strand: b.strand,
+            ref_start: b.ref_start,
+            ref_end: b.ref_end,
+            query_start: b.query_start,
+            query_end: b.query_end,
+        })
+        .collect()
+}
+
+/// Two blocks that follow each other along the query, as signed block ids
+///
+/// Ids follow `SyntenyBlock::signed_id`, with 0 and `n + 1` capping the two ends of the query
+/// for `n` blocks. The adjacency is a breakpoint when the reference does not have the same two
+/// blocks side by side in the same orientation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Adjacency {
+    pub left: i64,
+    pub right: i64,
+    pub breakpoint: bool,
+}
+
+/// Adjacencies of the block order along the query, the edges of its breakpoint graph against
+/// the reference order 0, 1, ..., n + 1
+///
+/// Block ids must run from 1 to `blocks.len()`, as `synteny_blocks` numbers them. `(a, b)` is
+/// conserved exactly when `b = a + 1`: `+i, +(i+1)` read forwards, or `-(i+1), -i` read on the
+/// reverse strand.
+pub fn block_adjacencies(blocks: &[SyntenyBlock]) -> Vec<Adjacency> {
+    let mut in_query_order: Vec<&SyntenyBlock> = blocks.iter().collect();
+    in_query_order.sort_by_key(|b| (b.query_start, b.query_end));
+
+    let order: Vec<i64> = std::iter::once(0)
+        .chain(in_query_order.iter().map(|b| b.signed_id()))
+        .chain(std::iter::once(blocks.len() as i64 + 1))
+        .collect();
+    order
+        .windows(2)
+        .map(|pair| Adjacency { left: pair[0], right: pair[1], breakpoint: pair[1] - pair[0] != 1 })
+        .collect()
+}
+
+/// Breakpoints between the reference and query block orders, with their flanking block ids
+pub fn breakpoints(blocks: &[SyntenyBlock]) -> Vec<Adjacency> {
+    block_adjacencies(blocks).into_iter().filter(|a| a.breakpoint).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inverted_block() {
+        let matches = vec![
+            Match::new(0, 0, 200),
+            Match::new(200, 200, 100).with_strand(Strand::Reverse),
+            Match::new(300, 300, 200),
+        ];
+        let variants = structural_variants(&matches, 90, DEFAULT_MIN_SV_SIZE);
+        assert_eq!(variants, vec![StructuralVariant {
+            kind: SvType::Inversion,
+            ref_start: 200,
+            ref_end: 300,
+            query_start: 200,
+            query_end: 300,
+            size: 100,
+        }]);
+
+        let mut out = Vec::new();
+        write_structural_variants(&mut out, "q", &variants).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "q\tINV\t201\t300\t201\t300\t100\n");
+    }
+
+    #[test]
+    fn test_breakpoints_of_two_block_inversion() {
+        // Reference order 1 2 3 4; the query carries blocks 2 and 3 inverted: 1 -3 -2 4
+        let block = |id, strand, query_start| SyntenyBlock {
+            id,
+            strand,
+            ref_start: id * 1000,
+            ref_end: id * 1000 + 500,
+            query_start,
+            query_end: query_start + 500,
+        };
+        let blocks = vec![
+            block(1, Strand::Forward, 0),
+            block(2, Strand::Reverse, 1500),
+            block(3, Strand::Reverse, 1000),
+            block(4, Strand::Forward, 2000),
+        ];
+
+        let adjacencies = block_adjacencies(&blocks);
+        assert_eq!(adjacencies.len(), 5);
+        assert_eq!(adjacencies.iter().filter(|a| !a.breakpoint).count(), 3);
+        assert_eq!(breakpoints(&blocks), vec![
+            Adjacency { left: 1, right: -3, breakpoint: true },
+            Adjacency { left: -2, right: 4, breakpoint: true },
+        ]);
+
+        // Matches laid out the same way give the same blocks
+        let matches = vec![
+            Match::new(1000, 0, 500),
+            Match::new(3000, 1000, 500).with_strand(Strand::Reverse),
+            Match::new(2000, 1500, 500).with_strand(Strand::Reverse),
+            Match::new(4000, 2000, 500),
+        ];
+        let ids: Vec<i64> = synteny_blocks(&matches, 90, DEFAULT_MIN_SV_SIZE, 100).iter().map(|b| b.signed_id()).collect();
+        assert_eq!(ids, vec![1, -2, -3, 4]);
+    }
+
+    #[test]
+    fn test_translocation_and_indels() {
+        let matches = vec![
+            Match::new(0, 0, 100),
+            Match::new(250, 100, 100), // reference block 250..350 moved before 100..250
+            Match::new(100, 200, 150),
+            Match::new(350, 350, 100),
+            Match::new(550, 450, 100), // 100 reference bases deleted
+            Match::new(650, 610, 100), // 60 bases inserted
+        ];
+        let variants = structural_variants(&matches, 90, DEFAULT_MIN_SV_SIZE);
+        let summary: Vec<(SvType, usize, usize)> = variants.iter().map(|v| (v.kind, v.ref_start, v.size)).collect();
+        assert_eq!(summary, vec![
+            (SvType::Translocation, 250, 100),
+            (SvType::Deletion, 450, 100),
+            (SvType::Insertion, 650, 60),
+        ]);
+    }
+}
@@ -1,14 +1,38 @@
 //! Suffix array implementation for efficient string matching
 //! Based on the sparse suffix array implementation in the original MUMmer
 
-use std::cmp::Ordering;
+use crate::error::HelixError;
+use crate::sequence::{reverse_complement_preserving_case, DnaSequence};
+
+/// Strand of the query a match was found on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum Strand {
+    #[default]
+    Forward,
+    Reverse, // the reverse complement of the query matches the reference
+}
+
+/// Sequence a match's query position counts along
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub enum QueryCoords {
+    #[default]
+    Forward,           // the query as given
+    ReverseComplement, // the reverse complement of the query
+}
 
 /// A match found between reference and query sequences
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub struct Match {
     pub ref_pos: usize,   // position in reference sequence
     pub query_pos: usize, // position in query sequence  
     pub len: usize,       // length of match
+    pub case_difference: bool, // spans are equal only when case is ignored
+    pub strand: Strand,
+    pub query_coords: QueryCoords,
+    pub score: Option<i64>, // DP score of an extended alignment; exact matches rank by length
 }
 
 impl Match {
@@ -17,12 +41,88 @@ impl Match {
             ref_pos,
             query_pos,
             len,
+            case_difference: false,
+            strand: Strand::Forward,
+            query_coords: QueryCoords::Forward,
+            score: None,
         }
     }
+
+    /// Same match, tagged with the given strand
+    pub fn with_strand(mut self, strand: Strand) -> Self {
+        self.strand = strand;
+        self
+    }
+
+    /// Same match, with the alignment score it ranks by
+    pub fn with_score(mut self, score: i64) -> Self {
+        self.score = Some(score);
+        self
+    }
+
+    /// What matches are ranked by wherever one is preferred over another: the alignment score
+    /// when there is one, otherwise the length
+    pub fn rank_score(&self) -> i64 {
+        self.score.unwrap_or(self.len as i64)
+    }
+
+    /// Same match, with its query position counted along the given sequence
+    pub fn with_query_coords(mut self, query_coords: QueryCoords) -> Self {
+        self.query_coords = query_coords;
+        self
+    }
+
+    /// The match in canonical form, as every output format expects it
+    ///
+    /// Reference coordinates are always forward. In canonical form `query_pos` also counts along
+    /// the query as given, whatever the strand, and `strand` alone says whether it is the
+    /// reverse complement of that span that matches. `query_len` is the length of the whole query.
+    pub fn canonicalize(&self, query_len: usize) -> Match {
+        let mut m = self.clone();
+        if m.query_coords == QueryCoords::ReverseComplement {
+            m.query_pos = query_len - m.query_pos - m.len;
+            m.query_coords = QueryCoords::Forward;
+        }
+        m
+    }
+
+    /// Reverse complement of the query span of the match, as it lines up with the reference for
+    /// a reverse-strand match
+    pub fn revcomp_query_slice(&self, query: &[u8]) -> Vec<u8> {
+        let m = self.canonicalize(query.len());
+        let span = DnaSequence {
+            sequence: query[m.query_pos..m.query_pos + m.len].to_vec(),
+            description: String::new(),
+        };
+        span.reverse_complement().sequence
+    }
+}
+
+/// An occurrence of a pattern, with how far the match continues past the pattern on each side
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtendedMatch {
+    pub ref_pos: usize, // start of the pattern occurrence in the reference
+    pub len: usize,     // length of the pattern
+    pub left: usize,    // bases the match extends to the left of `ref_pos`
+    pub right: usize,   // bases the match extends past the end of the pattern
+}
+
+impl ExtendedMatch {
+    /// The maximal match through this occurrence, for a pattern found at `query_pos` in the query
+    pub fn maximal_match(&self, query_pos: usize) -> Match {
+        Match::new(self.ref_pos - self.left, query_pos - self.left, self.left + self.len + self.right)
+    }
 }
 
 /// A sparse suffix array implementation
 /// This is a simplified version of the original MUMmer sparse suffix array
+///
+/// No sentinel byte is appended: a suffix that runs out of characters sorts before every
+/// longer suffix sharing its prefix, so any byte value (including 0) can appear in the sequence.
+///
+/// With a sampling rate `k > 1` only the suffixes starting at multiples of `k` are stored; an
+/// occurrence is then found through the first sampled suffix inside it and verified backwards.
+/// Patterns shorter than `k` need not contain a sampled suffix, so they are never found.
 pub struct SparseSuffixArray {
     sequence: Vec<u8>,
     suffix_array: Vec<usize>,
@@ -32,13 +132,26 @@ pub struct SparseSuffixArray {
 
 impl SparseSuffixArray {
     /// Create a new sparse suffix array from a sequence
-    pub fn new(sequence: &[u8], k: usize) -> Result<Self, String> {
+    /// An empty sequence is an `EmptySequence` error, as there is nothing to search.
+    pub fn new(sequence: &[u8], k: usize) -> Result<Self, HelixError> {
+        Self::with_lcp(sequence, k, true)
+    }
+
+    /// Create a sparse suffix array, skipping the LCP array unless `build_lcp` is set
+    ///
+    /// Without it the index takes about half the memory. Searches work as before, but the index
+    /// is not offered for suffix-array interval walks (MUMi falls back to the MUM search) and the
+    /// LCP statistics recompute the array on demand.
+    pub fn with_lcp(sequence: &[u8], k: usize, build_lcp: bool) -> Result<Self, HelixError> {
         if k == 0 {
-            return Err("Sampling rate k must be greater than 0".to_string());
+            return Err(HelixError::InvalidSamplingRate(k));
+        }
+        if sequence.is_empty() {
+            return Err(HelixError::EmptySequence);
         }
 
         let n = sequence.len();
-        let mut suffix_indices: Vec<usize> = (0..n).collect();
+        let mut suffix_indices: Vec<usize> = (0..n).step_by(k).collect();
         
         // Sort the suffixes based on their lexicographic order
         suffix_indices.sort_by(|&i, &j| {
@@ -48,7 +161,11 @@ impl SparseSuffixArray {
         });
 
         // Compute LCP array
-        let lcp_array = Self::compute_lcp_array(sequence, &suffix_indices);
+        let lcp_array = if build_lcp {
+            Self::compute_lcp_array(sequence, &suffix_indices)
+        } else {
+            Vec::new()
+        };
 
         Ok(Self {
             sequence: sequence.to_vec(),
@@ -58,6 +175,73 @@ impl SparseSuffixArray {
         })
     }
 
+    /// Build the densest suffix array whose estimated size fits in `max_bytes`
+    ///
+    /// `min_pattern_len` is the shortest pattern that will be searched for, such as the seed
+    /// length; a budget that needs a sampling rate above it is an error.
+    pub fn with_memory_budget(sequence: &[u8], max_bytes: usize, min_pattern_len: usize) -> Result<Self, HelixError> {
+        let k = Self::sampling_rate_for_budget(sequence.len(), max_bytes).ok_or_else(|| HelixError::IndexTooLarge {
+            sequence_len: sequence.len(),
+            min_bytes: Self::estimated_memory(sequence.len(), sequence.len().max(1)),
+        })?;
+        if k > min_pattern_len.max(1) {
+            return Err(HelixError::IndexTooSparse { sampling_rate: k, min_pattern_len });
+        }
+        Self::new(sequence, k)
+    }
+
+    /// Index of this sequence with `extra` appended, keeping the sampling rate and LCP choice
+    ///
+    /// The sequence is copied and every suffix is sorted again, but starting from the existing
+    /// order: appending only reorders two old suffixes when one is a prefix of the other, so the
+    /// old order is nearly sorted and the adaptive sort mostly merges it with the newly sorted
+    /// suffixes of `extra`. Each comparison can still scan long repeats, and the LCP array is
+    /// recomputed in full, so this is cheaper than `new` on the combined sequence but still
+    /// linear in the whole reference rather than in `extra` alone.
+    pub fn rebuild_with_appended(&self, extra: &[u8]) -> Result<Self, HelixError> {
+        let mut sequence = Vec::with_capacity(self.sequence.len() + extra.len());
+        sequence.extend_from_slice(&self.sequence);
+        sequence.extend_from_slice(extra);
+
+        let first_new = self.sequence.len().div_ceil(self.k) * self.k;
+        let mut new_suffixes: Vec<usize> = (first_new..sequence.len()).step_by(self.k).collect();
+        new_suffixes.sort_by(|&i, &j| sequence[i..].cmp(&sequence[j..]));
+        let mut suffix_indices = self.suffix_array.clone();
+        suffix_indices.extend(new_suffixes);
+        suffix_indices.sort_by(|&i, &j| sequence[i..].cmp(&sequence[j..]));
+
+        let lcp_array = if self.has_lcp() {
+            Self::compute_lcp_array(&sequence, &suffix_indices)
+        } else {
+            Vec::new()
+        };
+        Ok(Self { sequence, suffix_array: suffix_indices, lcp_array, k: self.k })
+    }
+
+    /// Approximate bytes used by an index of `sequence_len` bases sampled every `k` suffixes:
+    /// the sequence plus one suffix-array and one LCP entry per sampled suffix
+    pub fn estimated_memory(sequence_len: usize, k: usize) -> usize {
+        sequence_len + 2 * std::mem::size_of::<usize>() * sequence_len.div_ceil(k.max(1))
+    }
+
+    /// Bytes actually held by this index: the sequence, suffix array and LCP array
+    pub fn memory_usage(&self) -> usize {
+        self.sequence.len() + std::mem::size_of::<usize>() * (self.suffix_array.len() + self.lcp_array.len())
+    }
+
+    /// Smallest sampling rate whose estimated memory fits in `max_bytes`, if any does
+    pub fn sampling_rate_for_budget(sequence_len: usize, max_bytes: usize) -> Option<usize> {
+        if sequence_len == 0 {
+            return Some(1);
+        }
+        let per_suffix = 2 * std::mem::size_of::<usize>();
+        let max_suffixes = max_bytes.checked_sub(sequence_len)? / per_suffix;
+        if max_suffixes == 0 {
+            return None;
+        }
+        Some(sequence_len.div_ceil(max_suffixes).max(1))
+    }
+
     /// Compute the LCP (Longest Common Prefix) array
     fn compute_lcp_array(sequence: &[u8], suffix_array: &[usize]) -> Vec<usize> {
         let n = suffix_array.len();
@@ -89,129 +273,138 @@ impl SparseSuffixArray {
         lcp
     }
 
-    /// Binary search to find the left boundary of an interval for character c at position i
-    fn bsearch_left(&self, c: u8, i: usize, start: usize, end: usize) -> usize {
-        let mut s = start;
-        let mut e = end;
-        
-        while s <= e && s < self.suffix_array.len() {
-            if s > e {
-                break;
-            }
-            
-            let mid = s + (e - s) / 2;
-            if mid >= self.suffix_array.len() {
-                break;
-            }
-            
-            let suffix_pos = self.suffix_array[mid];
-            if suffix_pos + i >= self.sequence.len() {
-                s = mid + 1;
-                continue;
-            }
-            
-            let suffix_char = self.sequence[suffix_pos + i];
-            match suffix_char.cmp(&c) {
-                Ordering::Less => s = mid + 1,
-                Ordering::Greater => {
-                    if mid == 0 { break; }
-                    e = mid - 1;
-                },
-                Ordering::Equal => {
-                    e = mid;
-                    if s == e {
-                        break;
-                    }
-                }
-            }
-        }
-        
-        s.min(self.suffix_array.len().saturating_sub(1))
-    }
-
-    /// Binary search to find the right boundary of an interval for character c at position i
-    fn bsearch_right(&self, c: u8, i: usize, start: usize, end: usize) -> usize {
-        let mut s = start;
-        let mut e = end;
-        let mut result = start;
-        
-        while s <= e && s < self.suffix_array.len() {
-            if s > e {
-                break;
-            }
-            
-            let mid = s + (e - s) / 2;
-            if mid >= self.suffix_array.len() {
-                break;
-            }
-            
-            let suffix_pos = self.suffix_array[mid];
-            if suffix_pos + i >= self.sequence.len() {
-                s = mid + 1;
-                continue;
-            }
-            
-            let suffix_char = self.sequence[suffix_pos + i];
-            match suffix_char.cmp(&c) {
-                Ordering::Less => s = mid + 1,
-                Ordering::Greater => {
-                    if mid == 0 { break; }
-                    e = mid - 1;
-                },
-                Ordering::Equal => {
-                    result = mid;
-                    s = mid + 1;
-                }
-            }
-        }
-        
-        result.min(self.suffix_array.len().saturating_sub(1))
+    /// Character at offset `i` of the suffix at `suffix_pos`, None past the end of the sequence
+    /// Suffixes that end early sort before any character
+    fn char_at(&self, suffix_pos: usize, i: usize) -> Option<u8> {
+        self.sequence.get(suffix_pos + i).copied()
     }
 
     /// Simple suffix array search for a pattern
-    pub fn search(&self, pattern: &[u8]) -> Option<(usize, usize)> {
-        if pattern.is_empty() || self.suffix_array.is_empty() {
+    /// Returns the interval of suffixes starting with the pattern, None if there are none
+    pub fn search(&self, pattern: &[u8]) -> Option<SaInterval> {
+        // A pattern longer than the whole reference cannot occur in it
+        if pattern.is_empty() || self.suffix_array.is_empty() || pattern.len() > self.sequence.len() {
             return None;
         }
 
-        let mut start = 0;
-        let mut end = self.suffix_array.len() - 1;
-
-        for (i, &c) in pattern.iter().enumerate() {
-            let new_start = self.bsearch_left(c, i, start, end);
-            let new_end = self.bsearch_right(c, i, start, end);
+        let mut interval = (0, self.suffix_array.len());
+        for (depth, &c) in pattern.iter().enumerate() {
+            interval = self.refine_interval(interval, depth, c)?;
+        }
 
-            if new_start > new_end || new_start >= self.suffix_array.len() {
-                return None; // Pattern not found
-            }
+        Some(SaInterval { start: interval.0, end: interval.1, depth: pattern.len() })
+    }
 
-            start = new_start;
-            end = new_end;
+    /// Narrow a half-open interval of suffixes sharing a prefix of length `depth` to those
+    /// continuing with `c`; None if no suffix does
+    pub(crate) fn refine_interval(&self, (start, end): (usize, usize), depth: usize, c: u8) -> Option<(usize, usize)> {
+        // Every suffix in [start, end) shares the same prefix, so their next characters are sorted
+        let interval = &self.suffix_array[start..end];
+        let left = interval.partition_point(|&pos| self.char_at(pos, depth) < Some(c));
+        let right = interval.partition_point(|&pos| self.char_at(pos, depth) <= Some(c));
 
-            if start > end {
-                return None; // Pattern not found
-            }
+        if left == right {
+            None
+        } else {
+            Some((start + left, start + right))
         }
+    }
 
-        Some((start, end))
+    /// Whether `pattern` occurs anywhere in the reference
+    ///
+    /// Cheaper than `find_matches` for membership queries: a dense index stops as soon as the
+    /// pattern's suffix interval is found and a sparse one at the first verified occurrence,
+    /// without collecting any positions. An empty pattern is never contained.
+    pub fn contains(&self, pattern: &[u8]) -> bool {
+        if self.k == 1 {
+            return !pattern.is_empty() && pattern.len() <= self.sequence.len() && self.search(pattern).is_some();
+        }
+        self.sparse_occurrences(pattern).next().is_some()
     }
 
     /// Find all matches of a pattern in the reference sequence
     pub fn find_matches(&self, pattern: &[u8]) -> Vec<Match> {
-        let interval = self.search(pattern);
-        match interval {
-            Some((start, end)) => {
-                let mut matches = Vec::new();
-                for i in start..=end {
-                    if i < self.suffix_array.len() {
-                        let ref_pos = self.suffix_array[i];
-                        matches.push(Match::new(ref_pos, 0, pattern.len()));
-                    }
-                }
-                matches
-            }
-            None => Vec::new(),
+        if pattern.len() > self.sequence.len() {
+            return Vec::new();
+        }
+        self.occurrences(pattern)
+            .map(|ref_pos| Match::new(ref_pos, 0, pattern.len()))
+            .collect()
+    }
+
+    /// Find all matches of a pattern or its reverse complement, tagged with the strand
+    /// A pattern that is its own reverse complement is only reported on the forward strand
+    pub fn find_matches_both_strands(&self, pattern: &[u8]) -> Vec<Match> {
+        let mut matches = self.find_matches(pattern);
+
+        let rev_comp = reverse_complement_preserving_case(pattern);
+        if rev_comp != pattern {
+            matches.extend(
+                self.occurrences(&rev_comp)
+                    .map(|ref_pos| Match::new(ref_pos, 0, pattern.len()).with_strand(Strand::Reverse)),
+            );
         }
+        matches
+    }
+
+    /// Find all matches of a pattern, each with its extension bounds against the query
+    ///
+    /// `before` and `after` are the query bases preceding and following the pattern; an occurrence
+    /// extends left while the reference agrees with `before` read backwards, and right while it
+    /// agrees with `after`.
+    pub fn find_matches_extended(&self, pattern: &[u8], before: &[u8], after: &[u8]) -> Vec<ExtendedMatch> {
+        self.find_matches(pattern)
+            .into_iter()
+            .map(|m| {
+                let left = self.sequence[..m.ref_pos]
+                    .iter()
+                    .rev()
+                    .zip(before.iter().rev())
+                    .take_while(|(r, q)| r == q)
+                    .count();
+                let right = self.sequence[m.ref_pos + pattern.len()..]
+                    .iter()
+                    .zip(after)
+                    .take_while(|(r, q)| r == q)
+                    .count();
+                ExtendedMatch { ref_pos: m.ref_pos, len: pattern.len(), left, right }
+            })
+            .collect()
+    }
+
+    /// Lazily iterate over the reference positions of a pattern
+    /// Positions are yielded in suffix-array order, so callers can stop early
+    /// (e.g. `.take(2)` is enough to decide whether a pattern is unique)
+    /// A sparse index yields them one sampled offset at a time, in no particular order overall.
+    pub fn occurrences<'a>(&'a self, pattern: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        if self.k > 1 {
+            return Occurrences::Sparse(Box::new(self.sparse_occurrences(pattern)));
+        }
+        let suffixes = match self.search(pattern) {
+            Some(interval) => self.suffixes(&interval),
+            None => &[][..],
+        };
+        Occurrences::Dense(suffixes.iter().copied())
+    }
+
+    /// Every position of `pattern` found through the sampled suffixes; none when the pattern is
+    /// shorter than the sampling rate
+    fn sparse_occurrences<'a>(&'a self, pattern: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        let searchable = pattern.len() >= self.k && pattern.len() <= self.sequence.len();
+        let skips = if searchable { 0..self.k } else { 0..0 };
+        // An occurrence at `pos` contains exactly one sampled suffix, `skip` bases in
+        skips.flat_map(move |skip| {
+            let sampled = self.search(&pattern[skip..]).map_or(&[][..], |interval| self.suffixes(&interval));
+            sampled
+                .iter()
+                .filter(move |&&sampled| sampled >= skip && self.sequence[sampled - skip..sampled] == pattern[..skip])
+                .map(move |&sampled| sampled - skip)
+        })
+    }
+
+    /// Start positions of the suffixes in an interval returned by `search`, in suffix-array order
+    pub fn suffixes(&self, interval: &SaInterval) -> &[usize] {
+        &self.suffix_array[interval.start..interval.end]
     }
 
     /// Get the original sequence
@@ -224,20 +417,129 @@ impl SparseSuffixArray {
         &self.suffix_array
     }
 
-    /// Get the LCP array
+    /// Get the LCP array, empty when the index was built without one
     pub fn lcp_array(&self) -> &[usize] {
         &self.lcp_array
     }
 
+    /// Whether the LCP array was built
+    pub fn has_lcp(&self) -> bool {
+        self.lcp_array.len() == self.suffix_array.len()
+    }
+
+    /// The LCP array, computed on the spot when the index was built without one
+    fn lcp_or_compute(&self) -> std::borrow::Cow<'_, [usize]> {
+        if self.has_lcp() {
+            std::borrow::Cow::Borrowed(&self.lcp_array)
+        } else {
+            std::borrow::Cow::Owned(Self::compute_lcp_array(&self.sequence, &self.suffix_array))
+        }
+    }
+
+    /// Longest common prefix between any two suffixes
+    /// A large value indicates long repeats in the indexed sequence
+    pub fn max_lcp(&self) -> usize {
+        self.lcp_or_compute().iter().copied().max().unwrap_or(0)
+    }
+
+    /// Mean LCP between adjacent suffixes, a measure of overall repetitiveness
+    pub fn average_lcp(&self) -> f64 {
+        let lcp_array = self.lcp_or_compute();
+        // The first entry has no predecessor and is always 0
+        if lcp_array.len() < 2 {
+            return 0.0;
+        }
+        let total: usize = lcp_array[1..].iter().sum();
+        total as f64 / (lcp_array.len() - 1) as f64
+    }
+
     /// Get the sampling rate
     pub fn sampling_rate(&self) -> usize {
         self.k
     }
 }
 
+/// A full-text index of a reference sequence that the MUMmer algorithms can search
+///
+/// Implement `find_matches` and `sequence` to plug in another backend such as an FM-index.
+/// The provided methods can be overridden when the backend offers something faster.
+pub trait Index {
+    /// Every occurrence of `pattern` in the reference, with `query_pos` 0
+    fn find_matches(&self, pattern: &[u8]) -> Vec<Match>;
+
+    /// The indexed reference sequence
+    fn sequence(&self) -> &[u8];
+
+    /// Reference positions of `pattern`; backends able to stop early should yield lazily
+    fn occurrences<'a>(&'a self, pattern: &'a [u8]) -> Box<dyn Iterator<Item = usize> + 'a> {
+        Box::new(self.find_matches(pattern).into_iter().map(|m| m.ref_pos))
+    }
+
+    /// The underlying dense suffix array, for algorithms that walk its intervals directly
+    fn as_suffix_array(&self) -> Option<&SparseSuffixArray> {
+        None
+    }
+}
+
+impl Index for SparseSuffixArray {
+    fn find_matches(&self, pattern: &[u8]) -> Vec<Match> {
+        SparseSuffixArray::find_matches(self, pattern)
+    }
+
+    fn sequence(&self) -> &[u8] {
+        SparseSuffixArray::sequence(self)
+    }
+
+    fn occurrences<'a>(&'a self, pattern: &'a [u8]) -> Box<dyn Iterator<Item = usize> + 'a> {
+        Box::new(SparseSuffixArray::occurrences(self, pattern))
+    }
+
+    fn as_suffix_array(&self) -> Option<&SparseSuffixArray> {
+        // Interval walks need every suffix and the LCP between neighbours
+        (self.k == 1 && self.has_lcp()).then_some(self)
+    }
+}
+
+/// The half-open range `[start, end)` of the suffix array holding the suffixes that start with a
+/// pattern of `depth` characters
+///
+/// On a dense index the width is the number of occurrences of the pattern; on a sparse one it
+/// only counts the occurrences at sampled positions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaInterval {
+    pub start: usize,
+    pub end: usize,
+    pub depth: usize,
+}
+
+impl SaInterval {
+    /// Number of suffixes in the interval
+    pub fn width(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+/// Positions from a dense suffix-array interval, or verified one by one from a sparse index
+enum Occurrences<'a> {
+    Dense(std::iter::Copied<std::slice::Iter<'a, usize>>),
+    Sparse(Box<dyn Iterator<Item = usize> + 'a>),
+}
+
+impl Iterator for Occurrences<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        match self {
+            Occurrences::Dense(positions) => positions.next(),
+            Occurrences::Sparse(positions) => positions.next(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bench::synthetic_sequence;
 
     #[test]
     fn test_sparse_suffix_array() {
@@ -252,6 +554,54 @@ mod tests {
         assert_eq!(sa.suffix_array(), &expected_suffixes);
     }
 
+    #[test]
+    fn test_contains() {
+        let sequence = b"ACGTTGCAAGGCTTACGATCGGATCCA";
+        for k in [1, 3] {
+            let sa = SparseSuffixArray::new(sequence, k).unwrap();
+            assert!(sa.contains(b"GCTTACG"), "k = {}", k);
+            // Patterns shorter than the sampling rate are not indexed
+            assert_eq!(sa.contains(b"GT"), k == 1, "k = {}", k);
+            assert!(!sa.contains(b"GCTTACC"), "k = {}", k);
+            assert!(!sa.contains(b""), "k = {}", k);
+        }
+    }
+
+    #[test]
+    fn test_revcomp_query_slice() {
+        let query = b"TTACGGATCAA";
+        let forward_slice = DnaSequence::new("ACGGAT", String::new());
+        let m = Match::new(10, 2, 6).with_strand(Strand::Reverse);
+        assert_eq!(m.revcomp_query_slice(query), forward_slice.reverse_complement().sequence);
+        assert_eq!(m.revcomp_query_slice(query), b"ATCCGT");
+
+        // The same span, counted along the reverse complement of the query
+        let rc_coords = Match::new(10, 3, 6)
+            .with_strand(Strand::Reverse)
+            .with_query_coords(QueryCoords::ReverseComplement);
+        assert_eq!(rc_coords.revcomp_query_slice(query), b"ATCCGT");
+    }
+
+    #[test]
+    fn test_canonicalize_reverse_match() {
+        // Query positions 2..6 of a 10 base query are 4..8 of its reverse complement
+        let forward_coords = Match::new(30, 2, 4).with_strand(Strand::Reverse);
+        let rev_comp_coords = Match::new(30, 4, 4)
+            .with_strand(Strand::Reverse)
+            .with_query_coords(QueryCoords::ReverseComplement);
+
+        assert_eq!(forward_coords.canonicalize(10), forward_coords);
+        assert_eq!(rev_comp_coords.canonicalize(10), forward_coords);
+        assert_eq!(rev_comp_coords.canonicalize(10).canonicalize(10), forward_coords);
+    }
+
+    #[test]
+    fn test_zero_sampling_rate_is_rejected() {
+        let result = SparseSuffixArray::new(b"ACGT", 0);
+        assert!(matches!(result, Err(HelixError::InvalidSamplingRate(0))));
+        assert_eq!(result.err().unwrap().to_string(), "Sampling rate k must be greater than 0, got 0");
+    }
+
     #[test]
     fn test_search() {
         let sequence = b"banana$";
@@ -261,11 +611,242 @@ mod tests {
         let result = sa.search(b"ana");
         assert!(result.is_some());
         
-        let (start, end) = result.unwrap();
-        assert!(start <= end);
+        let interval = result.unwrap();
+        assert!(interval.start < interval.end);
+        assert_eq!(interval.depth, 3);
         
         // Should find "ana" at position 1 and 3
         let matches = sa.find_matches(b"ana");
         assert!(!matches.is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_search_agrees_with_naive_scan() {
+        // Patterns that do not occur must not be reported as found
+        let sa = SparseSuffixArray::new(b"GTCCC", 1).unwrap();
+        for absent in [&b"A"[..], b"GA", b"TA"] {
+            assert!(sa.search(absent).is_none(), "{}", String::from_utf8_lossy(absent));
+        }
+
+        // Every pattern of up to 4 bases, against a scan of all reference windows
+        for (len, seed) in [(5, 7), (9, 11), (17, 13), (40, 17)] {
+            let sequence = synthetic_sequence(len, seed);
+            let sa = SparseSuffixArray::new(&sequence, 1).unwrap();
+            for pattern_len in 1..=4 {
+                for code in 0..1usize << (2 * pattern_len) {
+                    let pattern: Vec<u8> = (0..pattern_len).map(|i| b"ACGT"[(code >> (2 * i)) & 3]).collect();
+                    let mut expected: Vec<usize> = (0..len.saturating_sub(pattern_len - 1))
+                        .filter(|&pos| sequence[pos..pos + pattern_len] == pattern[..])
+                        .collect();
+                    let mut found = sa.search(&pattern).map_or_else(Vec::new, |interval| sa.suffixes(&interval).to_vec());
+                    found.sort_unstable();
+                    expected.sort_unstable();
+                    assert_eq!(found, expected, "{} in {}", String::from_utf8_lossy(&pattern), String::from_utf8_lossy(&sequence));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_interval_width_is_occurrence_count() {
+        let sequence = b"ACGTTGCAAGGCTTACGATCGGATCCAACGT";
+        let sa = SparseSuffixArray::new(sequence, 1).unwrap();
+        for pattern in [&b"A"[..], b"ACG", b"GAT", b"TT", b"CGGATCC", b"ACGTT"] {
+            let interval = sa.search(pattern).unwrap();
+            let count = sequence.windows(pattern.len()).filter(|window| *window == pattern).count();
+            assert_eq!(interval.width(), count, "{}", String::from_utf8_lossy(pattern));
+            assert_eq!(interval.depth, pattern.len());
+            assert!(sa.suffixes(&interval).iter().all(|&pos| sequence[pos..].starts_with(pattern)));
+        }
+    }
+
+    #[test]
+    fn test_memory_budget_selects_sampling_rate() {
+        let reference = synthetic_sequence(2000, 99);
+        let query = [&reference[100..160], b"TTTT".as_slice(), &reference[1500..1530], &reference[100..130]].concat();
+
+        let dense = SparseSuffixArray::with_memory_budget(&reference, 1 << 20, 20).unwrap();
+        assert_eq!(dense.sampling_rate(), 1);
+        let sparse = SparseSuffixArray::with_memory_budget(&reference, 8000, 20).unwrap();
+        assert!(sparse.sampling_rate() > 1);
+        assert!(SparseSuffixArray::estimated_memory(reference.len(), sparse.sampling_rate()) <= 8000);
+        assert!(matches!(
+            SparseSuffixArray::with_memory_budget(&reference, 1000, 20),
+            Err(HelixError::IndexTooLarge { sequence_len: 2000, .. })
+        ));
+        // Seeds shorter than the sampling rate could not be found
+        assert!(matches!(
+            SparseSuffixArray::with_memory_budget(&reference, 8000, sparse.sampling_rate() - 1),
+            Err(HelixError::IndexTooSparse { .. })
+        ));
+
+        for algorithm in [crate::MatchType::MUM, crate::MatchType::MAM, crate::MatchType::MEM, crate::MatchType::MUMi] {
+            let expected = crate::run_mummer_algorithm(&dense, &query, algorithm.clone(), 20);
+            assert!(!expected.is_empty());
+            assert_eq!(crate::run_mummer_algorithm(&sparse, &query, algorithm, 20), expected);
+        }
+
+        // Patterns of at least the sampling rate are all found, shorter ones are not indexed
+        let k = sparse.sampling_rate();
+        for pattern in [&reference[10..10 + k], &reference[10..30]] {
+            let mut found: Vec<usize> = sparse.occurrences(pattern).collect();
+            found.sort();
+            assert_eq!(found, dense.occurrences(pattern).collect::<Vec<usize>>());
+            assert!(sparse.contains(pattern));
+        }
+        assert_eq!(sparse.occurrences(&reference[10..10 + k - 1]).count(), 0);
+    }
+
+    #[test]
+    fn test_sequence_containing_zero_bytes() {
+        let reference = [3, 0, 7, 0, 0, 7, 1, 0];
+        let sa = SparseSuffixArray::new(&reference, 1).unwrap();
+
+        let mut positions: Vec<usize> = sa.occurrences(&[0, 7]).collect();
+        positions.sort();
+        assert_eq!(positions, vec![1, 4]);
+        assert_eq!(sa.occurrences(&[0]).count(), 4);
+        assert_eq!(sa.occurrences(&[0, 0, 0]).count(), 0);
+
+        let matches = crate::find_all_mems(&sa, &[9, 0, 0, 7, 1, 9], 2);
+        assert_eq!(matches, vec![Match::new(1, 2, 2), Match::new(3, 1, 4)]);
+    }
+
+    #[test]
+    fn test_search_pattern_longer_than_reference() {
+        let sa = SparseSuffixArray::new(b"ACGT", 1).unwrap();
+        assert_eq!(sa.search(b"ACGTA"), None);
+        assert!(sa.find_matches(b"ACGTACGTACGT").is_empty());
+        assert_eq!(sa.occurrences(b"ACGTA").count(), 0);
+    }
+
+    #[test]
+    fn test_occurrences_matches_find_matches() {
+        let sequence = b"banana$";
+        let sa = SparseSuffixArray::new(sequence, 1).unwrap();
+
+        let positions: Vec<usize> = sa.occurrences(b"ana").collect();
+        let expected: Vec<usize> = sa.find_matches(b"ana").iter().map(|m| m.ref_pos).collect();
+        assert_eq!(positions, expected);
+
+        let mut sorted = positions.clone();
+        sorted.sort();
+        assert_eq!(sorted, vec![1, 3]);
+
+        // Early stop is enough for a uniqueness check
+        assert_eq!(sa.occurrences(b"a").take(2).count(), 2);
+        assert_eq!(sa.occurrences(b"b").take(2).count(), 1);
+        assert_eq!(sa.occurrences(b"x").count(), 0);
+    }
+
+    #[test]
+    fn test_index_without_lcp() {
+        let reference: Vec<u8> = (0..2000u32).map(|i| b"ACGT"[((i * 7 + i / 5) % 4) as usize]).collect();
+        let with_lcp = SparseSuffixArray::new(&reference, 1).unwrap();
+        let without_lcp = SparseSuffixArray::with_lcp(&reference, 1, false).unwrap();
+
+        assert!(without_lcp.lcp_array().is_empty() && !without_lcp.has_lcp());
+        assert!(without_lcp.memory_usage() < with_lcp.memory_usage());
+        assert!(without_lcp.as_suffix_array().is_none());
+
+        let pattern = &reference[300..320];
+        assert_eq!(without_lcp.find_matches(pattern), with_lcp.find_matches(pattern));
+        assert_eq!(without_lcp.max_lcp(), with_lcp.max_lcp());
+        let query = &reference[500..700];
+        assert_eq!(
+            crate::run_mummer_algorithm(&without_lcp, query, crate::MatchType::MUMi, 20),
+            crate::run_mummer_algorithm(&with_lcp, query, crate::MatchType::MUMi, 20)
+        );
+    }
+
+    #[test]
+    fn test_lcp_statistics() {
+        let repetitive = vec![b'A'; 50];
+        let sa = SparseSuffixArray::new(&repetitive, 1).unwrap();
+        assert_eq!(sa.max_lcp(), 49);
+        assert!(sa.average_lcp() > 20.0);
+
+        let varied = b"GATTACACGTTGCAAGCTCCTAGG";
+        let sa = SparseSuffixArray::new(varied, 1).unwrap();
+        assert!(sa.max_lcp() <= 3);
+        assert!(sa.average_lcp() < 2.0);
+
+        let single = SparseSuffixArray::new(b"A", 1).unwrap();
+        assert_eq!(single.max_lcp(), 0);
+        assert_eq!(single.average_lcp(), 0.0);
+        assert!(matches!(SparseSuffixArray::new(b"", 1), Err(HelixError::EmptySequence)));
+    }
+
+    #[test]
+    fn test_find_matches_both_strands() {
+        let sa = SparseSuffixArray::new(b"TTTGGCACC", 1).unwrap();
+
+        // GGTGCC only occurs as its reverse complement GGCACC
+        assert!(sa.find_matches(b"GGTGCC").is_empty());
+        let matches = sa.find_matches_both_strands(b"GGTGCC");
+        assert_eq!(matches, vec![Match::new(3, 0, 6).with_strand(Strand::Reverse)]);
+
+        let matches = sa.find_matches_both_strands(b"TTTG");
+        assert_eq!(matches, vec![Match::new(0, 0, 4)]);
+
+        // Lower case patterns are searched in lower case on both strands
+        let sa = SparseSuffixArray::new(b"tttggcaccAATT", 1).unwrap();
+        let matches = sa.find_matches_both_strands(b"ggtgcc");
+        assert_eq!(matches, vec![Match::new(3, 0, 6).with_strand(Strand::Reverse)]);
+        // A lower case palindrome is reported once, and not as its upper case complement
+        assert!(sa.find_matches_both_strands(b"aatt").is_empty());
+
+        // Non-UTF-8 patterns still get their reverse hits
+        let sa = SparseSuffixArray::new(b"ACGT\xffAAC", 1).unwrap();
+        let matches = sa.find_matches_both_strands(b"GTT\xff");
+        assert_eq!(matches, vec![Match::new(4, 0, 4).with_strand(Strand::Reverse)]);
+    }
+
+    #[test]
+    fn test_find_matches_extended() {
+        let reference = b"CAGTACGATTGTACGAGTACGCT";
+        let query = b"TTAGTACGACC";
+        let sa = SparseSuffixArray::new(reference, 1).unwrap();
+
+        // The seed GTAC occurs three times in the reference
+        let (start, end) = (3, 7);
+        let mut extended = sa.find_matches_extended(&query[start..end], &query[..start], &query[end..]);
+        extended.sort_by_key(|e| e.ref_pos);
+        assert_eq!(extended.len(), 3);
+        for e in &extended {
+            let mut left = 0;
+            while left < e.ref_pos && left < start && reference[e.ref_pos - left - 1] == query[start - left - 1] {
+                left += 1;
+            }
+            let mut right = 0;
+            while e.ref_pos + e.len + right < reference.len()
+                && end + right < query.len()
+                && reference[e.ref_pos + e.len + right] == query[end + right]
+            {
+                right += 1;
+            }
+            assert_eq!((e.left, e.right), (left, right), "occurrence at {}", e.ref_pos);
+        }
+        assert_eq!(extended[0].maximal_match(start), Match::new(1, 2, 7));
+    }
+
+    #[test]
+    fn test_rebuild_with_appended() {
+        let original = b"ACGTTGCAACGGT";
+        let extra = b"TTACGGATCCA";
+        let combined = [&original[..], &extra[..]].concat();
+        for k in [1, 3] {
+            let sa = SparseSuffixArray::new(original, k).unwrap();
+            let appended = sa.rebuild_with_appended(extra).unwrap();
+            assert_eq!(appended.sequence(), &combined[..]);
+            let fresh = SparseSuffixArray::new(&combined, k).unwrap();
+            assert_eq!(appended.suffix_array(), fresh.suffix_array());
+
+            // ACGG occurs once in each region, GATCC only in the appended one
+            let mut positions: Vec<usize> = appended.find_matches(b"ACGG").iter().map(|m| m.ref_pos).collect();
+            positions.sort_unstable();
+            assert_eq!(positions, vec![8, 15]);
+            assert_eq!(appended.find_matches(b"GATCC"), vec![Match::new(18, 0, 5)]);
+        }
+    }
+}
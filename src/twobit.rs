@@ -0,0 +1,174 @@
+//! Reader for the UCSC `.2bit` genome format
+//!
+//! Bases are packed four per byte (T=0, C=1, A=2, G=3, most significant bits first), with
+//! separate block lists for runs of N and for soft-masked (lower case) regions.
+
+/// Signature stored in the first four bytes, in the byte order of the file
+pub const TWOBIT_SIGNATURE: u32 = 0x1A41_2743;
+
+/// Check whether a buffer starts with the `.2bit` signature in either byte order
+pub fn is_twobit(data: &[u8]) -> bool {
+    data.len() >= 4 && {
+        let head = [data[0], data[1], data[2], data[3]];
+        u32::from_le_bytes(head) == TWOBIT_SIGNATURE || u32::from_be_bytes(head) == TWOBIT_SIGNATURE
+    }
+}
+
+/// Cursor over the buffer that reads integers in the byte order given by the signature
+struct TwoBitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    little_endian: bool,
+}
+
+impl<'a> TwoBitReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.data.len())
+            .ok_or_else(|| format!("Truncated 2bit data at offset {}", self.pos))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        let word = [b[0], b[1], b[2], b[3]];
+        Ok(if self.little_endian { u32::from_le_bytes(word) } else { u32::from_be_bytes(word) })
+    }
+
+    fn u32_list(&mut self, count: usize) -> Result<Vec<u32>, String> {
+        (0..count).map(|_| self.u32()).collect()
+    }
+}
+
+/// Decode every sequence of a `.2bit` buffer into (name, sequence) records
+/// N blocks are decoded as `N`; mask blocks are read but dropped, since sequences are kept upper case
+pub fn parse_twobit(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    if !is_twobit(data) {
+        return Err("Not a 2bit file: bad signature".to_string());
+    }
+    let little_endian = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) == TWOBIT_SIGNATURE;
+    let mut header = TwoBitReader { data, pos: 4, little_endian };
+
+    let version = header.u32()?;
+    if version != 0 {
+        return Err(format!("Unsupported 2bit version {}", version));
+    }
+    let sequence_count = header.u32()? as usize;
+    let _reserved = header.u32()?;
+
+    // The count comes from the file, so nothing is allocated for it before the entries are read
+    let mut index = Vec::new();
+    for _ in 0..sequence_count {
+        let name_len = header.bytes(1)?[0] as usize;
+        let name = String::from_utf8_lossy(header.bytes(name_len)?).into_owned();
+        let offset = header.u32()? as usize;
+        index.push((name, offset));
+    }
+
+    let mut records = Vec::with_capacity(index.len());
+    for (name, offset) in index {
+        let mut record = TwoBitReader { data, pos: offset, little_endian };
+        let dna_size = record.u32()? as usize;
+
+        let n_block_count = record.u32()? as usize;
+        let n_starts = record.u32_list(n_block_count)?;
+        let n_sizes = record.u32_list(n_block_count)?;
+
+        let mask_block_count = record.u32()? as usize;
+        let _mask_starts = record.u32_list(mask_block_count)?;
+        let _mask_sizes = record.u32_list(mask_block_count)?;
+        let _reserved = record.u32()?;
+
+        let packed = record.bytes(dna_size.div_ceil(4))?;
+        let mut sequence: Vec<u8> = (0..dna_size)
+            .map(|i| {
+                let shift = 6 - 2 * (i % 4);
+                match (packed[i / 4] >> shift) & 0b11 {
+                    0 => b'T',
+                    1 => b'C',
+                    2 => b'A',
+                    _ => b'G',
+                }
+            })
+            .collect();
+
+        for (&start, &size) in n_starts.iter().zip(&n_sizes) {
+            let start = start as usize;
+            let end = start + size as usize;
+            if end > dna_size {
+                return Err(format!("N block {}..{} outside sequence {} of length {}", start, end, name, dna_size));
+            }
+            sequence[start..end].fill(b'N');
+        }
+
+        records.push((name, sequence));
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack(sequence: &[u8]) -> Vec<u8> {
+        let mut packed = vec![0u8; sequence.len().div_ceil(4)];
+        for (i, base) in sequence.iter().enumerate() {
+            let code = match base {
+                b'T' | b'N' => 0,
+                b'C' => 1,
+                b'A' => 2,
+                _ => 3,
+            };
+            packed[i / 4] |= code << (6 - 2 * (i % 4));
+        }
+        packed
+    }
+
+    #[test]
+    fn test_parse_twobit_round_trip() {
+        let sequence = b"ACGTNNNGATTACA";
+        let name = b"chrT";
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&TWOBIT_SIGNATURE.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes()); // version
+        data.extend_from_slice(&1u32.to_le_bytes()); // sequence count
+        data.extend_from_slice(&0u32.to_le_bytes()); // reserved
+        data.push(name.len() as u8);
+        data.extend_from_slice(name);
+        let offset = data.len() as u32 + 4;
+        data.extend_from_slice(&offset.to_le_bytes());
+
+        data.extend_from_slice(&(sequence.len() as u32).to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes()); // one N block
+        data.extend_from_slice(&4u32.to_le_bytes());
+        data.extend_from_slice(&3u32.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes()); // one mask block
+        data.extend_from_slice(&7u32.to_le_bytes());
+        data.extend_from_slice(&4u32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes()); // reserved
+        data.extend_from_slice(&pack(sequence));
+
+        assert!(is_twobit(&data));
+        let records = parse_twobit(&data).unwrap();
+        assert_eq!(records, vec![("chrT".to_string(), sequence.to_vec())]);
+
+        assert!(parse_twobit(&data[..data.len() - 1]).is_err());
+        assert!(!is_twobit(b">chr1\nACGT\n"));
+    }
+
+    #[test]
+    fn test_parse_twobit_corrupt_count() {
+        // A header claiming 2^32 - 1 sequences with no index behind it
+        let mut data = Vec::new();
+        data.extend_from_slice(&TWOBIT_SIGNATURE.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes()); // version
+        data.extend_from_slice(&u32::MAX.to_le_bytes()); // sequence count
+        data.extend_from_slice(&0u32.to_le_bytes()); // reserved
+
+        let err = parse_twobit(&data).unwrap_err();
+        assert!(err.starts_with("Truncated 2bit data"), "{}", err);
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn missing_input_file_exits_non_zero() {
+    let output = Command::new(env!("CARGO_BIN_EXE_helixalign"))
+        .args(["test_ref.fa", "does_not_exist.fa"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("Could not run binary");
+
+    assert!(!output.status.success());
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Could not read does_not_exist.fa"), "{}", stderr);
+}
+
+#[test]
+fn unknown_format_exits_non_zero() {
+    let output = Command::new(env!("CARGO_BIN_EXE_helixalign"))
+        .args(["-f", "psf", "test_ref.fa", "test_query.fa"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("Could not run binary");
+
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn report_time_prints_each_phase() {
+    let output = Command::new(env!("CARGO_BIN_EXE_helixalign"))
+        .args(["--report-time", "test_ref.fa", "test_query.fa"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("Could not run binary");
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for phase in ["index construction", "alignment", "output"] {
+        assert!(stderr.contains(&format!("Time for {}: ", phase)), "{}", stderr);
+    }
+}
+
+#[test]
+fn strict_bases_rejects_invalid_query() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_helixalign"))
+        .args(["--strict-bases", "test_ref.fa", "-"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Could not run binary");
+    child.stdin.take().unwrap().write_all(b">query\nATCGA1CGATCG\n").unwrap();
+    let output = child.wait_with_output().expect("Could not run binary");
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid base '1' in record 'query' at position 6"), "{}", stderr);
+}
+
+#[test]
+fn first_only_reports_one_match_per_query_record() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_helixalign"))
+        .args(["-maxmatch", "--first-only", "test_ref.fa", "-"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Could not run binary");
+    // Both records match the repetitive reference at many positions
+    child.stdin.take().unwrap()
+        .write_all(b">a\nATCGATCGATCGATCGATCGATCG\n>b\nGATCGATCGATCGATCGATCGATC\n")
+        .unwrap();
+    let output = child.wait_with_output().expect("Could not run binary");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let matches: Vec<&str> = stdout.lines().filter(|line| line.contains("Ref:")).collect();
+    assert_eq!(matches, vec!["  Ref: 1  Query: 1  Len: 24  Strand: +", "  Ref: 1  Query: 26  Len: 23  Strand: +"], "{}", stdout);
+}
+
+#[test]
+fn extract_usage_goes_to_stderr() {
+    let output = Command::new(env!("CARGO_BIN_EXE_helixalign"))
+        .args(["extract", "test_ref.fa"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("Could not run binary");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Usage:"), "{}", stderr);
+}